@@ -0,0 +1,338 @@
+use std::env;
+use std::io;
+use std::process;
+use std::time::Duration;
+
+use clap::{Args, Subcommand, ValueEnum};
+
+use crate::clipboard::Quirks;
+use crate::doctor::{self, Test};
+use crate::identify::{self, Identity};
+use crate::json;
+use crate::query;
+use crate::terminal::{Terminal, TimeoutArgs};
+
+/// The exit code of `has` for a missing capability, so it's not confused with
+/// the failures.
+const EXIT_MISSING: i32 = 10;
+
+/// The request to report the graphic rendition, DECRQSS for SGR, with a true
+/// color background and a curly underline set for the time of the request.
+/// The terminal reports them back only if it supports them.
+const RENDITION_REQUEST: &[u8] = b"\x1B[0;4:3;48:2::1:2:3m\x1BP$qm\x1B\\\x1B[0m";
+
+/// The request to query the support of the kitty graphics protocol, which
+/// the terminal responds to with the same image ID.
+const KITTY_GRAPHICS_REQUEST: &[u8] = b"\x1B_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1B\\";
+
+/// The request to report the flags of the kitty keyboard protocol.
+const KITTY_KEYBOARD_REQUEST: &[u8] = b"\x1B[?u";
+
+/// The request to report the state of the synchronized output mode, DECRQM.
+const SYNC_OUTPUT_REQUEST: &[u8] = b"\x1B[?2026$p";
+
+/// The request to report the capabilities of the desktop notifications, OSC
+/// 99, which only kitty's notification protocol supports.
+const NOTIFICATIONS_REQUEST: &[u8] = b"\x1B]99;i=ttybox-caps:p=?;\x1B\\";
+
+/// The request to paste the clipboard, OSC 52.
+const OSC52_READ_REQUEST: &[u8] = b"\x1B]52;c;?\x1B\\";
+
+/// The terminfo capabilities the terminal is asked for by means of XTGETTCAP:
+/// true color, curly underline, and OSC 52.
+const TERMCAP_NAMES: [&str; 5] = ["RGB", "Tc", "Smulx", "Su", "Ms"];
+
+/// The terminals known to support hyperlinks, OSC 8, which can't be queried.
+const HYPERLINKS_TERMINALS: &[&str] = &[
+    "kitty",
+    "WezTerm",
+    "iTerm2",
+    "Visual Studio Code",
+    "ghostty",
+    "Alacritty",
+    "Konsole",
+    "Windows Terminal",
+    "foot",
+    "VTE",
+    "mintty",
+];
+
+/// The terminals known to support the notifications of OSC 9 or OSC 777,
+/// which can't be queried unlike OSC 99.
+const NOTIFICATIONS_TERMINALS: &[&str] = &["iTerm2", "WezTerm", "ghostty", "foot"];
+
+/// The terminals known to support curly underlines, for the ones that report
+/// neither the graphic rendition nor the capabilities.
+const UNDERCURL_TERMINALS: &[&str] = &[
+    "kitty",
+    "WezTerm",
+    "iTerm2",
+    "ghostty",
+    "Alacritty",
+    "Konsole",
+    "foot",
+    "VTE",
+    "mintty",
+];
+
+/// The capabilities of the terminal.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    /// 24-bit colors in SGR sequences.
+    Truecolor,
+    /// Reading the clipboard via OSC 52.
+    Osc52Read,
+    /// Writing the clipboard via OSC 52.
+    Osc52Write,
+    /// Hyperlinks via OSC 8.
+    Hyperlinks,
+    /// Sixel graphics.
+    Sixel,
+    /// The kitty graphics protocol.
+    KittyGraphics,
+    /// The kitty keyboard protocol.
+    KittyKeyboard,
+    /// Synchronized output, mode 2026.
+    SyncOutput,
+    /// Curly underlines, SGR 4:3.
+    Undercurl,
+    /// Desktop notifications via OSC 99, OSC 9, or OSC 777.
+    Notifications,
+}
+
+impl Capability {
+    const ALL: [Capability; 10] = [
+        Capability::Truecolor,
+        Capability::Osc52Read,
+        Capability::Osc52Write,
+        Capability::Hyperlinks,
+        Capability::Sixel,
+        Capability::KittyGraphics,
+        Capability::KittyKeyboard,
+        Capability::SyncOutput,
+        Capability::Undercurl,
+        Capability::Notifications,
+    ];
+
+    /// Returns the test of "doctor" the capability is checked by, if any.
+    fn test(self) -> Option<Test> {
+        match self {
+            Capability::Truecolor => Some(Test::Truecolor),
+            Capability::Osc52Write => Some(Test::Osc52Write),
+            Capability::Hyperlinks => Some(Test::Hyperlinks),
+            Capability::Undercurl => Some(Test::Undercurl),
+            Capability::Notifications => Some(Test::Notifications),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Capability::Truecolor => "truecolor",
+            Capability::Osc52Read => "osc52-read",
+            Capability::Osc52Write => "osc52-write",
+            Capability::Hyperlinks => "hyperlinks",
+            Capability::Sixel => "sixel",
+            Capability::KittyGraphics => "kitty-graphics",
+            Capability::KittyKeyboard => "kitty-keyboard",
+            Capability::SyncOutput => "sync-output",
+            Capability::Undercurl => "undercurl",
+            Capability::Notifications => "notifications",
+        }
+    }
+}
+
+/// The formats the capabilities are printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CapsFormat {
+    /// "NAME: yes" or "NAME: no" per line.
+    Text,
+    /// {"NAME": true, …}.
+    Json,
+}
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct CapsArgs {
+    #[command(subcommand)]
+    command: Option<CapsCommands>,
+
+    /// The format to print the capabilities in.
+    #[arg(long, value_enum, default_value_t = CapsFormat::Text)]
+    format: CapsFormat,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CapsCommands {
+    /// Exit with 0 if the terminal has the capability, and with 10 if it
+    /// doesn't, printing nothing.
+    Has(CapsHasArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CapsHasArgs {
+    /// The capability to check.
+    #[arg(value_enum)]
+    capability: Capability,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(args: CapsArgs, terminal: &Terminal) -> io::Result<()> {
+    if let Some(CapsCommands::Has(args)) = args.command {
+        let timeout = args.timeout.duration();
+        let capabilities = probe(terminal, timeout)?;
+        if capabilities.contains(&args.capability) {
+            return Ok(());
+        }
+        process::exit(EXIT_MISSING)
+    }
+
+    let timeout = args.timeout.duration();
+    let capabilities = probe(terminal, timeout)?;
+    match args.format {
+        CapsFormat::Text => {
+            for capability in Capability::ALL {
+                let answer = if capabilities.contains(&capability) {
+                    "yes"
+                } else {
+                    "no"
+                };
+                println!("{}: {answer}", capability.name());
+            }
+        }
+        CapsFormat::Json => {
+            let members = Capability::ALL
+                .iter()
+                .map(|capability| {
+                    format!(
+                        "{}: {}",
+                        json::quote(capability.name()),
+                        capabilities.contains(capability)
+                    )
+                })
+                .collect::<Vec<_>>();
+            println!("{{{}}}", members.join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the capabilities the terminal has. They are probed in a single
+/// round trip, and the ones that can't be probed are told by the identity of
+/// the terminal.
+pub fn probe(terminal: &Terminal, timeout: Duration) -> io::Result<Vec<Capability>> {
+    // Reading the clipboard isn't probed in the terminals known not to
+    // support it, as well as in kitty, which asks the user for permission by
+    // default, and thus may respond long after the probe is over.
+    let identity = identify::detect_env();
+    let read_quirks = identity.as_ref().and_then(Quirks::of);
+    let probe_read = match &identity {
+        Some(identity) if identity.name == "kitty" => false,
+        _ => read_quirks.is_none_or(|quirks| quirks.paste),
+    };
+
+    let mut request = identify::IDENTIFY_REQUEST.to_vec();
+    request.extend_from_slice(RENDITION_REQUEST);
+    request.extend_from_slice(query::termcap_request(&TERMCAP_NAMES).as_bytes());
+    request.extend_from_slice(KITTY_GRAPHICS_REQUEST);
+    request.extend_from_slice(KITTY_KEYBOARD_REQUEST);
+    request.extend_from_slice(SYNC_OUTPUT_REQUEST);
+    request.extend_from_slice(NOTIFICATIONS_REQUEST);
+    if probe_read {
+        request.extend_from_slice(OSC52_READ_REQUEST);
+    }
+    let response = query::exchange(terminal, &request, timeout)?;
+
+    // The primary device attributes come last, as they're requested last,
+    // and the features they claim include sixel graphics and the clipboard.
+    let start = query::find_device_attributes(&response).unwrap_or(response.len());
+    let (response, attributes) = response.split_at(start);
+    let features = query::find_csi(attributes, b"\x1B[?", b'c')
+        .map(|report| String::from_utf8_lossy(report).into_owned())
+        .unwrap_or_default();
+    let features = features.split(';').skip(1).collect::<Vec<_>>();
+
+    let identity = identify::from_response(response);
+    let known = |terminals: &[&str]| {
+        identity
+            .as_ref()
+            .is_some_and(|identity| terminals.contains(&identity.name.as_str()))
+    };
+    let termcap = query::parse_termcap(response);
+    let has_cap = |name: &str| termcap.iter().any(|(capability, _)| capability == name);
+    let rendition = query::find_osc(response, b"\x1BP1$r")
+        .map(|report| String::from_utf8_lossy(report).into_owned())
+        .unwrap_or_default();
+    let has_rendition = !rendition.is_empty();
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    let sync_output = query::find_csi(response, b"\x1B[?2026;", b'$')
+        .is_some_and(|state| matches!(state, b"1" | b"2" | b"3" | b"4"));
+
+    let osc52_read = match &identity {
+        Some(Identity { name, .. }) if name == "kitty" => true,
+        _ => probe_read && query::find_osc(response, b"\x1B]52;").is_some(),
+    };
+    let osc52_write = osc52_read
+        || features.contains(&"52")
+        || has_cap("Ms")
+        || identity
+            .as_ref()
+            .and_then(Quirks::of)
+            .is_some_and(|quirks| quirks.write);
+
+    let capabilities = [
+        (
+            Capability::Truecolor,
+            rendition.contains("48:2")
+                || rendition.contains("48;2")
+                || has_cap("RGB")
+                || has_cap("Tc")
+                || matches!(colorterm.as_str(), "truecolor" | "24bit"),
+        ),
+        (Capability::Osc52Read, osc52_read),
+        (Capability::Osc52Write, osc52_write),
+        (Capability::Hyperlinks, known(HYPERLINKS_TERMINALS)),
+        (Capability::Sixel, features.contains(&"4")),
+        (
+            Capability::KittyGraphics,
+            query::find_osc(response, b"\x1B_Gi=31;").is_some(),
+        ),
+        (
+            Capability::KittyKeyboard,
+            query::find_csi(response, b"\x1B[?", b'u').is_some(),
+        ),
+        (Capability::SyncOutput, sync_output),
+        (
+            Capability::Undercurl,
+            rendition.contains("4:3")
+                || has_cap("Smulx")
+                || has_cap("Su")
+                || (!has_rendition && known(UNDERCURL_TERMINALS)),
+        ),
+        (
+            Capability::Notifications,
+            query::find_osc(response, b"\x1B]99;i=ttybox-caps:p=?;").is_some()
+                || known(NOTIFICATIONS_TERMINALS),
+        ),
+    ];
+
+    // The answers given to "doctor" prevail, as they're what the user has
+    // actually seen.
+    let name = identity.map(|identity| identity.name);
+    let name = name.as_deref().unwrap_or(doctor::UNKNOWN_TERMINAL);
+    Ok(capabilities
+        .into_iter()
+        .filter(|(capability, has)| {
+            capability
+                .test()
+                .and_then(|test| doctor::lookup(name, test))
+                .unwrap_or(*has)
+        })
+        .map(|(capability, _)| capability)
+        .collect())
+}
@@ -1,42 +1,275 @@
+mod bridge;
+mod cache;
+mod history;
+mod kitty;
+mod macos;
+mod nvim;
+mod osc52;
+mod quirks;
+mod serve;
+mod tmux;
+mod wayland;
+mod x11;
+
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::ffi::OsString;
 use std::format;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
-use std::os::unix::ffi::{OsStrExt, OsStringExt};
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::time::Duration;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use base64::prelude::{Engine as _, BASE64_STANDARD};
-use clap::{Args, Subcommand};
-use libc;
-use mio::unix::SourceFd;
-use mio::{Events, Interest, Poll, Token};
-use ncurses;
-
-/// The controlling terminal associated with the process group of that process.
-/// It can be used to write to and read from the terminal no matter how output
-/// has been redirected.
-const TTY_DEVICE: &str = "/dev/tty";
-
-/// The buffer size for reading clipboard data from the terminal. One should
-/// consider a trade-off between memory utilization and the frequency of system
-/// calls when picking the value.
-const TTY_CLIPBOARD_BUFFER_SIZE: usize = 8192;
-
-/// The maximum waiting time for clipboard content to be pushed by the terminal
-/// emulator to the terminal device. If no content has been pushed within the
-/// allocated amount of time, the terminal emulator most likely doesn't support
-/// OSC-52 or is simply sluggish. The value should be as small as possible to
-/// provide smooth experience in unsupported terminals but remain big enough to
-/// properly work in slow terminals.
-const TTY_CLIPBOARD_MAX_WAIT_TIME: Duration = Duration::from_millis(500);
-
-// const OSC_52_RESPONSE: &[u8] = Regex::new(r"\x1B]52;;[]");
+use clap::{Args, Subcommand, ValueEnum};
+
+use crate::error::{self, Failure};
+use crate::query::find;
+use crate::terminal::{Terminal, TimeoutArgs};
+use crate::tty;
+use bridge::BridgeMode;
+use cache::Cache;
+use history::History;
+use kitty::Kitty;
+use macos::Macos;
+use osc52::{Osc52, PayloadLimit};
+pub use quirks::Quirks;
+use serve::Remote;
+use tmux::Tmux;
+use wayland::Wayland;
+use x11::X11;
+
+/// The order in which backends are tried when the "auto" backend is requested.
+/// OSC 52 goes first as it's the only one that works on remote machines, then
+/// go the clipboards of the local desktop environment. Wayland compositors
+/// usually run XWayland too, so Wayland takes precedence over X11 as the
+/// clipboard of X11 applications is synchronized with Wayland anyway.
+const AUTO_BACKENDS: [BackendKind; 6] = [
+    BackendKind::Osc52,
+    BackendKind::Kitty,
+    BackendKind::Wayland,
+    BackendKind::X11,
+    BackendKind::Macos,
+    BackendKind::Tmux,
+];
+
+/// The markers terminals send around the pasted content in bracketed paste mode.
+const BRACKETED_PASTE_START: &[u8] = b"\x1B[200~";
+const BRACKETED_PASTE_END: &[u8] = b"\x1B[201~";
+
+/// The exit codes of `probe` for selections that can be read only and can't
+/// be read at all, so they're not confused with the failures.
+const EXIT_READ_ONLY: i32 = 10;
+const EXIT_UNREADABLE: i32 = 11;
+
+/// The selections an OSC 52 sequence may target, as defined by xterm. Terminal
+/// emulators are free to support a subset of them only, and usually do support
+/// the clipboard selection at least.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    #[value(alias = "c")]
+    Clipboard,
+    #[value(alias = "p")]
+    Primary,
+    #[value(alias = "q")]
+    Secondary,
+    #[value(alias = "s")]
+    Select,
+    #[value(name = "cut0", alias = "0")]
+    Cut0,
+    #[value(name = "cut1", alias = "1")]
+    Cut1,
+    #[value(name = "cut2", alias = "2")]
+    Cut2,
+    #[value(name = "cut3", alias = "3")]
+    Cut3,
+    #[value(name = "cut4", alias = "4")]
+    Cut4,
+    #[value(name = "cut5", alias = "5")]
+    Cut5,
+    #[value(name = "cut6", alias = "6")]
+    Cut6,
+    #[value(name = "cut7", alias = "7")]
+    Cut7,
+}
+
+impl Selection {
+    /// The character representing the selection in the Pc parameter of OSC 52.
+    fn parameter(&self) -> u8 {
+        match self {
+            Selection::Clipboard => b'c',
+            Selection::Primary => b'p',
+            Selection::Secondary => b'q',
+            Selection::Select => b's',
+            Selection::Cut0 => b'0',
+            Selection::Cut1 => b'1',
+            Selection::Cut2 => b'2',
+            Selection::Cut3 => b'3',
+            Selection::Cut4 => b'4',
+            Selection::Cut5 => b'5',
+            Selection::Cut6 => b'6',
+            Selection::Cut7 => b'7',
+        }
+    }
+}
+
+/// The means to access the clipboard. OSC 52 works anywhere, including remote
+/// machines, as long as the terminal emulator supports it, while the rest rely
+/// on the clipboard tools of the local environment.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendKind {
+    Osc52,
+    Kitty,
+    X11,
+    Wayland,
+    Macos,
+    Tmux,
+    Auto,
+}
+
+/// The options of the commands that use the clipboard backends.
+#[derive(Args, Debug)]
+pub struct BackendArgs {
+    /// The clipboard backends to try in order, as a comma separated list. The
+    /// "auto" backend stands for all the backends available in the environment,
+    /// starting with OSC 52.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "osc52",
+        env = "TTYBOX_CLIPBOARD_BACKEND"
+    )]
+    backend: Vec<BackendKind>,
+}
+
+/// The options of the commands that wait for the terminal to paste, which
+/// take the timeout from the environment too.
+#[derive(Args, Debug)]
+#[command(mut_arg("timeout", |arg| arg.env("TTYBOX_CLIPBOARD_TIMEOUT")))]
+pub struct ClipboardTimeoutArgs {
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+impl ClipboardTimeoutArgs {
+    pub fn duration(&self) -> Duration {
+        self.timeout.duration()
+    }
+}
+
+/// The options of the commands that copy via OSC 52.
+#[derive(Args, Debug)]
+pub struct LimitArgs {
+    /// The maximum size of the base64 encoded content the terminal accepts in
+    /// a single OSC 52 sequence. If omitted, the limit is detected for known
+    /// terminals.
+    #[arg(long, value_name = "BYTES")]
+    max_bytes: Option<usize>,
+}
+
+impl LimitArgs {
+    pub fn detect(&self) -> Option<PayloadLimit> {
+        PayloadLimit::detect_with(self.max_bytes)
+    }
+}
+
+/// The interface every clipboard backend implements.
+trait Backend {
+    /// Returns whether the backend can be used in the current environment.
+    fn is_available(&self) -> bool;
+
+    /// Copies the content to the given selections.
+    fn copy(&self, content: &[u8], selections: &[Selection]) -> io::Result<()>;
+
+    /// Pastes the content of the first given selection that's supported.
+    fn paste(&self, selections: &[Selection]) -> io::Result<Vec<u8>>;
+
+    /// Copies the base64 encoded content to the given selections.
+    fn copy_encoded(&self, payload: &[u8], selections: &[Selection]) -> io::Result<()> {
+        let content = BASE64_STANDARD.decode(payload).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The content isn't valid base64.",
+            )
+        })?;
+        self.copy(&content, selections)
+    }
+
+    /// Pastes the content of the first given selection that's supported, base64
+    /// encoded.
+    fn paste_encoded(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        Ok(BASE64_STANDARD.encode(self.paste(selections)?).into_bytes())
+    }
+}
+
+/// The format to print the raw terminal response in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RawFormat {
+    Bytes,
+    Hex,
+}
+
+/// The policy to apply when the content to copy exceeds the maximum size.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    Error,
+    Truncate,
+    Head,
+}
 
 #[derive(Subcommand, Debug)]
 pub enum ClipboardCommands {
     Set(ClipboardSetArgs),
     Get(ClipboardGetArgs),
+    Clear(ClipboardClearArgs),
+    /// Check which selections the terminal can read and write via OSC 52.
+    ///
+    /// The check is harmless: the original content of each selection is put
+    /// back once the selection is probed. The exit code is 0 if all the
+    /// selections are both readable and writable, 10 if some of them are
+    /// readable only, and 11 if some of them can't be read.
+    Probe(ClipboardProbeArgs),
+    Watch(ClipboardWatchArgs),
+    Tee(ClipboardTeeArgs),
+    /// Serve clipboard requests received on a Unix socket, so processes
+    /// without access to the terminal can use its clipboard via "--via".
+    Serve(ClipboardServeArgs),
+    /// Mirror the clipboard with another machine over TCP, authenticating with
+    /// a pre-shared key. The content isn't encrypted, so use a tunnel such as
+    /// SSH on untrusted networks.
+    Bridge(ClipboardBridgeArgs),
+    #[command(subcommand)]
+    History(history::HistoryCommands),
+    /// Copy the lines of a Neovim register read from the standard input,
+    /// remembering the register type, for use as a clipboard provider.
+    NvimSet(nvim::NvimSetArgs),
+    /// Print the type of the Neovim register the content was copied from on
+    /// the first line, followed by the content, for use as a clipboard
+    /// provider. The type of content copied elsewhere is guessed.
+    NvimGet(nvim::NvimGetArgs),
+    /// Mirror the tmux paste buffers to the clipboard as they are created or
+    /// changed, and optionally the clipboard to tmux, so copying in tmux
+    /// reaches the terminal without any tmux configuration.
+    TmuxSync(ClipboardTmuxSyncArgs),
+    /// Copy the output of the last command run in the shell, as marked by the
+    /// shell integration of the terminal. Only kitty is supported, with remote
+    /// control enabled.
+    LastOutput(ClipboardLastOutputArgs),
+    /// Type the content of the clipboard into the terminal, as if it was typed
+    /// by the user, so programs that can't access any clipboard, e.g. the ones
+    /// running on a remote machine, receive it as input.
+    Type(ClipboardTypeArgs),
+    /// Run the command, passing its output through, and copy the output once
+    /// the command exits. The exit code is the one of the command.
+    Exec(ClipboardExecArgs),
+    /// Keep the primary and clipboard selections in sync, so the content can be
+    /// pasted with both middle-click and Ctrl+V no matter how it was copied.
+    Syncsel(ClipboardSyncselArgs),
 }
 
 #[derive(Args, Debug)]
@@ -44,201 +277,1446 @@ pub struct ClipboardSetArgs {
     /// The content to copy to clipboard.
     content: Option<OsString>,
 
-    /// Use the "primary" clipboard.
-    #[arg(short, long, default_value_t = false)]
-    primary: bool,
+    /// Copy the content of the file. If given multiple times, the contents of
+    /// all the files are concatenated.
+    #[arg(short, long, value_name = "PATH", conflicts_with = "content")]
+    file: Vec<PathBuf>,
+
+    /// The selections to use, as a comma separated list.
+    #[arg(short, long, value_delimiter = ',', default_value = "clipboard")]
+    selection: Vec<Selection>,
+
+    /// Use both the "clipboard" and "primary" selections, so the content can be
+    /// pasted with either Ctrl+V or middle-click.
+    #[arg(short, long, default_value_t = false, conflicts_with = "selection")]
+    both: bool,
+
+    #[command(flatten)]
+    limit: LimitArgs,
+
+    /// Treat the content as already base64 encoded, and send it as is. Any
+    /// whitespace, such as line wrapping, is ignored.
+    #[arg(long, default_value_t = false)]
+    encoded: bool,
+
+    /// The maximum size of the content to copy, in bytes. The K, M, and G
+    /// suffixes stand for KiB, MiB, and GiB respectively.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "8M")]
+    max_size: u64,
+
+    /// What to do when the content exceeds the maximum size: fail, keep the
+    /// maximum size of bytes, or keep as many complete lines as fit.
+    #[arg(long, value_enum, default_value_t = Overflow::Error)]
+    on_overflow: Overflow,
+
+    /// Remove the trailing newline from the content, so a copied command isn't
+    /// run right away when pasted into a shell.
+    #[arg(long, alias = "trim-trailing-newline", default_value_t = false)]
+    chomp: bool,
+
+    /// Remove the escape sequences, such as colors, from the content.
+    #[arg(long, default_value_t = false, conflicts_with = "encoded")]
+    strip_ansi: bool,
+
+    /// Read the content back once copied, and fail if it doesn't match, e.g.
+    /// because the terminal has silently dropped the copy sequence. Failed
+    /// verification falls back to the next backend.
+    #[arg(long, default_value_t = false)]
+    verify: bool,
+
+    #[command(flatten)]
+    timeout: ClipboardTimeoutArgs,
+
+    /// Record the content in the clipboard history.
+    #[arg(
+        long,
+        env = "TTYBOX_CLIPBOARD_HISTORY",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    history: bool,
+
+    /// The MIME type of the content, e.g. "text/html" or "image/png". Only
+    /// kitty's clipboard protocol supports types other than plain text, and
+    /// hence it's used regardless of the requested backends.
+    #[arg(long, value_name = "TYPE", conflicts_with = "via")]
+    mime: Option<String>,
+
+    /// Forward the request to the clipboard server listening on the Unix
+    /// socket, instead of using the backends. See "clipboard serve".
+    #[arg(long, value_name = "PATH", env = "TTYBOX_CLIPBOARD_VIA")]
+    via: Option<PathBuf>,
+
+    #[command(flatten)]
+    backend: BackendArgs,
 }
 
 #[derive(Args, Debug)]
 pub struct ClipboardGetArgs {
-    /// Use the "primary" clipboard.
-    #[arg(short, long, default_value_t = false)]
-    primary: bool,
+    /// The selections to use, as a comma separated list.
+    #[arg(short, long, value_delimiter = ',', default_value = "clipboard")]
+    selection: Vec<Selection>,
+
+    #[command(flatten)]
+    timeout: ClipboardTimeoutArgs,
+
+    /// Print the content base64 encoded, as received from the terminal.
+    #[arg(long, default_value_t = false)]
+    encoded: bool,
+
+    /// Print the response of the terminal to the OSC 52 paste request as is,
+    /// escape sequences and all, optionally hex encoded, for diagnosing
+    /// terminal quirks. The backends aren't used in this mode.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "bytes",
+        conflicts_with_all = ["encoded", "lenient", "mime", "via", "any"]
+    )]
+    raw: Option<RawFormat>,
+
+    /// Paste the first selection out of the given ones that isn't empty,
+    /// querying them one by one in order. Handy with terminals such as xterm
+    /// that often hold the content in the primary selection only.
+    #[arg(
+        long,
+        value_name = "SELECTIONS",
+        value_delimiter = ',',
+        num_args = 0..=1,
+        default_missing_value = "primary,clipboard",
+        conflicts_with = "selection"
+    )]
+    any: Option<Vec<Selection>>,
+
+    /// Reuse the content pasted by a previous invocation on the same terminal
+    /// within the given time, in milliseconds, instead of querying the
+    /// clipboard again. The cache is dropped whenever the clipboard is changed
+    /// by ttybox.
+    #[arg(
+        long,
+        value_name = "MS",
+        env = "TTYBOX_CLIPBOARD_CACHE_TTL",
+        conflicts_with_all = ["raw", "mime", "via"]
+    )]
+    cache_ttl: Option<u64>,
+
+    /// Terminate the content with a newline unless it already ends with one,
+    /// so the prompt printed next starts on a line of its own.
+    #[arg(long, default_value_t = false, conflicts_with = "raw")]
+    newline: bool,
+
+    /// Tolerate deviations of the terminal response from the OSC 52 format,
+    /// such as surrounding noise or 8-bit control characters.
+    #[arg(long, default_value_t = false)]
+    lenient: bool,
+
+    /// Write the content to the file instead of the standard output. The file
+    /// is replaced atomically, so it's never left partially written.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Append the content to the output file instead of replacing it.
+    #[arg(long, default_value_t = false, requires = "output")]
+    append: bool,
+
+    /// The permissions of the output file, in octal. If omitted, the
+    /// permissions of the replaced file are preserved.
+    #[arg(long, value_name = "OCTAL", value_parser = parse_mode, requires = "output")]
+    mode: Option<u32>,
+
+    /// The MIME type of the content, e.g. "text/html" or "image/png". Only
+    /// kitty's clipboard protocol supports types other than plain text, and
+    /// hence it's used regardless of the requested backends.
+    #[arg(long, value_name = "TYPE", conflicts_with = "via")]
+    mime: Option<String>,
+
+    /// Forward the request to the clipboard server listening on the Unix
+    /// socket, instead of using the backends. See "clipboard serve".
+    #[arg(long, value_name = "PATH", env = "TTYBOX_CLIPBOARD_VIA")]
+    via: Option<PathBuf>,
+
+    #[command(flatten)]
+    backend: BackendArgs,
 }
 
-pub fn execute(command: ClipboardCommands) -> io::Result<()> {
+#[derive(Args, Debug)]
+pub struct ClipboardClearArgs {
+    /// The selections to use, as a comma separated list.
+    #[arg(short, long, value_delimiter = ',', default_value = "clipboard")]
+    selection: Vec<Selection>,
+
+    /// Wait for the given number of seconds before clearing the clipboard.
+    #[arg(long, value_name = "SECONDS")]
+    after: Option<u64>,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ClipboardProbeArgs {
+    /// The selections to probe, as a comma separated list.
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        default_value = "clipboard,primary"
+    )]
+    selection: Vec<Selection>,
+
+    #[command(flatten)]
+    timeout: ClipboardTimeoutArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ClipboardWatchArgs {
+    /// The selections to use, as a comma separated list.
+    #[arg(short, long, value_delimiter = ',', default_value = "clipboard")]
+    selection: Vec<Selection>,
+
+    /// The time to wait between clipboard queries, in milliseconds.
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    interval: u64,
+
+    /// The shell command to run on every change, with the new content of the
+    /// clipboard passed to its standard input. If omitted, the content is
+    /// printed to the standard output instead.
+    #[arg(long, value_name = "COMMAND")]
+    exec: Option<OsString>,
+
+    /// Separate printed contents with NUL instead of a newline character.
+    #[arg(short = '0', long, default_value_t = false)]
+    null: bool,
+
+    #[command(flatten)]
+    timeout: ClipboardTimeoutArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ClipboardServeArgs {
+    /// The path of the Unix socket to listen on.
+    #[arg(long, value_name = "PATH")]
+    socket: PathBuf,
+
+    #[command(flatten)]
+    limit: LimitArgs,
+
+    #[command(flatten)]
+    timeout: ClipboardTimeoutArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ClipboardBridgeArgs {
+    /// Wait for the peer to connect on the address, e.g. "0.0.0.0:7552".
+    #[arg(
+        long,
+        value_name = "HOST:PORT",
+        required_unless_present = "connect",
+        conflicts_with = "connect"
+    )]
+    listen: Option<String>,
+
+    /// Connect to the peer waiting on the address.
+    #[arg(long, value_name = "HOST:PORT")]
+    connect: Option<String>,
+
+    /// The file with the pre-shared key, which must be the same on both ends.
+    #[arg(long, value_name = "PATH", env = "TTYBOX_CLIPBOARD_BRIDGE_KEY_FILE")]
+    key_file: PathBuf,
+
+    /// How local clipboard changes are sent to the peer: by querying the
+    /// clipboard periodically, or as they are copied via the Unix socket given
+    /// by "--socket" (see "clipboard set --via").
+    #[arg(long, value_enum, default_value_t = BridgeMode::Poll)]
+    mode: BridgeMode,
+
+    /// The path of the Unix socket to accept the content to copy on, in push
+    /// mode.
+    #[arg(long, value_name = "PATH", required_if_eq("mode", "push"))]
+    socket: Option<PathBuf>,
+
+    /// The time to wait between clipboard queries in poll mode, in
+    /// milliseconds.
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    interval: u64,
+
+    /// The selections to use, as a comma separated list.
+    #[arg(short, long, value_delimiter = ',', default_value = "clipboard")]
+    selection: Vec<Selection>,
+
+    #[command(flatten)]
+    limit: LimitArgs,
+
+    #[command(flatten)]
+    timeout: ClipboardTimeoutArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ClipboardTeeArgs {
+    /// The selections to use, as a comma separated list.
+    #[arg(short, long, value_delimiter = ',', default_value = "clipboard")]
+    selection: Vec<Selection>,
+
+    #[command(flatten)]
+    limit: LimitArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ClipboardTmuxSyncArgs {
+    /// Also load the content copied elsewhere into a tmux paste buffer, by
+    /// querying the clipboard periodically.
+    #[arg(long, default_value_t = false)]
+    reverse: bool,
+
+    /// The time to wait between queries of the buffers and, in reverse mode,
+    /// the clipboard, in milliseconds.
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    interval: u64,
+
+    /// The selections to use, as a comma separated list.
+    #[arg(short, long, value_delimiter = ',', default_value = "clipboard")]
+    selection: Vec<Selection>,
+
+    #[command(flatten)]
+    limit: LimitArgs,
+
+    #[command(flatten)]
+    timeout: ClipboardTimeoutArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ClipboardLastOutputArgs {
+    /// The selections to use, as a comma separated list.
+    #[arg(short, long, value_delimiter = ',', default_value = "clipboard")]
+    selection: Vec<Selection>,
+
+    #[command(flatten)]
+    limit: LimitArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ClipboardTypeArgs {
+    /// The selections to use, as a comma separated list.
+    #[arg(short, long, value_delimiter = ',', default_value = "clipboard")]
+    selection: Vec<Selection>,
+
+    /// Wrap the content in the bracketed paste markers, so programs that
+    /// support bracketed paste treat it as pasted rather than typed, e.g.
+    /// without running the commands on every newline.
+    #[arg(long, default_value_t = false)]
+    bracketed: bool,
+
+    #[command(flatten)]
+    timeout: ClipboardTimeoutArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ClipboardExecArgs {
+    /// The command to run, along with its arguments.
+    #[arg(required = true, trailing_var_arg = true, value_name = "COMMAND")]
+    command: Vec<OsString>,
+
+    /// Copy the standard error of the command as well.
+    #[arg(long, default_value_t = false)]
+    stderr: bool,
+
+    /// Remove the escape sequences, such as colors, from the copied output.
+    /// The output passed through is left intact.
+    #[arg(long, default_value_t = false)]
+    strip_ansi: bool,
+
+    /// Copy the output only if the command succeeds.
+    #[arg(long, default_value_t = false)]
+    on_success_only: bool,
+
+    /// The selections to use, as a comma separated list.
+    #[arg(short, long, value_delimiter = ',', default_value = "clipboard")]
+    selection: Vec<Selection>,
+
+    #[command(flatten)]
+    limit: LimitArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+#[derive(Args, Debug)]
+#[group(id = "direction", multiple = false)]
+pub struct ClipboardSyncselArgs {
+    /// Mirror the primary selection to the clipboard only.
+    #[arg(long, group = "direction", default_value_t = false)]
+    primary_to_clipboard: bool,
+
+    /// Mirror the clipboard to the primary selection only.
+    #[arg(long, group = "direction", default_value_t = false)]
+    clipboard_to_primary: bool,
+
+    /// Mirror the selections both ways, which is the default.
+    #[arg(long, group = "direction", default_value_t = false)]
+    bidirectional: bool,
+
+    /// The time to wait between selection queries, in milliseconds.
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    interval: u64,
+
+    #[command(flatten)]
+    limit: LimitArgs,
+
+    #[command(flatten)]
+    timeout: ClipboardTimeoutArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+pub fn execute(command: ClipboardCommands, terminal: &Terminal) -> io::Result<()> {
     match command {
-        ClipboardCommands::Set(args) => execute_set(args),
-        ClipboardCommands::Get(args) => execute_get(args),
+        ClipboardCommands::Set(args) => execute_set(args, terminal),
+        ClipboardCommands::Get(args) => execute_get(args, terminal),
+        ClipboardCommands::Clear(args) => execute_clear(args, terminal),
+        ClipboardCommands::Probe(args) => execute_probe(args, terminal),
+        ClipboardCommands::Watch(args) => execute_watch(args, terminal),
+        ClipboardCommands::Tee(args) => execute_tee(args, terminal),
+        ClipboardCommands::Serve(args) => execute_serve(args, terminal),
+        ClipboardCommands::Bridge(args) => execute_bridge(args, terminal),
+        ClipboardCommands::History(command) => history::execute(command, terminal),
+        ClipboardCommands::NvimSet(args) => nvim::execute_set(args, terminal),
+        ClipboardCommands::NvimGet(args) => nvim::execute_get(args, terminal),
+        ClipboardCommands::TmuxSync(args) => execute_tmux_sync(args, terminal),
+        ClipboardCommands::LastOutput(args) => execute_last_output(args, terminal),
+        ClipboardCommands::Type(args) => execute_type(args, terminal),
+        ClipboardCommands::Exec(args) => execute_exec(args, terminal),
+        ClipboardCommands::Syncsel(args) => execute_syncsel(args, terminal),
     }
 }
 
-fn execute_set(args: ClipboardSetArgs) -> io::Result<()> {
-    // If no content is supplied for copying via the command line argument it's
-    // retrieved from the standard input. If the content is supplied via both
-    // the command line argument and the standard input, the command line
-    // argument takes precedence.
-    let content = match args.content {
-        Some(content) => content,
-        None => OsString::from_vec(io::stdin().bytes().collect::<io::Result<Vec<_>>>()?),
+fn execute_set(args: ClipboardSetArgs, terminal: &Terminal) -> io::Result<()> {
+    let selections = if args.both {
+        vec![Selection::Clipboard, Selection::Primary]
+    } else {
+        args.selection
     };
-    osc_copy(content.as_os_str().as_bytes(), args.primary)
-}
 
-fn execute_get(args: ClipboardGetArgs) -> io::Result<()> {
-    let content = osc_paste(args.primary)?;
-    io::stdout().write(content.as_slice())?;
+    let mut osc52 = Osc52::new(terminal);
+    osc52.limit = args.limit.detect();
+    osc52.timeout = args.timeout.duration();
+
+    // The content read from the standard input or files is streamed straight
+    // to the terminal, unless it's needed once copied or may have to be copied
+    // by another backend.
+    let streaming = args.content.is_none()
+        && args.backend.backend == [BackendKind::Osc52]
+        && args.via.is_none()
+        && args.mime.is_none()
+        && !(args.encoded || args.verify || args.history || args.chomp || args.strip_ansi);
+
+    if streaming {
+        let mut reader: Box<dyn Read> = Box::new(io::stdin().lock());
+        if !args.file.is_empty() {
+            reader = Box::new(io::empty());
+            for path in &args.file {
+                let file = File::open(path).map_err(|e| {
+                    io::Error::new(e.kind(), format!("Cannot read '{}': {e}", path.display()))
+                })?;
+                reader = Box::new(reader.chain(file));
+            }
+        }
+        osc52.copy_stream(reader, &selections, args.max_size, args.on_overflow)?;
+        return cache::invalidate(terminal);
+    }
+
+    // If no content is supplied for copying via either the command line
+    // argument or files it's retrieved from the standard input. If the content
+    // is supplied via both the command line and the standard input, the command
+    // line takes precedence.
+    //
+    // Reading a byte past the maximum size is enough to detect the overflow, so
+    // huge inputs are never loaded into memory entirely.
+    let read_limit = args.max_size.saturating_add(1);
+    let mut content = match args.content {
+        Some(content) => content.into_vec(),
+        None if !args.file.is_empty() => {
+            let mut content = Vec::new();
+            for path in &args.file {
+                let limit = read_limit.saturating_sub(content.len() as u64);
+                File::open(path)
+                    .and_then(|file| file.take(limit).read_to_end(&mut content))
+                    .map_err(|e| {
+                        io::Error::new(e.kind(), format!("Cannot read '{}': {e}", path.display()))
+                    })?;
+            }
+            content
+        }
+        None => {
+            let mut content = Vec::new();
+            io::stdin().take(read_limit).read_to_end(&mut content)?;
+            content
+        }
+    };
+
+    if args.strip_ansi {
+        content = strip_ansi(&content);
+    }
+    if args.chomp && content.ends_with(b"\n") {
+        content.pop();
+        if content.ends_with(b"\r") {
+            content.pop();
+        }
+    }
+
+    if content.len() as u64 > args.max_size {
+        let max_size = args.max_size as usize;
+        match args.on_overflow {
+            Overflow::Error => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("The content exceeds the maximum size of {max_size} bytes."),
+                ))
+            }
+            Overflow::Truncate => content.truncate(max_size),
+            Overflow::Head => {
+                let size = content[..max_size]
+                    .iter()
+                    .rposition(|byte| *byte == b'\n')
+                    .map_or(0, |position| position + 1);
+                content.truncate(size);
+            }
+        }
+    }
+    let kitty = args.mime.map(|mime| Kitty {
+        mime,
+        timeout: osc52.timeout,
+        ..Kitty::new(terminal)
+    });
+
+    // The encoded content is sent as is, while the decoded one is needed to
+    // verify and record the content. The latter is missing if the content
+    // isn't valid base64, in which case the backends fail anyway.
+    let payload: Option<Vec<u8>> = args.encoded.then(|| {
+        content
+            .iter()
+            .copied()
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .collect()
+    });
+    let copied: Option<Cow<[u8]>> = match &payload {
+        Some(payload) => BASE64_STANDARD.decode(payload).ok().map(Cow::Owned),
+        None => Some(Cow::Borrowed(&content)),
+    };
+
+    // Terminal emulators don't acknowledge OSC 52 copy sequences, thus the
+    // fallback to the next backend happens only if the terminal can't be
+    // reached at all, the content doesn't fit, or the verification fails.
+    let copy = |backend: &dyn Backend| {
+        match &payload {
+            Some(payload) => backend.copy_encoded(payload, &selections),
+            None => backend.copy(&content, &selections),
+        }?;
+        if args.verify {
+            verify_copy(backend, copied.as_deref(), &selections)?;
+        }
+        Ok(())
+    };
+    // with_backends() drops the cached pastes itself.
+    match (&args.via, kitty) {
+        (Some(socket), _) => copy(&Remote { socket }).and_then(|()| cache::invalidate(terminal)),
+        (_, Some(kitty)) => copy(&kitty).and_then(|()| cache::invalidate(terminal)),
+        _ => with_backends(&args.backend, osc52, copy),
+    }?;
+
+    if let (true, Some(copied)) = (args.history, &copied) {
+        History::open()?.record(copied)?;
+    }
     Ok(())
 }
 
-fn osc_copy<T: AsRef<[u8]>>(content: T, primary: bool) -> io::Result<()> {
-    let mut osc_copy_sequence = vec![
-        b'\x1B',
-        b']',
-        b'5',
-        b'2',
-        b';',
-        if primary { b'p' } else { b'c' },
-        b';',
-    ];
-    osc_copy_sequence.extend(BASE64_STANDARD.encode(content).as_bytes());
-    osc_copy_sequence.push(b'\x07');
-    fs::write(TTY_DEVICE, osc_copy_sequence)?;
+/// Ensures the selections hold the content by reading them back.
+fn verify_copy(
+    backend: &dyn Backend,
+    content: Option<&[u8]>,
+    selections: &[Selection],
+) -> io::Result<()> {
+    for selection in selections {
+        if content != Some(backend.paste(&[*selection])?.as_slice()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The '{}' selection doesn't hold the copied content.",
+                    selection.to_possible_value().unwrap().get_name()
+                ),
+            ));
+        }
+    }
     Ok(())
 }
 
-// OSC 52 pasting is not as simple as copying. Aside of nuances such as
-// switching terminal into noecho/cbreak mode, the procedure consists of three
-// steps: (1) request paste content, (2) read and (3) decode paste response.
-fn osc_paste(primary: bool) -> io::Result<Vec<u8>> {
-    osc_decode_paste(
-        // Switching the terminal into noecho/cbreak mode [^1] is imperative
-        // before requesting the content of the clipboard. Otherwise, an OSC 52
-        // paste response (escape codes + base64 encoded clipboard content) is
-        // printed to the screen, and that's undesired. The response has to be
-        // decoded first before being sent to the screen.
-        //
-        // [^1]: See `man 3 curs_inopts` for details on noecho/cbreak mode.
-        with_noecho_cbreak_mode(|| {
-            let mut tty = File::options().write(true).read(true).open(TTY_DEVICE)?;
-            osc_request_paste(&mut tty, primary)?;
-            osc_receive_paste(&mut tty)
-        })?,
-    )
-}
+fn execute_get(args: ClipboardGetArgs, terminal: &Terminal) -> io::Result<()> {
+    let mut osc52 = Osc52::new(terminal);
+    osc52.lenient = args.lenient;
+    osc52.timeout = args.timeout.duration();
 
-fn osc_request_paste(file: &mut File, primary: bool) -> io::Result<()> {
-    let osc_paste_sequence = vec![
-        b'\x1B',
-        b']',
-        b'5',
-        b'2',
-        b';',
-        if primary { b'p' } else { b'c' },
-        b';',
-        b'?',
-        b'\x07',
-    ];
-    file.write(osc_paste_sequence.as_slice())?;
-    file.flush()
-}
-
-fn osc_receive_paste(file: &mut File) -> io::Result<Vec<u8>> {
-    set_nonblocking(file.as_raw_fd())?;
-    read_paste_response(file)
-}
-
-// ESC] -> \x9B]
-// OSC  -> \x1B
-// ST   -> \x9C
-// BEL  -> \x07
-
-// FIXME: provide response example and note that the Ps can be omitted or be the same as in the
-// request, and the terminating character could be different.
-fn osc_decode_paste(osc_response: Vec<u8>) -> io::Result<Vec<u8>> {
-    let content = osc_response
-        .rsplit(|byte| *byte == b';')
-        .next()
-        .ok_or(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Cannot parse OSC 52 response.",
-        ))?
-        .strip_suffix(b"\x07")
-        .ok_or(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "OSC 52 response doesn't contain the terminating character.",
-        ))?;
-    BASE64_STANDARD.decode(content).map_err(|_| {
-        io::Error::new(
-            io::ErrorKind::InvalidData,
-            "OSC 52 response doesn't contain valid base64 content.",
+    // The content written to the standard output is decoded and written as the
+    // response arrives, unless it may have to be pasted by another backend.
+    let streaming = args.output.is_none()
+        && args.backend.backend == [BackendKind::Osc52]
+        && args.raw.is_none()
+        && args.via.is_none()
+        && args.mime.is_none()
+        && args.any.is_none()
+        && args.cache_ttl.is_none()
+        && !args.newline
+        && !(args.encoded || args.lenient);
+
+    if streaming {
+        return osc52.paste_stream(&args.selection, io::stdout().lock());
+    }
+
+    // The request the content is cached for, telling apart the selections
+    // and the forms of the content.
+    let request = {
+        let parameters: String = args
+            .any
+            .as_ref()
+            .unwrap_or(&args.selection)
+            .iter()
+            .map(|selection| char::from(selection.parameter()))
+            .collect();
+        format!(
+            "{}{parameters}{}",
+            if args.any.is_some() { "any-" } else { "" },
+            if args.encoded { "-encoded" } else { "" }
         )
-    })
+    };
+    let cache = args
+        .cache_ttl
+        .and_then(|ttl| Some((Cache::open(terminal)?, Duration::from_millis(ttl))));
+    let cached = cache
+        .as_ref()
+        .and_then(|(cache, ttl)| cache.get(&request, *ttl));
+
+    let paste_selections = |backend: &dyn Backend, selections: &[Selection]| {
+        if args.encoded {
+            backend.paste_encoded(selections)
+        } else {
+            backend.paste(selections)
+        }
+    };
+    let paste = |backend: &dyn Backend| match &args.any {
+        Some(selections) => paste_any(backend, selections, paste_selections),
+        None => paste_selections(backend, &args.selection),
+    };
+    let kitty = args.mime.map(|mime| Kitty {
+        mime,
+        timeout: osc52.timeout,
+        ..Kitty::new(terminal)
+    });
+    let mut content = match (cached, args.raw, &args.via, kitty) {
+        (Some(content), _, _, _) => Ok(content),
+        (_, Some(RawFormat::Bytes), _, _) => osc52.paste_raw(&args.selection),
+        (_, Some(RawFormat::Hex), _, _) => osc52.paste_raw(&args.selection).map(|response| {
+            let hex: Vec<String> = response.iter().map(|byte| format!("{byte:02x}")).collect();
+            format!("{}\n", hex.join(" ")).into_bytes()
+        }),
+        (_, _, Some(socket), _) => paste(&Remote { socket }),
+        (_, _, _, Some(kitty)) => paste(&kitty),
+        _ => with_backends(&args.backend, osc52, paste),
+    }?;
+
+    // Failing to cache the content isn't a reason to fail pasting it.
+    if let Some((cache, _)) = &cache {
+        let _ = cache.put(&request, &content);
+    }
+
+    if args.newline && !content.is_empty() && !content.ends_with(b"\n") {
+        content.push(b'\n');
+    }
+
+    match args.output {
+        Some(path) => write_output(&path, &content, args.append, args.mode).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot write to '{}': {e}", path.display()),
+            )
+        }),
+        None => io::stdout().write_all(content.as_slice()),
+    }
 }
 
-fn with_noecho_cbreak_mode<F>(func: F) -> io::Result<Vec<u8>>
+/// Pastes the content of the first selection out of the given ones that isn't
+/// empty, querying them one by one. The selections that fail to be pasted are
+/// skipped as well, and the first failure is returned only if all of them fail.
+fn paste_any<F>(backend: &dyn Backend, selections: &[Selection], paste: F) -> io::Result<Vec<u8>>
 where
-    F: FnOnce() -> io::Result<Vec<u8>>,
+    F: Fn(&dyn Backend, &[Selection]) -> io::Result<Vec<u8>>,
 {
-    ncurses::initscr();
-    ncurses::noecho();
-    ncurses::cbreak();
+    let mut empty = false;
+    let mut error = None;
 
-    let rv = func();
+    for selection in selections {
+        match paste(backend, &[*selection]) {
+            Ok(content) if !content.is_empty() => return Ok(content),
+            Ok(_) => empty = true,
+            Err(e) => {
+                error.get_or_insert(e);
+            }
+        }
+    }
 
-    ncurses::nocbreak();
-    ncurses::echo();
-    ncurses::endwin();
-    return rv;
+    match error {
+        Some(e) if !empty => Err(e),
+        _ => Ok(Vec::new()),
+    }
 }
 
-fn set_nonblocking(fd: RawFd) -> io::Result<()> {
-    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+fn write_output(path: &Path, content: &[u8], append: bool, mode: Option<u32>) -> io::Result<()> {
+    if append {
+        let mut options = File::options();
+        options.append(true).create(true);
+        if let Some(mode) = mode {
+            options.mode(mode);
+        }
+        return options.open(path)?.write_all(content);
+    }
 
-    if flags < 0 {
-        return Err(io::Error::last_os_error());
+    // The content is written to a temporary file next to the target first, and
+    // then the temporary file is renamed over the target, since renaming within
+    // the same file system is atomic.
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "The path isn't a file."))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".ttybox-{}", process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let mode = mode.or_else(|| {
+        fs::metadata(path)
+            .ok()
+            .map(|metadata| metadata.permissions().mode())
+    });
+
+    let result = File::options()
+        .write(true)
+        .create_new(true)
+        .mode(mode.unwrap_or(0o666))
+        .open(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            // The mode given on creation is subject to umask, while the one of
+            // the replaced file or the one given explicitly is expected as is.
+            if let Some(mode) = mode {
+                file.set_permissions(fs::Permissions::from_mode(mode))?;
+            }
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
     }
+    result
+}
 
-    if unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
-        return Err(io::Error::last_os_error());
+/// Parses file permissions given in octal.
+fn parse_mode(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("invalid mode '{value}'"))
+}
+
+fn execute_clear(args: ClipboardClearArgs, terminal: &Terminal) -> io::Result<()> {
+    if let Some(seconds) = args.after {
+        thread::sleep(Duration::from_secs(seconds));
     }
-    Ok(())
+
+    // Terminal emulators set the clipboard to the content of an OSC 52 copy
+    // sequence as is, and hence an empty content clears the clipboard. Although
+    // xterm also clears the clipboard when the content isn't valid base64 (e.g.
+    // `!`), other terminal emulators just ignore such sequences.
+    with_backends(&args.backend, Osc52::new(terminal), |backend| {
+        backend.copy(b"", &args.selection)
+    })
 }
 
-fn read_paste_response(tty: &File) -> io::Result<Vec<u8>> {
-    const TOKEN: Token = Token(0);
-    let mut poll = Poll::new()?;
-    let mut events = Events::with_capacity(1);
-    let mut content = Vec::<u8>::with_capacity(TTY_CLIPBOARD_BUFFER_SIZE);
+fn execute_probe(args: ClipboardProbeArgs, terminal: &Terminal) -> io::Result<()> {
+    let mut osc52 = Osc52::new(terminal);
+    osc52.timeout = args.timeout.duration();
 
-    poll.registry()
-        .register(&mut SourceFd(&tty.as_raw_fd()), TOKEN, Interest::READABLE)?;
+    let sentinel = format!("ttybox-probe-{}", process::id());
+    let mut exit_code = 0;
 
-    'poll: loop {
-        poll.poll(&mut events, Some(TTY_CLIPBOARD_MAX_WAIT_TIME))?;
+    for selection in args.selection {
+        let name = selection.to_possible_value().unwrap().get_name().to_owned();
 
-        if events.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::Unsupported,
-                "The terminal emulator either doesn't support OSC 52 or is sluggish.",
-            ));
+        // Writing can be verified only by reading the content back, hence it
+        // remains unknown for the selections that can't be read.
+        let (read, write) = match osc52.paste(&[selection]) {
+            Ok(original) => {
+                // The original content is put back even if reading the
+                // sentinel back fails.
+                let pasted = osc52
+                    .copy(sentinel.as_bytes(), &[selection])
+                    .and_then(|()| osc52.paste(&[selection]));
+                osc52.copy(&original, &[selection])?;
+                let write = match pasted {
+                    Ok(pasted) if pasted == sentinel.as_bytes() => "yes",
+                    Ok(_) => "no",
+                    // E.g. the terminal may prompt before answering again.
+                    Err(e)
+                        if matches!(
+                            error::failure(&e),
+                            Some(Failure::Unsupported | Failure::Timeout)
+                        ) =>
+                    {
+                        "unknown"
+                    }
+                    Err(e) => return Err(e),
+                };
+                ("yes", write)
+            }
+            Err(e)
+                if matches!(
+                    error::failure(&e),
+                    Some(Failure::Unsupported | Failure::Timeout)
+                ) =>
+            {
+                ("no", "unknown")
+            }
+            Err(e) => return Err(e),
+        };
+
+        exit_code = match (read, write) {
+            ("no", _) => EXIT_UNREADABLE,
+            (_, "no") => exit_code.max(EXIT_READ_ONLY),
+            _ => exit_code,
+        };
+        println!("{name}: read={read} write={write}");
+    }
+
+    process::exit(exit_code)
+}
+
+fn execute_watch(args: ClipboardWatchArgs, terminal: &Terminal) -> io::Result<()> {
+    let paste = || {
+        let mut osc52 = Osc52::new(terminal);
+        osc52.timeout = args.timeout.duration();
+        with_backends(&args.backend, osc52, |backend| {
+            backend.paste(&args.selection)
+        })
+    };
+
+    // The content the clipboard holds at the moment watching starts isn't a
+    // change, and hence is only remembered to compare subsequent ones against.
+    let mut last_content = paste()?;
+
+    loop {
+        thread::sleep(Duration::from_millis(args.interval));
+
+        let content = paste()?;
+        if content == last_content {
+            continue;
+        }
+
+        match &args.exec {
+            Some(exec) => {
+                let mut child = Command::new("sh")
+                    .arg("-c")
+                    .arg(exec)
+                    .stdin(Stdio::piped())
+                    .spawn()?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(&content)?;
+                }
+                child.wait()?;
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(&content)?;
+                stdout.write_all(if args.null { b"\0" } else { b"\n" })?;
+                stdout.flush()?;
+            }
         }
+        last_content = content;
+    }
+}
+
+fn execute_tee(args: ClipboardTeeArgs, terminal: &Terminal) -> io::Result<()> {
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut content = Vec::new();
+    let mut buffer = [0u8; 8192];
 
-        for event in events.iter() {
-            if event.token() == TOKEN && event.is_readable() {
-                content.extend(read_with_draining(&tty)?);
+    // The content is passed through as soon as it's read, so the next command
+    // in the pipeline doesn't have to wait for the input to end.
+    loop {
+        let size = match stdin.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        stdout.write_all(&buffer[..size])?;
+        stdout.flush()?;
+        content.extend_from_slice(&buffer[..size]);
+    }
+
+    let mut osc52 = Osc52::new(terminal);
+    osc52.limit = args.limit.detect();
+    with_backends(&args.backend, osc52, |backend| {
+        backend.copy(&content, &args.selection)
+    })
+}
+
+fn execute_tmux_sync(args: ClipboardTmuxSyncArgs, terminal: &Terminal) -> io::Result<()> {
+    if !Tmux.is_available() {
+        return Err(error::new(
+            Failure::Unsupported,
+            "The process isn't running inside tmux.",
+        ));
+    }
+
+    let mut osc52 = Osc52::new(terminal);
+    osc52.limit = args.limit.detect();
+    osc52.timeout = args.timeout.duration();
+    let copy = |content: &[u8]| {
+        with_backends(&args.backend, osc52.clone(), |backend| {
+            backend.copy(content, &args.selection)
+        })
+    };
+    let paste = || {
+        with_backends(&args.backend, osc52.clone(), |backend| {
+            backend.paste(&args.selection)
+        })
+    };
+
+    let (_client, buffers) = tmux::watch_buffers()?;
+    let interval = Duration::from_millis(args.interval);
+    let mut next_poll = Instant::now() + interval;
+
+    // The contents the buffers and the clipboard hold at the moment syncing
+    // starts aren't changes. The content mirrored either way is remembered on
+    // both ends, so it's not mirrored back.
+    let mut last_buffer = Tmux.paste(&[Selection::Clipboard]).unwrap_or_default();
+    let mut last_clipboard = if args.reverse { paste()? } else { Vec::new() };
+
+    loop {
+        // The buffers are also queried periodically, as tmux versions before
+        // 3.4 don't notify control clients of buffer changes. Either way, a
+        // buffer may be gone by the time it's read, which isn't a reason to
+        // stop syncing.
+        let buffer = match buffers.recv_timeout(next_poll.saturating_duration_since(Instant::now()))
+        {
+            Ok(name) => tmux::save_buffer(&name).ok(),
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        let polling = Instant::now() >= next_poll;
+        let buffer = match buffer {
+            None if polling => Tmux.paste(&[Selection::Clipboard]).ok(),
+            buffer => buffer,
+        };
+
+        if let Some(content) = buffer.filter(|content| *content != last_buffer) {
+            copy(&content)?;
+            last_clipboard = content.clone();
+            last_buffer = content;
+        }
 
-                // TODO: Investiate whether response may end with ST character
-                // instead of BEL, and add support if needed.
-                if content.ends_with(b"\x07") {
-                    break 'poll;
+        if polling {
+            if args.reverse {
+                let content = paste()?;
+                if content != last_clipboard {
+                    Tmux.copy(&content, &[Selection::Clipboard])?;
+                    last_buffer = content.clone();
+                    last_clipboard = content;
                 }
             }
+            next_poll = Instant::now() + interval;
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::ConnectionAborted,
+        "The tmux control client has exited.",
+    ))
+}
+
+fn execute_last_output(args: ClipboardLastOutputArgs, terminal: &Terminal) -> io::Result<()> {
+    let content = kitty::last_command_output()?;
+
+    let mut osc52 = Osc52::new(terminal);
+    osc52.limit = args.limit.detect();
+    with_backends(&args.backend, osc52, |backend| {
+        backend.copy(&content, &args.selection)
+    })
+}
+
+fn execute_type(args: ClipboardTypeArgs, terminal: &Terminal) -> io::Result<()> {
+    let mut osc52 = Osc52::new(terminal);
+    osc52.timeout = args.timeout.duration();
+    let content = with_backends(&args.backend, osc52, |backend| {
+        backend.paste(&args.selection)
+    })?;
+
+    // The content may contain the end marker itself, which would let the rest
+    // of it escape the bracketed paste and be run as typed, hence it's dropped
+    // as terminal emulators do when pasting.
+    let input = if args.bracketed {
+        let mut input = BRACKETED_PASTE_START.to_vec();
+        let mut rest = content.as_slice();
+        while let Some(index) = find(rest, BRACKETED_PASTE_END) {
+            input.extend_from_slice(&rest[..index]);
+            rest = &rest[index + BRACKETED_PASTE_END.len()..];
+        }
+        input.extend_from_slice(rest);
+        input.extend_from_slice(BRACKETED_PASTE_END);
+        input
+    } else {
+        content
+    };
+    terminal.type_input(&input)
+}
+
+fn execute_exec(args: ClipboardExecArgs, terminal: &Terminal) -> io::Result<()> {
+    let mut child = Command::new(&args.command[0])
+        .args(&args.command[1..])
+        .stdout(Stdio::piped())
+        .stderr(if args.stderr {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        })
+        .spawn()
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot run '{}': {e}", args.command[0].to_string_lossy()),
+            )
+        })?;
+
+    // The output is passed through as soon as it's read, so the command can
+    // be watched while it runs. Both outputs are read at once, as otherwise
+    // the command may block on writing to the one that isn't read.
+    let content = Mutex::new(Vec::new());
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let status = thread::scope(|scope| {
+        let content = &content;
+        let stdout =
+            stdout.map(|pipe| scope.spawn(move || pass_through(pipe, io::stdout(), content)));
+        let stderr =
+            stderr.map(|pipe| scope.spawn(move || pass_through(pipe, io::stderr(), content)));
+        for handle in stdout.into_iter().chain(stderr) {
+            handle.join().expect("the output isn't passed through")?;
+        }
+        child.wait()
+    })?;
+
+    if status.success() || !args.on_success_only {
+        let mut content = content.into_inner().expect("the output isn't captured");
+        if args.strip_ansi {
+            content = strip_ansi(&content);
+        }
+        let mut osc52 = Osc52::new(terminal);
+        osc52.limit = args.limit.detect();
+        with_backends(&args.backend, osc52, |backend| {
+            backend.copy(&content, &args.selection)
+        })?;
+    }
+
+    process::exit(tty::exit_code(status))
+}
+
+/// Copies the output of the command to the given output as it's read, and
+/// captures it along the way.
+fn pass_through(
+    mut pipe: impl Read,
+    mut output: impl Write,
+    content: &Mutex<Vec<u8>>,
+) -> io::Result<()> {
+    let mut buffer = [0u8; 8192];
+    loop {
+        let size = match pipe.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(size) => size,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        output.write_all(&buffer[..size])?;
+        output.flush()?;
+        content
+            .lock()
+            .expect("the output isn't captured")
+            .extend_from_slice(&buffer[..size]);
+    }
+}
+
+/// Returns the content with the escape sequences removed: CSI sequences such
+/// as colors, OSC sequences such as hyperlinks, and two-byte escapes.
+fn strip_ansi(content: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(content.len());
+    let mut index = 0;
+
+    while index < content.len() {
+        if content[index] != b'\x1B' {
+            stripped.push(content[index]);
+            index += 1;
+            continue;
         }
+        let rest = &content[index + 1..];
+        index += 1 + match rest.first() {
+            // CSI ends with a byte in the 0x40-0x7E range.
+            Some(b'[') => rest[1..]
+                .iter()
+                .position(|byte| (0x40..=0x7E).contains(byte))
+                .map_or(rest.len(), |end| end + 2),
+            // OSC ends with either BEL or ST.
+            Some(b']') => (1..rest.len())
+                .find_map(|end| match &rest[end..] {
+                    [b'\x07', ..] => Some(end + 1),
+                    [b'\x1B', b'\\', ..] => Some(end + 2),
+                    _ => None,
+                })
+                .unwrap_or(rest.len()),
+            Some(_) => 1,
+            None => 0,
+        };
     }
-    Ok(content)
+    stripped
 }
 
-fn read_with_draining(mut tty: &File) -> io::Result<Vec<u8>> {
-    let mut content = Vec::<u8>::with_capacity(TTY_CLIPBOARD_BUFFER_SIZE);
-    let mut content_buf = [0u8; TTY_CLIPBOARD_BUFFER_SIZE];
+fn execute_syncsel(args: ClipboardSyncselArgs, terminal: &Terminal) -> io::Result<()> {
+    let mut osc52 = Osc52::new(terminal);
+    osc52.limit = args.limit.detect();
+    osc52.timeout = args.timeout.duration();
+    let copy = |content: &[u8], selection: Selection| {
+        with_backends(&args.backend, osc52.clone(), |backend| {
+            backend.copy(content, &[selection])
+        })
+    };
+    let paste = |selection: Selection| {
+        with_backends(&args.backend, osc52.clone(), |backend| {
+            backend.paste(&[selection])
+        })
+    };
+    let to_clipboard = !args.clipboard_to_primary;
+    let to_primary = !args.primary_to_clipboard;
+
+    // The contents the selections hold at the moment syncing starts aren't
+    // changes. The content mirrored to a selection is remembered as its last
+    // one, so it's not mirrored back.
+    let mut last_primary = paste(Selection::Primary)?;
+    let mut last_clipboard = paste(Selection::Clipboard)?;
+
     loop {
-        match tty.read(&mut content_buf) {
-            Ok(size) if size == 0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
-            Ok(size) => content.extend_from_slice(&content_buf[0..size]),
-            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+        thread::sleep(Duration::from_millis(args.interval));
+
+        // Empty content isn't mirrored, as terminals usually clear the
+        // primary selection once the text is deselected.
+        let primary = paste(Selection::Primary)?;
+        if primary != last_primary {
+            if to_clipboard && !primary.is_empty() && primary != last_clipboard {
+                copy(&primary, Selection::Clipboard)?;
+                last_clipboard = primary.clone();
+            }
+            last_primary = primary;
+            continue;
+        }
+
+        let clipboard = paste(Selection::Clipboard)?;
+        if clipboard != last_clipboard {
+            if to_primary && !clipboard.is_empty() && clipboard != last_primary {
+                copy(&clipboard, Selection::Primary)?;
+                last_primary = clipboard.clone();
+            }
+            last_clipboard = clipboard;
+        }
+    }
+}
+
+fn execute_serve(args: ClipboardServeArgs, terminal: &Terminal) -> io::Result<()> {
+    let mut osc52 = Osc52::new(terminal);
+    osc52.limit = args.limit.detect();
+    osc52.timeout = args.timeout.duration();
+    serve::serve(&args.socket, |request| match request {
+        serve::Request::Copy(selections, content) => {
+            with_backends(&args.backend, osc52.clone(), |backend| {
+                backend.copy(&content, &selections)
+            })?;
+            Ok(Vec::new())
+        }
+        serve::Request::Paste(selections) => {
+            with_backends(&args.backend, osc52.clone(), |backend| {
+                backend.paste(&selections)
+            })
+        }
+    })
+}
+
+fn execute_bridge(args: ClipboardBridgeArgs, terminal: &Terminal) -> io::Result<()> {
+    let key = bridge::read_key(&args.key_file)?;
+
+    let mut osc52 = Osc52::new(terminal);
+    osc52.limit = args.limit.detect();
+    osc52.timeout = args.timeout.duration();
+
+    let copy = |content: &[u8]| {
+        with_backends(&args.backend, osc52.clone(), |backend| {
+            backend.copy(content, &args.selection)
+        })
+    };
+    let paste = || {
+        with_backends(&args.backend, osc52.clone(), |backend| {
+            backend.paste(&args.selection)
+        })
+    };
+    let local = bridge::Local {
+        copy: &copy,
+        paste: &paste,
+        mode: args.mode,
+        interval: Duration::from_millis(args.interval),
+        socket: match (args.mode, &args.socket) {
+            (BridgeMode::Push, Some(socket)) => Some(serve::bind(socket)?),
+            _ => None,
+        },
+    };
+
+    match (&args.listen, &args.connect) {
+        (Some(address), _) => bridge::listen(address, &key, &local),
+        (_, Some(address)) => bridge::connect(address, &key, &local),
+        _ => unreachable!(),
+    }
+}
+
+/// Parses a size in bytes, optionally followed by the K, M, or G suffix.
+fn parse_size(value: &str) -> Result<u64, String> {
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&value[..index], 1 << 10),
+        Some((index, 'M' | 'm')) => (&value[..index], 1 << 20),
+        Some((index, 'G' | 'g')) => (&value[..index], 1 << 30),
+        _ => (value, 1),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size '{value}'"))
+}
+
+/// Runs the operation against the requested backends in order, until it
+/// succeeds. Backends requested explicitly are always tried, while the ones
+/// implied by "auto" are tried only if they are available in the environment.
+/// If all of them fail, the error of the first one is returned.
+fn with_backends<T, F>(args: &BackendArgs, osc52: Osc52, operation: F) -> io::Result<T>
+where
+    F: Fn(&dyn Backend) -> io::Result<T>,
+{
+    let mut candidates = Vec::new();
+    for kind in &args.backend {
+        match kind {
+            BackendKind::Auto => candidates.extend(AUTO_BACKENDS.map(|kind| (kind, false))),
+            kind => candidates.push((*kind, true)),
+        }
+    }
+
+    let terminal = osc52.terminal;
+    let kitty = Kitty {
+        timeout: osc52.timeout,
+        ..Kitty::new(osc52.terminal)
+    };
+    let mut osc52 = Some(osc52);
+    let mut kitty = Some(kitty);
+    let mut tried = Vec::new();
+    let mut error = None;
+
+    for (kind, explicit) in candidates {
+        if tried.contains(&kind) {
+            continue;
+        }
+        tried.push(kind);
+
+        let backend: Box<dyn Backend + '_> = match kind {
+            BackendKind::Osc52 => match osc52.take() {
+                Some(osc52) => Box::new(osc52),
+                None => continue,
+            },
+            BackendKind::Kitty => match kitty.take() {
+                Some(kitty) => Box::new(kitty),
+                None => continue,
+            },
+            BackendKind::X11 => Box::new(X11),
+            BackendKind::Wayland => Box::new(Wayland),
+            BackendKind::Macos => Box::new(Macos),
+            BackendKind::Tmux => Box::new(Tmux),
+            BackendKind::Auto => unreachable!(),
+        };
+
+        if !explicit && !backend.is_available() {
+            continue;
+        }
+
+        // The cached pastes are stale once the clipboard is changed, even if
+        // the operation fails afterwards, e.g. when verifying the copy.
+        let backend = Copying {
+            backend: backend.as_ref(),
+            copied: Cell::new(false),
+        };
+        let result = operation(&backend);
+        if backend.copied.get() {
+            cache::invalidate(terminal)?;
+        }
+        match result {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                error.get_or_insert(e);
+            }
+        }
+    }
+
+    Err(error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "None of the clipboard backends is available.",
+        )
+    }))
+}
+
+/// The backend that remembers whether it has copied anything, so the cached
+/// pastes can be dropped.
+struct Copying<'a> {
+    backend: &'a dyn Backend,
+    copied: Cell<bool>,
+}
+
+impl Copying<'_> {
+    fn track(&self, result: io::Result<()>) -> io::Result<()> {
+        if result.is_ok() {
+            self.copied.set(true);
+        }
+        result
+    }
+}
+
+impl Backend for Copying<'_> {
+    fn is_available(&self) -> bool {
+        self.backend.is_available()
+    }
+
+    fn copy(&self, content: &[u8], selections: &[Selection]) -> io::Result<()> {
+        self.track(self.backend.copy(content, selections))
+    }
+
+    fn paste(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        self.backend.paste(selections)
+    }
+
+    fn copy_encoded(&self, payload: &[u8], selections: &[Selection]) -> io::Result<()> {
+        self.track(self.backend.copy_encoded(payload, selections))
+    }
+
+    fn paste_encoded(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        self.backend.paste_encoded(selections)
+    }
+}
+
+/// Returns the error to report when a backend doesn't support the selection.
+fn unsupported_selection(backend: &str, selection: &Selection) -> io::Error {
+    error::new(
+        Failure::Unsupported,
+        format!(
+            "The {backend} backend doesn't support the '{}' selection.",
+            selection.to_possible_value().unwrap().get_name()
+        ),
+    )
+}
+
+/// Ensures the backend supports all the selections.
+fn check_selections(
+    backend: &str,
+    selections: &[Selection],
+    supported: &[Selection],
+) -> io::Result<()> {
+    match selections
+        .iter()
+        .find(|selection| !supported.contains(selection))
+    {
+        Some(selection) => Err(unsupported_selection(backend, selection)),
+        None => Ok(()),
+    }
+}
+
+/// Runs the first clipboard tool out of the candidates that is installed. The
+/// input, if any, is fed to the tool, otherwise the tool's output is returned.
+fn run_clipboard_tool<'a, I>(candidates: I, input: Option<&[u8]>) -> io::Result<Vec<u8>>
+where
+    I: IntoIterator<Item = (&'a str, Vec<&'a str>)>,
+{
+    let mut names = Vec::new();
+
+    for (program, args) in candidates {
+        let mut command = Command::new(program);
+        command.args(args).stderr(Stdio::inherit());
+
+        // Copying tools such as xclip fork a process that holds the selection
+        // until some other application takes it over. The forked process
+        // inherits the standard output, so it must not be a pipe as otherwise
+        // reading the output never ends.
+        let child = match input {
+            Some(_) => command.stdin(Stdio::piped()).stdout(Stdio::null()).spawn(),
+            None => command.stdin(Stdio::null()).stdout(Stdio::piped()).spawn(),
+        };
+
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                names.push(program);
+                continue;
+            }
             Err(e) => return Err(e),
+        };
+
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin.write_all(input)?;
         }
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "{program} failed: {}",
+                output.status
+            )));
+        }
+        return Ok(output.stdout);
     }
-    Ok(content)
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "None of the clipboard tools is installed: {}.",
+            names.join(", ")
+        ),
+    ))
 }
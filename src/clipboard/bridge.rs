@@ -0,0 +1,320 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use hmac::{Hmac, Mac};
+use mio::unix::SourceFd;
+use mio::{Events, Interest, Poll, Token};
+use sha2::Sha256;
+
+use super::serve::{self, invalid_data, read_frame, write_frame, Request};
+
+/// The greeting both peers start with, so connecting to something that isn't a
+/// bridge fails early and clearly.
+const GREETING: &[u8] = b"ttybox-bridge/1\n";
+
+const NONCE_SIZE: usize = 16;
+const TAG_SIZE: usize = 32;
+
+/// The labels the HMACs of the handshake proofs and of the messages start
+/// with, so neither can stand in for the other.
+const PROOF_LABEL: &[u8] = b"ttybox-bridge proof";
+const MESSAGE_LABEL: &[u8] = b"ttybox-bridge message";
+
+/// The maximum time to wait for the peer during the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How local clipboard changes are noticed and sent to the peer.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BridgeMode {
+    /// Query the local clipboard periodically.
+    Poll,
+    /// Send the content copied via the Unix socket of the bridge.
+    Push,
+}
+
+/// The local end of the bridge, i.e. the means to access the local clipboard
+/// and to notice its changes.
+pub struct Local<'a> {
+    pub copy: &'a dyn Fn(&[u8]) -> io::Result<()>,
+    pub paste: &'a dyn Fn() -> io::Result<Vec<u8>>,
+    pub mode: BridgeMode,
+    pub interval: Duration,
+    pub socket: Option<UnixListener>,
+}
+
+/// Accepts peers on the address one at a time, and mirrors the clipboard with
+/// each of them until it disconnects.
+pub fn listen(address: &str, key: &[u8], local: &Local) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| {
+            let peer = stream.peer_addr()?;
+            mirror(stream, key, local).map_err(|e| {
+                io::Error::new(e.kind(), format!("The bridge with {peer} is broken: {e}"))
+            })
+        });
+        // The peer going away must not bring the bridge down, as another one
+        // may connect later.
+        if let Err(e) = result {
+            eprintln!("ttybox: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// Connects to the peer listening on the address, and mirrors the clipboard
+/// with it until it disconnects.
+pub fn connect(address: &str, key: &[u8], local: &Local) -> io::Result<()> {
+    let stream = TcpStream::connect(address)
+        .map_err(|e| io::Error::new(e.kind(), format!("Cannot connect to '{address}': {e}")))?;
+    mirror(stream, key, local)
+}
+
+/// Reads the pre-shared key from the file, ignoring trailing whitespace such as
+/// the final newline.
+pub fn read_key(path: &Path) -> io::Result<Vec<u8>> {
+    let mut key = fs::read(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Cannot read the key '{}': {e}", path.display()),
+        )
+    })?;
+    while key.last().is_some_and(u8::is_ascii_whitespace) {
+        key.pop();
+    }
+    if key.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The key '{}' is empty.", path.display()),
+        ));
+    }
+    Ok(key)
+}
+
+fn mirror(mut stream: TcpStream, key: &[u8], local: &Local) -> io::Result<()> {
+    let mut session = Session::establish(&mut stream, key)?;
+
+    const PEER: Token = Token(0);
+    const SOCKET: Token = Token(1);
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(2);
+
+    poll.registry()
+        .register(&mut SourceFd(&stream.as_raw_fd()), PEER, Interest::READABLE)?;
+    if let Some(socket) = &local.socket {
+        poll.registry().register(
+            &mut SourceFd(&socket.as_raw_fd()),
+            SOCKET,
+            Interest::READABLE,
+        )?;
+    }
+
+    // The content the clipboard holds at the moment mirroring starts isn't a
+    // change, and hence is only remembered to compare subsequent ones against.
+    // The content received from the peer is remembered as well, so it's not
+    // sent back.
+    let mut last_content = match local.mode {
+        BridgeMode::Poll => (local.paste)()?,
+        BridgeMode::Push => Vec::new(),
+    };
+    let mut next_poll = Instant::now() + local.interval;
+
+    loop {
+        let timeout = match local.mode {
+            BridgeMode::Poll => Some(next_poll.saturating_duration_since(Instant::now())),
+            BridgeMode::Push => None,
+        };
+        match poll.poll(&mut events, timeout) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => result?,
+        }
+
+        for event in events.iter() {
+            match event.token() {
+                PEER => {
+                    let content = session.receive(&mut stream)?;
+                    (local.copy)(&content)?;
+                    last_content = content;
+                }
+                SOCKET => {
+                    let Some(socket) = &local.socket else {
+                        continue;
+                    };
+                    let (mut client, _) = socket.accept()?;
+                    client.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+                    let mut pushed = None;
+                    let result = serve::handle(&mut client, |request| match request {
+                        Request::Copy(_, content) => {
+                            (local.copy)(&content)?;
+                            pushed = Some(content);
+                            Ok(Vec::new())
+                        }
+                        Request::Paste(_) => (local.paste)(),
+                    });
+                    if let Err(e) = result {
+                        eprintln!("ttybox: {e}");
+                    }
+                    if let Some(content) = pushed {
+                        session.send(&mut stream, &content)?;
+                        last_content = content;
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        if local.mode == BridgeMode::Poll && Instant::now() >= next_poll {
+            let content = (local.paste)()?;
+            if content != last_content {
+                session.send(&mut stream, &content)?;
+                last_content = content;
+            }
+            next_poll = Instant::now() + local.interval;
+        }
+    }
+}
+
+/// The authenticated session with the peer.
+///
+/// Both peers send a random nonce, and prove the knowledge of the pre-shared
+/// key by replying with the HMAC of the nonces. Every message is then followed
+/// by the HMAC of the nonces, its sequence number, and its content. The HMACs
+/// also cover which of the two peers is the sender, telling them apart by the
+/// order of their nonces, which must differ, so messages can't be forged,
+/// replayed, or reflected back. The content itself isn't
+/// encrypted, so a tunnel such as SSH is still needed to keep it private.
+struct Session<'a> {
+    key: &'a [u8],
+    local_nonce: [u8; NONCE_SIZE],
+    remote_nonce: [u8; NONCE_SIZE],
+    sent: u64,
+    received: u64,
+}
+
+impl<'a> Session<'a> {
+    fn establish(stream: &mut TcpStream, key: &'a [u8]) -> io::Result<Self> {
+        Self::handshake(stream, key).map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::new(
+                io::ErrorKind::TimedOut,
+                "The peer hasn't completed the handshake in time.",
+            ),
+            _ => e,
+        })
+    }
+
+    fn handshake(stream: &mut TcpStream, key: &'a [u8]) -> io::Result<Self> {
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+
+        let mut local_nonce = [0u8; NONCE_SIZE];
+        File::open("/dev/urandom")?.read_exact(&mut local_nonce)?;
+        stream.write_all(GREETING)?;
+        stream.write_all(&local_nonce)?;
+
+        let mut greeting = [0u8; GREETING.len()];
+        let mut remote_nonce = [0u8; NONCE_SIZE];
+        stream.read_exact(&mut greeting)?;
+        if greeting != GREETING {
+            return Err(invalid_data("The peer isn't a ttybox bridge."));
+        }
+        stream.read_exact(&mut remote_nonce)?;
+        // A peer echoing the nonce back would be indistinguishable from this
+        // one, and could reflect its proof and messages back.
+        if remote_nonce == local_nonce {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "The peer has failed to authenticate.",
+            ));
+        }
+
+        let session = Session {
+            key,
+            local_nonce,
+            remote_nonce,
+            sent: 0,
+            received: 0,
+        };
+
+        stream.write_all(&session.sign(PROOF_LABEL, &[]))?;
+        let mut proof = [0u8; TAG_SIZE];
+        stream.read_exact(&mut proof)?;
+        session.verify(PROOF_LABEL, &[], &proof)?;
+
+        stream.set_read_timeout(None)?;
+        Ok(session)
+    }
+
+    fn send(&mut self, stream: &mut TcpStream, content: &[u8]) -> io::Result<()> {
+        let tag = self.sign(MESSAGE_LABEL, &[&self.sent.to_be_bytes(), content]);
+        write_frame(stream, content)?;
+        stream.write_all(&tag)?;
+        self.sent += 1;
+        Ok(())
+    }
+
+    fn receive(&mut self, stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+        let content = read_frame(stream).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "The peer has closed the connection.",
+            ),
+            _ => e,
+        })?;
+        let mut tag = [0u8; TAG_SIZE];
+        stream.read_exact(&mut tag)?;
+        self.verify(
+            MESSAGE_LABEL,
+            &[&self.received.to_be_bytes(), &content],
+            &tag,
+        )?;
+        self.received += 1;
+        Ok(content)
+    }
+
+    /// Returns the HMAC of the label, the nonces in ascending order, whether
+    /// the sender is the peer with the greater nonce, and the parts.
+    fn mac(&self, label: &[u8], sender: &[u8], parts: &[&[u8]]) -> Hmac<Sha256> {
+        let (lower, greater) = if self.local_nonce < self.remote_nonce {
+            (&self.local_nonce, &self.remote_nonce)
+        } else {
+            (&self.remote_nonce, &self.local_nonce)
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key).expect("HMAC takes any key size");
+        mac.update(label);
+        mac.update(lower);
+        mac.update(greater);
+        mac.update(&[u8::from(sender == greater)]);
+        for part in parts {
+            mac.update(part);
+        }
+        mac
+    }
+
+    /// Returns the tag of what this peer sends.
+    fn sign(&self, label: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+        self.mac(label, &self.local_nonce, parts)
+            .finalize()
+            .into_bytes()
+            .to_vec()
+    }
+
+    /// Checks the tag of what the other peer has sent.
+    fn verify(&self, label: &[u8], parts: &[&[u8]], tag: &[u8]) -> io::Result<()> {
+        self.mac(label, &self.remote_nonce, parts)
+            .verify_slice(tag)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "The peer has failed to authenticate.",
+                )
+            })
+    }
+}
@@ -0,0 +1,114 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::terminal::{Terminal, TTY_DEVICE};
+
+/// The cache of pasted contents, so repeated pastes in quick succession, e.g.
+/// by a shell prompt or a fuzzy finder preview, don't pay for a round trip to
+/// the terminal each. Every entry is a file named after the terminal and the
+/// request, and is fresh as long as its modification time is within the TTL.
+///
+/// The cache is kept under `$XDG_RUNTIME_DIR`, which is private to the user
+/// and cleared on logout, and is disabled if there's no such directory.
+pub struct Cache {
+    directory: PathBuf,
+    prefix: String,
+}
+
+impl Cache {
+    /// Opens the cache of the terminal, if caching is possible.
+    pub fn open(terminal: &Terminal) -> Option<Self> {
+        let runtime_dir = env::var_os("XDG_RUNTIME_DIR").filter(|path| !path.is_empty())?;
+
+        // The controlling terminal is the same for all the processes of a
+        // session, yet it's known by the generic name only, hence the session
+        // stands for the terminal.
+        let terminal_key = if terminal.device == Path::new(TTY_DEVICE) {
+            format!("session{}", unsafe { libc::getsid(0) })
+        } else {
+            terminal.device.to_string_lossy().replace('/', "_")
+        };
+
+        Some(Cache {
+            directory: PathBuf::from(runtime_dir).join("ttybox"),
+            prefix: format!("clipboard-cache-{terminal_key}-"),
+        })
+    }
+
+    /// Returns the content cached for the request, unless it's older than the
+    /// TTL.
+    pub fn get(&self, request: &str, ttl: Duration) -> Option<Vec<u8>> {
+        let path = self.path(request);
+        let age = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        if age > ttl {
+            return None;
+        }
+        fs::read(path).ok()
+    }
+
+    /// Caches the content pasted for the request.
+    pub fn put(&self, request: &str, content: &[u8]) -> io::Result<()> {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.directory)?;
+
+        // The entry is replaced atomically, so concurrent readers never see it
+        // partially written.
+        let path = self.path(request);
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(format!(".{}", std::process::id()));
+        File::options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&temp_path)?
+            .write_all(content)?;
+        fs::rename(&temp_path, &path)
+    }
+
+    /// Removes all the entries of the terminal, e.g. once the clipboard is
+    /// changed.
+    pub fn invalidate(&self) -> io::Result<()> {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(&self.prefix)
+            {
+                match fs::remove_file(entry.path()) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, request: &str) -> PathBuf {
+        self.directory.join(format!("{}{request}", self.prefix))
+    }
+}
+
+/// Drops the cached contents of the terminal, if any, since the clipboard has
+/// been changed.
+pub fn invalidate(terminal: &Terminal) -> io::Result<()> {
+    match Cache::open(terminal) {
+        Some(cache) => cache.invalidate(),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,406 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use clap::{Args, Subcommand, ValueEnum};
+
+use super::{run_clipboard_tool, with_backends, BackendArgs, Osc52, PayloadLimit, Selection};
+use crate::dirs::data_dir;
+use crate::json;
+use crate::terminal::Terminal;
+
+/// The maximum number of characters of an entry shown by `history list`.
+const PREVIEW_LENGTH: usize = 80;
+
+#[derive(Subcommand, Debug)]
+pub enum HistoryCommands {
+    List(HistoryListArgs),
+    Get(HistoryGetArgs),
+    Copy(HistoryCopyArgs),
+    Prune(HistoryPruneArgs),
+    /// Print the entries in the given format, or store them in the cliphist
+    /// database.
+    Export(HistoryExportArgs),
+    /// Merge the entries in the given format read from the standard input, or
+    /// the ones of the cliphist database, into the history.
+    Import(HistoryImportArgs),
+}
+
+/// The formats the history is exported to and imported from.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryFormat {
+    /// An array of objects with the "timestamp" and either the "content" or,
+    /// for binary content, the "content_base64" member, oldest first.
+    Json,
+    /// An array of strings, oldest first, as in clipman's history file.
+    Clipman,
+    /// The database of cliphist, accessed by means of the cliphist tool.
+    Cliphist,
+}
+
+#[derive(Args, Debug)]
+pub struct HistoryListArgs {
+    /// The maximum number of entries to list, starting from the most recent.
+    #[arg(short = 'n', long)]
+    limit: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+pub struct HistoryGetArgs {
+    /// The number of the entry, where 1 stands for the most recent one.
+    number: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct HistoryCopyArgs {
+    /// The number of the entry, where 1 stands for the most recent one.
+    number: usize,
+
+    /// The selections to use, as a comma separated list.
+    #[arg(short, long, value_delimiter = ',', default_value = "clipboard")]
+    selection: Vec<Selection>,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct HistoryPruneArgs {
+    /// Keep at most the given number of the most recent entries.
+    #[arg(long, value_name = "N")]
+    max_entries: Option<usize>,
+
+    /// Remove the entries older than the given number of days.
+    #[arg(long, value_name = "DAYS")]
+    max_age: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+pub struct HistoryExportArgs {
+    /// The format to export the entries in.
+    #[arg(long, value_enum, default_value_t = HistoryFormat::Json)]
+    format: HistoryFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct HistoryImportArgs {
+    /// The format to import the entries from.
+    #[arg(long, value_enum, default_value_t = HistoryFormat::Json)]
+    format: HistoryFormat,
+}
+
+pub fn execute(command: HistoryCommands, terminal: &Terminal) -> io::Result<()> {
+    let history = History::open()?;
+
+    match command {
+        HistoryCommands::List(args) => {
+            let mut stdout = io::stdout().lock();
+            let entries = history.entries()?;
+            let count = args.limit.unwrap_or(entries.len());
+
+            for (number, entry) in entries.iter().rev().take(count).enumerate() {
+                writeln!(stdout, "{}\t{}", number + 1, entry.preview())?;
+            }
+            Ok(())
+        }
+        HistoryCommands::Get(args) => {
+            let entry = history.entry(args.number)?;
+            io::stdout().write_all(&entry.content)
+        }
+        HistoryCommands::Copy(args) => {
+            let entry = history.entry(args.number)?;
+            let mut osc52 = Osc52::new(terminal);
+            osc52.limit = PayloadLimit::detect_with(None);
+            with_backends(&args.backend, osc52, |backend| {
+                backend.copy(&entry.content, &args.selection)
+            })
+        }
+        HistoryCommands::Prune(args) => {
+            let mut entries = history.entries()?;
+
+            if let Some(max_age) = args.max_age {
+                let oldest = now().saturating_sub(max_age.saturating_mul(24 * 60 * 60));
+                entries.retain(|entry| entry.timestamp >= oldest);
+            }
+            if let Some(max_entries) = args.max_entries {
+                entries.drain(..entries.len().saturating_sub(max_entries));
+            }
+            history.rewrite(&entries)
+        }
+        HistoryCommands::Export(args) => {
+            let entries = history.entries()?;
+            match args.format {
+                HistoryFormat::Json => {
+                    let items: Vec<String> = entries
+                        .iter()
+                        .map(|entry| {
+                            let content = match std::str::from_utf8(&entry.content) {
+                                Ok(text) => format!("\"content\": {}", json::quote(text)),
+                                Err(_) => format!(
+                                    "\"content_base64\": \"{}\"",
+                                    BASE64_STANDARD.encode(&entry.content)
+                                ),
+                            };
+                            format!("{{\"timestamp\": {}, {content}}}", entry.timestamp)
+                        })
+                        .collect();
+                    println!("[{}]", items.join(", "));
+                }
+                HistoryFormat::Clipman => {
+                    // clipman keeps text only.
+                    let items: Vec<String> = entries
+                        .iter()
+                        .filter_map(|entry| std::str::from_utf8(&entry.content).ok())
+                        .map(json::quote)
+                        .collect();
+                    println!("[{}]", items.join(", "));
+                }
+                HistoryFormat::Cliphist => {
+                    for entry in &entries {
+                        run_clipboard_tool([("cliphist", vec!["store"])], Some(&entry.content))?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        HistoryCommands::Import(args) => {
+            let imported = match args.format {
+                HistoryFormat::Json | HistoryFormat::Clipman => {
+                    let mut text = String::new();
+                    io::stdin().read_to_string(&mut text)?;
+                    parse_entries(&json::parse(&text)?, args.format)?
+                }
+                HistoryFormat::Cliphist => {
+                    // The list is ordered from the most recent entry, and each
+                    // line starts with the ID of the entry followed by a tab.
+                    let list = run_clipboard_tool([("cliphist", vec!["list"])], None)?;
+                    let mut entries = Vec::new();
+                    for line in list.split(|byte| *byte == b'\n').rev() {
+                        let Some(id) = line.split(|byte| *byte == b'\t').next() else {
+                            continue;
+                        };
+                        if id.is_empty() {
+                            continue;
+                        }
+                        let mut request = id.to_vec();
+                        request.push(b'\t');
+                        entries.push(Entry {
+                            timestamp: now(),
+                            content: run_clipboard_tool(
+                                [("cliphist", vec!["decode"])],
+                                Some(&request),
+                            )?,
+                        });
+                    }
+                    entries
+                }
+            };
+
+            // The entries already recorded are skipped, so importing the same
+            // entries again doesn't duplicate them.
+            let mut entries = history.entries()?;
+            for entry in imported {
+                if !entries
+                    .iter()
+                    .any(|recorded| recorded.content == entry.content)
+                {
+                    entries.push(entry);
+                }
+            }
+            entries.sort_by_key(|entry| entry.timestamp);
+            history.rewrite(&entries)
+        }
+    }
+}
+
+/// Returns the entries of the parsed JSON document in the given format. Entries
+/// without a timestamp are considered recorded now.
+fn parse_entries(document: &json::Value, format: HistoryFormat) -> io::Result<Vec<Entry>> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The history to import doesn't match the format.",
+        )
+    };
+    let json::Value::Array(items) = document else {
+        return Err(invalid());
+    };
+
+    items
+        .iter()
+        .map(|item| {
+            let content = match format {
+                HistoryFormat::Clipman => item.as_str().map(|text| text.as_bytes().to_vec()),
+                _ => match (item.get("content"), item.get("content_base64")) {
+                    (Some(content), _) => content.as_str().map(|text| text.as_bytes().to_vec()),
+                    (_, Some(content)) => content
+                        .as_str()
+                        .and_then(|content| BASE64_STANDARD.decode(content).ok()),
+                    _ => None,
+                },
+            };
+            Ok(Entry {
+                timestamp: item
+                    .get("timestamp")
+                    .and_then(json::Value::as_u64)
+                    .unwrap_or_else(now),
+                content: content.ok_or_else(invalid)?,
+            })
+        })
+        .collect()
+}
+
+/// A clipboard content recorded in the history.
+pub struct Entry {
+    pub timestamp: u64,
+    pub content: Vec<u8>,
+}
+
+impl Entry {
+    /// Returns the first line of the content, shortened and with control
+    /// characters stripped, suitable for listing.
+    fn preview(&self) -> String {
+        match std::str::from_utf8(&self.content) {
+            Ok(text) => {
+                let line = text.trim_start().lines().next().unwrap_or_default();
+                let mut preview: String = line
+                    .chars()
+                    .filter(|char| !char.is_control())
+                    .take(PREVIEW_LENGTH)
+                    .collect();
+                if line.chars().count() > PREVIEW_LENGTH || text.trim().lines().nth(1).is_some() {
+                    preview.push('…');
+                }
+                preview
+            }
+            Err(_) => format!("[binary {} bytes]", self.content.len()),
+        }
+    }
+}
+
+/// The clipboard history, stored in an append-only file where every entry is a
+/// header line with the entry's timestamp and size followed by the content as
+/// is, so binary content is stored intact.
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    /// Opens the history stored under `$XDG_DATA_HOME/ttybox`.
+    pub fn open() -> io::Result<Self> {
+        Ok(History {
+            path: data_dir()?.join("clipboard-history"),
+        })
+    }
+
+    /// Records the content as the most recent entry.
+    pub fn record(&self, content: &[u8]) -> io::Result<()> {
+        // A single write of the whole entry keeps the entries intact when
+        // multiple processes append to the history simultaneously.
+        self.open_for_writing(&self.path, File::options().append(true))?
+            .write_all(&serialize(now(), content))
+    }
+
+    /// Returns all the entries, ordered from the oldest to the most recent.
+    pub fn entries(&self) -> io::Result<Vec<Entry>> {
+        let mut reader = match File::open(&self.path) {
+            Ok(file) => BufReader::new(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut entries = Vec::new();
+        let mut header = String::new();
+
+        loop {
+            header.clear();
+            if reader.read_line(&mut header)? == 0 {
+                break;
+            }
+
+            let (timestamp, size) = header
+                .trim_end()
+                .split_once(' ')
+                .and_then(|(timestamp, size)| {
+                    Some((timestamp.parse().ok()?, size.parse::<usize>().ok()?))
+                })
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("The history file '{}' is corrupted.", self.path.display()),
+                    )
+                })?;
+
+            let mut content = vec![0; size + 1];
+            reader.read_exact(&mut content)?;
+            content.pop();
+            entries.push(Entry { timestamp, content });
+        }
+        Ok(entries)
+    }
+
+    /// Returns the entry by its number, where 1 stands for the most recent one.
+    pub fn entry(&self, number: usize) -> io::Result<Entry> {
+        let mut entries = self.entries()?;
+        let count = entries.len();
+
+        match number.checked_sub(1).filter(|index| *index < count) {
+            Some(index) => Ok(entries.swap_remove(count - 1 - index)),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("There's no history entry {number}, the history has {count} entries."),
+            )),
+        }
+    }
+
+    /// Replaces all the entries of the history. The history file is replaced
+    /// atomically, so it's never lost or left partially written.
+    pub fn rewrite(&self, entries: &[Entry]) -> io::Result<()> {
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(format!(".{}", std::process::id()));
+        let temp_path = PathBuf::from(temp_path);
+
+        let result = self
+            .open_for_writing(&temp_path, File::options().write(true).truncate(true))
+            .and_then(|mut file| {
+                for entry in entries {
+                    file.write_all(&serialize(entry.timestamp, &entry.content))?;
+                }
+                file.sync_all()
+            })
+            .and_then(|()| fs::rename(&temp_path, &self.path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    /// Opens the history file, creating it along with its directory if needed.
+    /// The history may contain sensitive data, hence it's kept private.
+    fn open_for_writing(&self, path: &Path, options: &mut fs::OpenOptions) -> io::Result<File> {
+        if let Some(directory) = path.parent() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(directory)?;
+        }
+        options.create(true).mode(0o600).open(path)
+    }
+}
+
+/// Returns the entry in the format of the history file.
+fn serialize(timestamp: u64, content: &[u8]) -> Vec<u8> {
+    let mut record = format!("{timestamp} {}\n", content.len()).into_bytes();
+    record.extend_from_slice(content);
+    record.push(b'\n');
+    record
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
@@ -0,0 +1,273 @@
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+
+use super::{check_selections, run_clipboard_tool, Backend, Selection};
+use crate::error::{self, Failure};
+use crate::identify;
+use crate::passthrough;
+use crate::terminal::Terminal;
+use crate::tty::{self, read_response, set_nonblocking, DEFAULT_TIMEOUT};
+
+/// The prefix of an OSC 5522 sequence, up to the metadata.
+const OSC_5522_PREFIX: &[u8] = b"\x1B]5522;";
+
+/// The maximum size of the content sent in a single OSC 5522 sequence, chosen
+/// so the base64 encoded chunk fits into the 4096 bytes kitty accepts.
+const CHUNK_SIZE: usize = 3072;
+
+/// The MIME type of the content unless requested otherwise.
+const DEFAULT_MIME: &str = "text/plain";
+
+/// Returns the output of the last command run in the shell, as delimited by the
+/// semantic prompt marks (OSC 133) the shell integration emits. kitty is asked
+/// for it by means of its remote control, which has to be enabled.
+pub fn last_command_output() -> io::Result<Vec<u8>> {
+    if !identify::is_kitty() {
+        return Err(error::new(
+            Failure::Unsupported,
+            "Only kitty provides the output of the last command.",
+        ));
+    }
+    let args = vec!["@", "get-text", "--extent", "last_cmd_output"];
+    run_clipboard_tool([("kitten", args.clone()), ("kitty", args)], None)
+}
+
+/// The backend that talks to kitty by means of its clipboard protocol (OSC
+/// 5522), which, unlike OSC 52, supports content of arbitrary size and MIME
+/// types, and acknowledges copying.
+///
+/// See https://sw.kovidgoyal.net/kitty/clipboard/ for details.
+pub struct Kitty<'a> {
+    pub terminal: &'a Terminal,
+    pub timeout: Duration,
+    pub mime: String,
+}
+
+impl<'a> Kitty<'a> {
+    pub fn new(terminal: &'a Terminal) -> Self {
+        Kitty {
+            terminal,
+            timeout: DEFAULT_TIMEOUT,
+            mime: DEFAULT_MIME.to_owned(),
+        }
+    }
+
+    /// Sends the requests to the terminal and returns the sequences of its
+    /// response, up to and including the one with the final status.
+    fn transact(&self, requests: Vec<Vec<u8>>) -> io::Result<Vec<Sequence>> {
+        let mut tty = self.terminal.open()?;
+
+        let response = tty::with_noecho_cbreak_mode(tty.as_raw_fd(), || {
+            let mut sequences = Vec::new();
+            for request in requests {
+                sequences.extend(self.terminal.wrap(request)?);
+            }
+            tty.write_all(&sequences)?;
+            tty.flush()?;
+
+            set_nonblocking(tty.as_raw_fd())?;
+            read_response(&tty, self.timeout, |response| {
+                parse_sequences(response)
+                    .last()
+                    .is_some_and(|sequence| sequence.is_final())
+            })
+        })
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::TimedOut if error::failure(&e).is_none() => error::new(
+                Failure::Timeout,
+                "The terminal emulator either doesn't support OSC 5522 or is sluggish.",
+            ),
+            _ => e,
+        })?;
+
+        let sequences = parse_sequences(&response);
+        match sequences.last().and_then(|sequence| sequence.get("status")) {
+            Some("DONE") => Ok(sequences),
+            Some(status) => Err(status_error(status)),
+            None => Err(error::new(
+                Failure::Protocol,
+                "Malformed OSC 5522 response: no status found.",
+            )),
+        }
+    }
+}
+
+impl Backend for Kitty<'_> {
+    // Zellij doesn't forward OSC 5522 sequences to kitty.
+    fn is_available(&self) -> bool {
+        identify::is_kitty() && !passthrough::is_zellij()
+    }
+
+    fn copy(&self, content: &[u8], selections: &[Selection]) -> io::Result<()> {
+        check_selections(
+            "kitty",
+            selections,
+            &[Selection::Clipboard, Selection::Primary],
+        )?;
+
+        let mime = BASE64_STANDARD.encode(&self.mime);
+        for selection in selections {
+            let location = location(selection);
+            let mut requests = vec![request(&format!("type=write{location}"), b"")];
+            for chunk in content.chunks(CHUNK_SIZE) {
+                requests.push(request(
+                    &format!("type=wdata:mime={mime}"),
+                    BASE64_STANDARD.encode(chunk).as_bytes(),
+                ));
+            }
+            requests.push(request("type=wdata", b""));
+            self.transact(requests)?;
+        }
+        Ok(())
+    }
+
+    fn paste(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        let selection = selections.first().unwrap_or(&Selection::Clipboard);
+        check_selections(
+            "kitty",
+            &[*selection],
+            &[Selection::Clipboard, Selection::Primary],
+        )?;
+
+        let location = location(selection);
+        let sequences = self.transact(vec![request(
+            &format!("type=read{location}"),
+            BASE64_STANDARD.encode(&self.mime).as_bytes(),
+        )])?;
+
+        let mime = BASE64_STANDARD.encode(&self.mime);
+        let mut content = Vec::new();
+        let mut found = false;
+        for sequence in &sequences {
+            // The MIME type is expected base64 encoded, yet a plain one is
+            // tolerated as well.
+            let matches = sequence
+                .get("mime")
+                .is_some_and(|value| value == mime || value == self.mime);
+            if sequence.get("status") == Some("DATA") && matches {
+                found = true;
+                content.extend(BASE64_STANDARD.decode(&sequence.payload).map_err(|_| {
+                    error::new(
+                        Failure::Protocol,
+                        "OSC 5522 response doesn't contain valid base64 content.",
+                    )
+                })?);
+            }
+        }
+
+        if !found {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("The clipboard has no content of type '{}'.", self.mime),
+            ));
+        }
+        Ok(content)
+    }
+}
+
+/// An OSC 5522 sequence, which looks like `ESC ] 5522 ; metadata ; payload ST`
+/// where metadata is a colon separated list of key=value pairs.
+struct Sequence {
+    metadata: Vec<(String, String)>,
+    payload: Vec<u8>,
+}
+
+impl Sequence {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns whether the sequence completes the response, i.e. has a status
+    /// other than the ones preceding the data.
+    fn is_final(&self) -> bool {
+        !matches!(self.get("status"), None | Some("OK" | "DATA"))
+    }
+}
+
+fn request(metadata: &str, payload: &[u8]) -> Vec<u8> {
+    let mut sequence = OSC_5522_PREFIX.to_vec();
+    sequence.extend(metadata.as_bytes());
+    if !payload.is_empty() {
+        sequence.push(b';');
+        sequence.extend(payload);
+    }
+    sequence.extend(b"\x1B\\");
+    sequence
+}
+
+/// Returns the metadata entry selecting the location of the clipboard.
+fn location(selection: &Selection) -> &'static str {
+    match selection {
+        Selection::Primary => ":loc=primary",
+        _ => "",
+    }
+}
+
+/// Returns the complete OSC 5522 sequences found in the response, ignoring any
+/// other bytes in between.
+fn parse_sequences(response: &[u8]) -> Vec<Sequence> {
+    let mut sequences = Vec::new();
+    let mut rest = response;
+
+    while let Some(start) = rest
+        .windows(OSC_5522_PREFIX.len())
+        .position(|window| window == OSC_5522_PREFIX)
+    {
+        let body = &rest[start + OSC_5522_PREFIX.len()..];
+        let Some((end, terminator)) =
+            body.iter()
+                .enumerate()
+                .find_map(|(index, byte)| match (byte, body.get(index + 1)) {
+                    (b'\x07', _) => Some((index, 1)),
+                    (b'\x1B', Some(b'\\')) => Some((index, 2)),
+                    _ => None,
+                })
+        else {
+            break;
+        };
+
+        let (metadata, payload) = match body[..end].iter().position(|byte| *byte == b';') {
+            Some(index) => (&body[..index], &body[index + 1..end]),
+            None => (&body[..end], &body[end..end]),
+        };
+        sequences.push(Sequence {
+            metadata: String::from_utf8_lossy(metadata)
+                .split(':')
+                .filter_map(|entry| entry.split_once('='))
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect(),
+            payload: payload.to_vec(),
+        });
+        rest = &body[end + terminator..];
+    }
+    sequences
+}
+
+fn status_error(status: &str) -> io::Error {
+    if status == "ENOSYS" {
+        return error::new(
+            Failure::Unsupported,
+            "The terminal doesn't support the requested selection (ENOSYS).",
+        );
+    }
+
+    let (kind, reason) = match status {
+        "EPERM" => (io::ErrorKind::PermissionDenied, "the access is denied"),
+        "EBUSY" => (
+            io::ErrorKind::ResourceBusy,
+            "another request is in progress",
+        ),
+        "EINVAL" => (io::ErrorKind::InvalidInput, "the request is invalid"),
+        _ => (io::ErrorKind::Other, "an I/O error has occurred"),
+    };
+    io::Error::new(
+        kind,
+        format!("The terminal has rejected the clipboard request: {reason} ({status})."),
+    )
+}
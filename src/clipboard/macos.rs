@@ -0,0 +1,28 @@
+use std::io;
+
+use super::{check_selections, run_clipboard_tool, Backend, Selection};
+
+/// The backend that relies on the pbcopy and pbpaste tools to access the macOS
+/// pasteboard, which has no notion of selections other than the clipboard.
+pub struct Macos;
+
+impl Backend for Macos {
+    fn is_available(&self) -> bool {
+        cfg!(target_os = "macos")
+    }
+
+    fn copy(&self, content: &[u8], selections: &[Selection]) -> io::Result<()> {
+        check_selections("macOS", selections, &[Selection::Clipboard])?;
+        run_clipboard_tool([("pbcopy", vec![])], Some(content))?;
+        Ok(())
+    }
+
+    fn paste(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        check_selections(
+            "macOS",
+            &selections[..selections.len().min(1)],
+            &[Selection::Clipboard],
+        )?;
+        run_clipboard_tool([("pbpaste", vec![])], None)
+    }
+}
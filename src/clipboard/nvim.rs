@@ -0,0 +1,228 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+use sha2::{Digest, Sha256};
+
+use super::{with_backends, BackendArgs, ClipboardTimeoutArgs, LimitArgs, Osc52, Selection};
+use crate::dirs::data_dir;
+use crate::terminal::Terminal;
+
+#[derive(Args, Debug)]
+pub struct NvimSetArgs {
+    /// The type of the register, as passed to the clipboard provider by
+    /// Neovim: "v" for charwise, "V" for linewise, or "b" followed by the width
+    /// for blockwise.
+    #[arg(long, value_name = "TYPE", value_parser = parse_regtype, default_value = "v")]
+    regtype: Regtype,
+
+    /// The selection to use.
+    #[arg(short, long, default_value = "clipboard")]
+    selection: Selection,
+
+    #[command(flatten)]
+    limit: LimitArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct NvimGetArgs {
+    /// The selection to use.
+    #[arg(short, long, default_value = "clipboard")]
+    selection: Selection,
+
+    #[command(flatten)]
+    timeout: ClipboardTimeoutArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+}
+
+/// The type of a Neovim register, which determines how its content is put.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Regtype {
+    Charwise,
+    Linewise,
+    Blockwise(Option<usize>),
+}
+
+impl Regtype {
+    /// Guesses the type of content copied by someone other than Neovim, the
+    /// way Neovim does: whole lines end with a newline character.
+    fn infer(content: &[u8]) -> Self {
+        if content.ends_with(b"\n") {
+            Regtype::Linewise
+        } else {
+            Regtype::Charwise
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Regtype::Charwise => "v".to_owned(),
+            Regtype::Linewise => "V".to_owned(),
+            Regtype::Blockwise(Some(width)) => format!("b{width}"),
+            Regtype::Blockwise(None) => "b".to_owned(),
+        }
+    }
+}
+
+/// Parses the register type, accepting CTRL-V in place of "b" as returned by
+/// Neovim's getregtype().
+fn parse_regtype(value: &str) -> Result<Regtype, String> {
+    let width = value
+        .strip_prefix('b')
+        .or_else(|| value.strip_prefix('\x16'));
+    match (value, width) {
+        ("v", _) => Ok(Regtype::Charwise),
+        ("V", _) => Ok(Regtype::Linewise),
+        (_, Some("")) => Ok(Regtype::Blockwise(None)),
+        (_, Some(width)) => width
+            .parse()
+            .map(|width| Regtype::Blockwise(Some(width)))
+            .map_err(|_| format!("invalid register type '{value}'")),
+        _ => Err(format!("invalid register type '{value}'")),
+    }
+}
+
+/// Copies the register lines read from the standard input, remembering the
+/// register type so that "nvim-get" can restore it.
+pub fn execute_set(args: NvimSetArgs, terminal: &Terminal) -> io::Result<()> {
+    let mut content = Vec::new();
+    io::stdin().read_to_end(&mut content)?;
+
+    // The lines may or may not be terminated by a newline character depending
+    // on how the provider feeds them, while the copied content of a linewise
+    // register always ends with one, so other applications paste whole lines.
+    if content.ends_with(b"\n") {
+        content.pop();
+    }
+    if args.regtype == Regtype::Linewise {
+        content.push(b'\n');
+    }
+
+    let mut osc52 = Osc52::new(terminal);
+    osc52.limit = args.limit.detect();
+    with_backends(&args.backend, osc52, |backend| {
+        backend.copy(&content, &[args.selection])
+    })?;
+
+    Regtypes::open()?.record(args.selection, &args.regtype, &content)
+}
+
+/// Prints the register type on the first line, followed by the register lines
+/// of the pasted content.
+pub fn execute_get(args: NvimGetArgs, terminal: &Terminal) -> io::Result<()> {
+    let mut osc52 = Osc52::new(terminal);
+    osc52.timeout = args.timeout.duration();
+    let content = with_backends(&args.backend, osc52, |backend| {
+        backend.paste(&[args.selection])
+    })?;
+
+    // The recorded register type applies only as long as the selection holds
+    // the content copied along with it.
+    let regtype = Regtypes::open()?
+        .lookup(args.selection, &content)?
+        .unwrap_or_else(|| Regtype::infer(&content));
+
+    let lines = match regtype {
+        Regtype::Linewise => content.strip_suffix(b"\n").unwrap_or(&content),
+        _ => &content,
+    };
+    let mut output = format!("{}\n", regtype.name()).into_bytes();
+    for line in lines.split(|byte| *byte == b'\n') {
+        output.extend_from_slice(line);
+        output.push(b'\n');
+    }
+    io::stdout().write_all(&output)
+}
+
+/// The register types of the contents copied via "nvim-set", stored per
+/// selection along with the SHA-256 digest of the content, one selection per
+/// line. The content itself isn't stored, as the selections hold it anyway.
+struct Regtypes {
+    path: PathBuf,
+}
+
+impl Regtypes {
+    /// Opens the register types stored under `$XDG_DATA_HOME/ttybox`.
+    fn open() -> io::Result<Self> {
+        Ok(Regtypes {
+            path: data_dir()?.join("nvim-regtypes"),
+        })
+    }
+
+    /// Returns the register type recorded for the selection, if the content is
+    /// the one it was recorded along with.
+    fn lookup(&self, selection: Selection, content: &[u8]) -> io::Result<Option<Regtype>> {
+        let digest = digest(content);
+        Ok(self
+            .records()?
+            .into_iter()
+            .find(|(name, _, recorded)| *name == selection_name(selection) && *recorded == digest)
+            .and_then(|(_, regtype, _)| parse_regtype(&regtype).ok()))
+    }
+
+    /// Records the register type of the content copied to the selection,
+    /// replacing the one recorded for the selection before.
+    fn record(&self, selection: Selection, regtype: &Regtype, content: &[u8]) -> io::Result<()> {
+        let mut records = self.records()?;
+        records.retain(|(name, _, _)| *name != selection_name(selection));
+        records.push((selection_name(selection), regtype.name(), digest(content)));
+
+        if let Some(directory) = self.path.parent() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(directory)?;
+        }
+        let mut file = File::options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.path)?;
+        for (name, regtype, digest) in records {
+            writeln!(file, "{name} {regtype} {digest}")?;
+        }
+        Ok(())
+    }
+
+    /// Returns the selection, register type, and digest of every record.
+    /// Malformed records are ignored, as they only cost a guessed type.
+    fn records(&self) -> io::Result<Vec<(String, String, String)>> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        Ok(text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ');
+                match (fields.next(), fields.next(), fields.next()) {
+                    (Some(name), Some(regtype), Some(digest)) => {
+                        Some((name.to_owned(), regtype.to_owned(), digest.to_owned()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect())
+    }
+}
+
+fn selection_name(selection: Selection) -> String {
+    selection.to_possible_value().unwrap().get_name().to_owned()
+}
+
+/// Returns the hex encoded SHA-256 digest of the content.
+fn digest(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
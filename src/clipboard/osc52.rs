@@ -0,0 +1,743 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+
+use super::quirks::Quirks;
+use super::{Backend, Overflow, Selection};
+use crate::error::{self, Failure};
+use crate::identify;
+use crate::query::{self, OSC_TERMINATORS};
+use crate::terminal::{SequenceWriter, Terminal};
+use crate::tty;
+
+/// The 8-bit (C1) forms of OSC and ST. They are rarely used by terminals these
+/// days, since they clash with UTF-8, yet they are tolerated in lenient mode.
+const C1_OSC: u8 = b'\x9D';
+const C1_ST: u8 = b'\x9C';
+
+/// The prefix of an OSC 52 sequence, up to the selection parameter.
+const OSC_52_PREFIX: &[u8] = b"\x1B]52;";
+
+/// The control character that cancels the control sequence being sent, as
+/// defined by ECMA-48.
+const CAN: u8 = b'\x18';
+
+/// The size of the blocks the content is read in when streaming, a multiple of
+/// 3 so the blocks are encoded into complete base64 quanta.
+const STREAM_BLOCK_SIZE: usize = 3 * 16 * 1024;
+
+/// The backend that talks to the terminal emulator by means of OSC 52 escape
+/// sequences, and hence works anywhere including remote machines, as long as
+/// the terminal emulator supports it.
+#[derive(Clone)]
+pub struct Osc52<'a> {
+    pub terminal: &'a Terminal,
+    pub limit: Option<PayloadLimit>,
+    pub timeout: Duration,
+    pub lenient: bool,
+}
+
+impl<'a> Osc52<'a> {
+    pub fn new(terminal: &'a Terminal) -> Self {
+        Osc52 {
+            terminal,
+            limit: None,
+            timeout: tty::DEFAULT_TIMEOUT,
+            lenient: false,
+        }
+    }
+
+    /// Copies the content read from the reader, encoding and sending it block
+    /// by block, so the memory usage doesn't depend on the size of the content.
+    /// The content beyond the maximum size is handled according to the
+    /// overflow policy, which requires buffering a single line at most.
+    pub fn copy_stream(
+        &self,
+        mut reader: impl Read,
+        selections: &[Selection],
+        max_size: u64,
+        overflow: Overflow,
+    ) -> io::Result<()> {
+        let mut stream = CopyStream::new(self.terminal, selections, self.limit.as_ref())?;
+        let mut buffer = vec![0u8; STREAM_BLOCK_SIZE];
+        let mut pending_line = Vec::new();
+        let mut size = 0u64;
+
+        loop {
+            let block = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => &buffer[..read],
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            let room = max_size - size;
+            if block.len() as u64 > room {
+                let block = &block[..room as usize];
+                match overflow {
+                    Overflow::Error => {
+                        stream.abort()?;
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("The content exceeds the maximum size of {max_size} bytes."),
+                        ));
+                    }
+                    Overflow::Truncate => stream.write(block)?,
+                    Overflow::Head => {
+                        if let Some(position) = block.iter().rposition(|byte| *byte == b'\n') {
+                            stream.write(&pending_line)?;
+                            stream.write(&block[..=position])?;
+                        }
+                    }
+                }
+                return stream.finish();
+            }
+            size += block.len() as u64;
+
+            // Only complete lines are sent in head mode, as the last one may
+            // turn out to exceed the maximum size.
+            match block.iter().rposition(|byte| *byte == b'\n') {
+                Some(position) if overflow == Overflow::Head => {
+                    stream.write(&pending_line)?;
+                    stream.write(&block[..=position])?;
+                    pending_line.clear();
+                    pending_line.extend_from_slice(&block[position + 1..]);
+                }
+                None if overflow == Overflow::Head => pending_line.extend_from_slice(block),
+                _ => stream.write(block)?,
+            }
+        }
+
+        stream.write(&pending_line)?;
+        stream.finish()
+    }
+
+    /// Pastes the content, decoding and writing it to the output as the
+    /// response arrives, so the memory usage doesn't depend on the size of the
+    /// content. When pasting fails, the output may have received a part of the
+    /// content already.
+    pub fn paste_stream(&self, selections: &[Selection], output: impl Write) -> io::Result<()> {
+        check_paste()?;
+        let mut tty = self.terminal.open()?;
+        let mut stream = PasteStream::new(output);
+
+        tty::with_noecho_cbreak_mode(tty.as_raw_fd(), || {
+            osc_request_paste(&mut tty, selections, self.terminal)?;
+            tty::set_nonblocking(tty.as_raw_fd())?;
+            tty::read_stream(&tty, self.timeout, |data| stream.feed(data))
+        })
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::TimedOut if error::failure(&e).is_none() => error::new(
+                Failure::Timeout,
+                "The terminal emulator either doesn't support OSC 52 or is sluggish.",
+            ),
+            _ => e,
+        })
+        .map_err(|e| explain_timeout(e, self.timeout, self.terminal))?;
+        stream.output.flush()
+    }
+
+    /// Returns the response of the terminal to the paste request as is, i.e.
+    /// without parsing and decoding it.
+    pub fn paste_raw(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        osc_query_paste(selections, self.timeout, false, self.terminal)
+    }
+}
+
+impl Backend for Osc52<'_> {
+    fn is_available(&self) -> bool {
+        self.terminal.is_available()
+    }
+
+    fn copy(&self, content: &[u8], selections: &[Selection]) -> io::Result<()> {
+        self.copy_encoded(BASE64_STANDARD.encode(content).as_bytes(), selections)
+    }
+
+    fn paste(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        decode_base64(&self.paste_encoded(selections)?)
+    }
+
+    // The OSC 52 payload is base64 encoded content, and thus the encoded
+    // content is sent and received as is without any conversion.
+    fn copy_encoded(&self, payload: &[u8], selections: &[Selection]) -> io::Result<()> {
+        osc_copy(payload, selections, self.limit.as_ref(), self.terminal)
+    }
+
+    fn paste_encoded(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        check_paste()?;
+        osc_paste(selections, self.timeout, self.lenient, self.terminal)
+            .map_err(|e| explain_timeout(e, self.timeout, self.terminal))
+    }
+}
+
+/// The limit terminal emulators impose on the size of the OSC 52 payload, i.e.
+/// the base64 encoded content. Some of them accept the content split into
+/// multiple OSC 52 sequences, and thus the limit applies to a single sequence
+/// only.
+#[derive(Clone, Debug, Default)]
+pub struct PayloadLimit {
+    max_bytes: usize,
+    chunking: bool,
+}
+
+impl PayloadLimit {
+    /// Detects the limit of the terminal emulator the process is running in,
+    /// if it's known to have one.
+    fn detect() -> Option<Self> {
+        Quirks::detect().and_then(|quirks| {
+            Some(PayloadLimit {
+                max_bytes: quirks.max_bytes?,
+                chunking: quirks.chunking,
+            })
+        })
+    }
+
+    /// Detects the limit of the terminal emulator, overriding the maximum size
+    /// of a single OSC 52 sequence if it's given explicitly.
+    pub fn detect_with(max_bytes: Option<usize>) -> Option<Self> {
+        let mut limit = PayloadLimit::detect();
+        if let Some(max_bytes) = max_bytes {
+            limit.get_or_insert(PayloadLimit::default()).max_bytes = max_bytes;
+        }
+        limit
+    }
+
+    /// Splits the payload into chunks that fit into the limit, one chunk per
+    /// OSC 52 sequence.
+    fn split<'a>(&self, payload: &'a [u8]) -> io::Result<Vec<&'a [u8]>> {
+        if payload.len() <= self.max_bytes {
+            return Ok(vec![payload]);
+        }
+
+        if !self.chunking {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "The content is {} bytes long once encoded, which exceeds the \
+                     terminal limit of {} bytes.",
+                    payload.len(),
+                    self.max_bytes
+                ),
+            ));
+        }
+
+        Ok(payload.chunks(self.chunk_size()).collect())
+    }
+
+    /// Returns the size of a chunk of the payload. Each chunk is decoded on its
+    /// own, hence the size must be a multiple of 4 in order to not split base64
+    /// quanta across chunks.
+    fn chunk_size(&self) -> usize {
+        (self.max_bytes - self.max_bytes % 4).max(4)
+    }
+}
+
+/// The OSC 52 copy sequences being sent, with the content encoded on the fly.
+struct CopyStream<'a> {
+    writer: SequenceWriter<'a>,
+    prefix: Vec<u8>,
+    limit: Option<&'a PayloadLimit>,
+    /// The size of the payload sent within the current sequence, or `None` if
+    /// there's no sequence open.
+    sent: Option<usize>,
+    sequences: usize,
+    /// The trailing bytes of the content that don't make a complete base64
+    /// quantum yet.
+    carry: Vec<u8>,
+}
+
+impl<'a> CopyStream<'a> {
+    fn new(
+        terminal: &'a Terminal,
+        selections: &[Selection],
+        limit: Option<&'a PayloadLimit>,
+    ) -> io::Result<Self> {
+        let mut prefix = OSC_52_PREFIX.to_vec();
+        prefix.extend(selections.iter().map(Selection::parameter));
+        prefix.push(b';');
+
+        Ok(CopyStream {
+            writer: terminal.writer()?,
+            prefix,
+            limit,
+            sent: None,
+            sequences: 0,
+            carry: Vec::with_capacity(3),
+        })
+    }
+
+    fn write(&mut self, content: &[u8]) -> io::Result<()> {
+        if content.is_empty() {
+            return Ok(());
+        }
+
+        // Only complete quanta are encoded, so the encoded blocks concatenate
+        // into the same payload as if the content was encoded at once.
+        let mut content = content;
+        let mut payload = String::new();
+        if !self.carry.is_empty() {
+            let missing = (3 - self.carry.len()).min(content.len());
+            self.carry.extend_from_slice(&content[..missing]);
+            content = &content[missing..];
+            if self.carry.len() < 3 {
+                return Ok(());
+            }
+            BASE64_STANDARD.encode_string(&self.carry, &mut payload);
+            self.carry.clear();
+        }
+        let whole = content.len() - content.len() % 3;
+        BASE64_STANDARD.encode_string(&content[..whole], &mut payload);
+        self.carry.extend_from_slice(&content[whole..]);
+
+        self.send(payload.as_bytes())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        let payload = BASE64_STANDARD.encode(&self.carry);
+        self.send(payload.as_bytes())?;
+
+        // An empty payload clears the clipboard, and thus the sequence has to
+        // be sent even if there's no content.
+        if self.sequences == 0 {
+            self.writer.write(&[&self.prefix, OSC_TERMINATORS[0]])?;
+        } else if self.sent.is_some() {
+            self.writer.write(&[OSC_TERMINATORS[0]])?;
+        }
+        self.writer.flush()
+    }
+
+    /// Cancels the sequence being sent, so the terminal discards it.
+    fn abort(mut self) -> io::Result<()> {
+        if self.sent.is_some() {
+            self.writer.write(&[&[CAN]])?;
+        }
+        self.writer.flush()
+    }
+
+    fn send(&mut self, mut payload: &[u8]) -> io::Result<()> {
+        while !payload.is_empty() {
+            let sent = self.sent.unwrap_or(0);
+            let room = match self.limit {
+                Some(limit) if limit.chunking => limit.chunk_size() - sent,
+                Some(limit) if sent + payload.len() > limit.max_bytes => {
+                    let max_bytes = limit.max_bytes;
+                    if self.sent.is_some() {
+                        self.writer.write(&[&[CAN]])?;
+                    }
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "The content exceeds the terminal limit of {max_bytes} bytes once \
+                             encoded."
+                        ),
+                    ));
+                }
+                _ => payload.len(),
+            };
+            let (chunk, rest) = payload.split_at(room.min(payload.len()));
+            let full = self.limit.is_some_and(|limit| limit.chunking)
+                && sent + chunk.len() == self.limit.map_or(0, PayloadLimit::chunk_size);
+
+            let mut pieces: Vec<&[u8]> = Vec::with_capacity(3);
+            if self.sent.is_none() {
+                pieces.push(&self.prefix);
+                self.sequences += 1;
+            }
+            pieces.push(chunk);
+            if full {
+                pieces.push(OSC_TERMINATORS[0]);
+            }
+            self.writer.write(&pieces)?;
+
+            self.sent = if full { None } else { Some(sent + chunk.len()) };
+            payload = rest;
+        }
+        Ok(())
+    }
+}
+
+/// The OSC 52 paste response being decoded as it arrives. The content is
+/// decoded in complete base64 quanta, and the incomplete one is carried over to
+/// the next part of the response.
+struct PasteStream<W> {
+    output: W,
+    header: Vec<u8>,
+    in_payload: bool,
+    carry: Vec<u8>,
+    offset: usize,
+    padded: bool,
+    escape: bool,
+}
+
+impl<W: Write> PasteStream<W> {
+    fn new(output: W) -> Self {
+        PasteStream {
+            output,
+            header: Vec::new(),
+            in_payload: false,
+            carry: Vec::new(),
+            offset: 0,
+            padded: false,
+            escape: false,
+        }
+    }
+
+    /// Consumes the next part of the response, and returns whether the
+    /// response is complete.
+    fn feed(&mut self, data: &[u8]) -> io::Result<bool> {
+        if self.in_payload {
+            return self.decode(data);
+        }
+
+        // The header, i.e. the prefix and the selection parameter, is validated
+        // the same way the complete response is in strict mode.
+        self.header.extend_from_slice(data);
+        let size = self.header.len().min(OSC_52_PREFIX.len());
+        if self.header[..size] != OSC_52_PREFIX[..size] {
+            return Err(malformed(format!(
+                "expected it to start with '{}', got '{}'",
+                escape(OSC_52_PREFIX),
+                escape(&self.header[..size])
+            )));
+        }
+        let Some(index) = self.header[size..].iter().position(|byte| *byte == b';') else {
+            return Ok(false);
+        };
+
+        if let Some(byte) = self.header[size..size + index]
+            .iter()
+            .find(|byte| !b"cpqs01234567".contains(byte))
+        {
+            return Err(malformed(format!(
+                "unexpected selection parameter '{}'",
+                escape(&[*byte])
+            )));
+        }
+
+        let rest = self.header.split_off(size + index + 1);
+        self.in_payload = true;
+        self.decode(&rest)
+    }
+
+    fn decode(&mut self, data: &[u8]) -> io::Result<bool> {
+        if self.escape {
+            return match data.first() {
+                Some(b'\\') => self.finish(),
+                Some(_) => Err(self.unexpected(b'\x1B')),
+                None => Ok(false),
+            };
+        }
+
+        let end = data
+            .iter()
+            .position(|byte| matches!(*byte, b'\x07' | b'\x1B'))
+            .unwrap_or(data.len());
+        let payload = &data[..end];
+
+        if let Some(index) = payload
+            .iter()
+            .position(|byte| !(byte.is_ascii_alphanumeric() || b"+/=".contains(byte)))
+        {
+            self.offset += index;
+            return Err(self.unexpected(payload[index]));
+        }
+        self.offset += payload.len();
+
+        // Padding may only end the content, and the engine rejects it anywhere
+        // else within the quanta decoded at once.
+        if self.padded && !payload.is_empty() {
+            return Err(invalid_base64());
+        }
+        let mut encoded = std::mem::take(&mut self.carry);
+        encoded.extend_from_slice(payload);
+        let complete = encoded.len() - encoded.len() % 4;
+        if complete > 0 {
+            self.output
+                .write_all(&decode_base64(&encoded[..complete])?)?;
+            self.padded = encoded[complete - 1] == b'=';
+        }
+        self.carry = encoded.split_off(complete);
+
+        match data.get(end..) {
+            Some([b'\x07', ..]) => self.finish(),
+            Some([b'\x1B', b'\\', ..]) => self.finish(),
+            Some([b'\x1B']) => {
+                self.escape = true;
+                Ok(false)
+            }
+            Some([b'\x1B', ..]) => Err(self.unexpected(b'\x1B')),
+            _ => Ok(false),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<bool> {
+        if !self.carry.is_empty() {
+            return Err(invalid_base64());
+        }
+        Ok(true)
+    }
+
+    fn unexpected(&self, byte: u8) -> io::Error {
+        malformed(format!(
+            "unexpected character '{}' in the content at offset {}",
+            escape(&[byte]),
+            self.offset
+        ))
+    }
+}
+
+fn osc_copy(
+    payload: &[u8],
+    selections: &[Selection],
+    limit: Option<&PayloadLimit>,
+    terminal: &Terminal,
+) -> io::Result<()> {
+    let chunks = match limit {
+        Some(limit) => limit.split(payload)?,
+        None => vec![payload],
+    };
+
+    let mut osc_copy_sequences = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        let mut osc_copy_sequence = vec![b'\x1B', b']', b'5', b'2', b';'];
+        osc_copy_sequence.extend(selections.iter().map(Selection::parameter));
+        osc_copy_sequence.push(b';');
+        osc_copy_sequence.extend(chunk);
+        osc_copy_sequence.push(b'\x07');
+        osc_copy_sequences.push(osc_copy_sequence);
+    }
+    terminal.send(osc_copy_sequences)
+}
+
+// OSC 52 pasting is not as simple as copying. Aside of nuances such as
+// switching terminal into noecho/cbreak mode, the procedure consists of three
+// steps: (1) request paste content, (2) read and (3) parse paste response. The
+// base64 encoded content extracted from the response is returned as is.
+fn osc_paste(
+    selections: &[Selection],
+    timeout: Duration,
+    lenient: bool,
+    terminal: &Terminal,
+) -> io::Result<Vec<u8>> {
+    osc_parse_paste(
+        lenient,
+        osc_query_paste(selections, timeout, !lenient, terminal)?,
+    )
+}
+
+/// Requests the content of the clipboard, and returns the response as is. In
+/// strict mode, reading stops as soon as the response is known to be malformed,
+/// instead of waiting for a terminator that may never come.
+fn osc_query_paste(
+    selections: &[Selection],
+    timeout: Duration,
+    strict: bool,
+    terminal: &Terminal,
+) -> io::Result<Vec<u8>> {
+    let mut tty = terminal.open()?;
+
+    // Switching the terminal into noecho/cbreak mode is imperative before
+    // requesting the content of the clipboard. Otherwise, an OSC 52 paste
+    // response (escape codes + base64 encoded clipboard content) is printed to
+    // the screen, and that's undesired. The response has to be decoded first
+    // before being sent to the screen.
+    tty::with_noecho_cbreak_mode(tty.as_raw_fd(), || {
+        osc_request_paste(&mut tty, selections, terminal)?;
+        osc_receive_paste(&mut tty, timeout, strict)
+    })
+}
+
+fn osc_request_paste(
+    file: &mut File,
+    selections: &[Selection],
+    terminal: &Terminal,
+) -> io::Result<()> {
+    let mut osc_paste_sequence = vec![b'\x1B', b']', b'5', b'2', b';'];
+    osc_paste_sequence.extend(selections.iter().map(Selection::parameter));
+    osc_paste_sequence.extend([b';', b'?', b'\x07']);
+    file.write_all(terminal.wrap(osc_paste_sequence)?.as_slice())?;
+    file.flush()
+}
+
+fn osc_receive_paste(file: &mut File, timeout: Duration, strict: bool) -> io::Result<Vec<u8>> {
+    tty::set_nonblocking(file.as_raw_fd())?;
+    let is_complete = |response: &[u8]| {
+        let size = response.len().min(OSC_52_PREFIX.len());
+        is_osc_terminated(response) || (strict && response[..size] != OSC_52_PREFIX[..size])
+    };
+    tty::read_response(file, timeout, is_complete).map_err(|e| match e.kind() {
+        io::ErrorKind::TimedOut if error::failure(&e).is_none() => error::new(
+            Failure::Timeout,
+            "The terminal emulator either doesn't support OSC 52 or is sluggish.",
+        ),
+        _ => e,
+    })
+}
+
+// An OSC 52 paste response looks like `ESC ] 52 ; Pc ; Pd ST`, for instance
+// `\x1B]52;c;Zm9vYmFy\x07`, where Pc is the selection parameter and Pd is the
+// base64 encoded content. Terminals either echo the selection parameter of the
+// request back or leave it empty, and terminate the response with either BEL or
+// ESC \ (ST).
+//
+// In lenient mode, the parser additionally tolerates noise around the response
+// (e.g. responses to other pending queries), the 8-bit forms of OSC and ST, an
+// unexpected selection parameter, and whitespace within the content.
+fn osc_parse_paste(lenient: bool, osc_response: Vec<u8>) -> io::Result<Vec<u8>> {
+    let response = if lenient {
+        find_osc_52(&osc_response).ok_or_else(|| {
+            malformed(format!(
+                "no OSC 52 sequence found in '{}'",
+                escape(&osc_response)
+            ))
+        })?
+    } else {
+        osc_response.strip_prefix(OSC_52_PREFIX).ok_or_else(|| {
+            malformed(format!(
+                "expected it to start with '{}', got '{}'",
+                escape(OSC_52_PREFIX),
+                escape(&osc_response[..osc_response.len().min(OSC_52_PREFIX.len())])
+            ))
+        })?
+    };
+
+    let (selections, rest) = match response.iter().position(|byte| *byte == b';') {
+        Some(index) => (&response[..index], &response[index + 1..]),
+        None => return Err(malformed("no selection parameter found".to_owned())),
+    };
+
+    if !lenient {
+        if let Some(byte) = selections
+            .iter()
+            .find(|byte| !b"cpqs01234567".contains(byte))
+        {
+            return Err(malformed(format!(
+                "unexpected selection parameter '{}'",
+                escape(&[*byte])
+            )));
+        }
+    }
+
+    let payload = if lenient {
+        let end = rest
+            .iter()
+            .position(|byte| matches!(*byte, b'\x07' | b'\x1B' | C1_ST))
+            .unwrap_or(rest.len());
+        rest[..end]
+            .iter()
+            .copied()
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .collect()
+    } else {
+        let payload = strip_osc_terminator(rest)
+            .ok_or_else(|| malformed("no terminating character found".to_owned()))?;
+
+        if let Some(index) = payload
+            .iter()
+            .position(|byte| !(byte.is_ascii_alphanumeric() || b"+/=".contains(byte)))
+        {
+            return Err(malformed(format!(
+                "unexpected character '{}' in the content at offset {index}",
+                escape(&payload[index..index + 1])
+            )));
+        }
+        payload.to_vec()
+    };
+
+    Ok(payload)
+}
+
+/// Fails right away if the terminal is known not to respond to paste requests,
+/// rather than once the request times out.
+fn check_paste() -> io::Result<()> {
+    match Quirks::detect() {
+        Some(quirks) if !quirks.paste => Err(paste_unsupported(quirks)),
+        _ => Ok(()),
+    }
+}
+
+/// Tells why the paste request has timed out if the environment doesn't tell
+/// the terminal, e.g. over SSH, by asking the terminal for its name and
+/// version. The round trip is paid for only once pasting has failed, not for
+/// every paste.
+fn explain_timeout(e: io::Error, timeout: Duration, terminal: &Terminal) -> io::Error {
+    if error::failure(&e) != Some(Failure::Timeout) || identify::detect_env().is_some() {
+        return e;
+    }
+    let quirks = query::query_version(terminal, timeout)
+        .ok()
+        .flatten()
+        .and_then(|version| Quirks::of(&identify::parse_version(&version)));
+    match quirks {
+        Some(quirks) if !quirks.paste => paste_unsupported(quirks),
+        _ => e,
+    }
+}
+
+fn paste_unsupported(quirks: Quirks) -> io::Error {
+    error::new(
+        Failure::Unsupported,
+        format!(
+            "{} doesn't support reading the clipboard via OSC 52.",
+            quirks.name
+        ),
+    )
+}
+
+fn malformed(message: String) -> io::Error {
+    error::new(
+        Failure::Protocol,
+        format!("Malformed OSC 52 response: {message}."),
+    )
+}
+
+/// Returns the last OSC 52 sequence within the response, without its prefix.
+/// Both the 7-bit and the 8-bit forms of OSC are recognized.
+fn find_osc_52(osc_response: &[u8]) -> Option<&[u8]> {
+    let c1_prefix = [C1_OSC, b'5', b'2', b';'];
+
+    (0..osc_response.len()).rev().find_map(|index| {
+        let tail = &osc_response[index..];
+        tail.strip_prefix(OSC_52_PREFIX)
+            .or_else(|| tail.strip_prefix(&c1_prefix[..]))
+    })
+}
+
+/// Returns a printable representation of the bytes, with control characters
+/// and non-ASCII bytes escaped.
+fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|byte| std::ascii::escape_default(*byte))
+        .map(char::from)
+        .collect()
+}
+
+fn decode_base64(payload: &[u8]) -> io::Result<Vec<u8>> {
+    BASE64_STANDARD
+        .decode(payload)
+        .map_err(|_| invalid_base64())
+}
+
+fn invalid_base64() -> io::Error {
+    error::new(
+        Failure::Protocol,
+        "OSC 52 response doesn't contain valid base64 content.",
+    )
+}
+
+/// Returns whether the OSC sequence is terminated, in either 7-bit or 8-bit form.
+fn is_osc_terminated(osc_sequence: &[u8]) -> bool {
+    strip_osc_terminator(osc_sequence).is_some() || osc_sequence.last() == Some(&C1_ST)
+}
+
+/// Returns the OSC sequence without its terminating character(s), or `None` if
+/// the sequence isn't terminated (yet).
+fn strip_osc_terminator(osc_sequence: &[u8]) -> Option<&[u8]> {
+    OSC_TERMINATORS
+        .iter()
+        .find_map(|terminator| osc_sequence.strip_suffix(*terminator))
+}
@@ -0,0 +1,124 @@
+use crate::identify::{self, Identity};
+use crate::passthrough;
+
+/// The maximum size of the payload of a single OSC 52 sequence when sent in
+/// chunks to kitty, which concatenates consecutive chunks back.
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// The maximum size of the payload of an OSC 52 sequence accepted by xterm by
+/// default. Bigger sequences are silently dropped.
+const XTERM_MAX_PAYLOAD_SIZE: usize = 100_000;
+
+/// The known deviations of a terminal emulator from the OSC 52 behavior
+/// ttybox assumes by default, so users don't have to discover them one by one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// The name of the terminal emulator, as shown in error messages.
+    pub name: &'static str,
+
+    /// The maximum size of the payload of a single OSC 52 sequence, if any.
+    pub max_bytes: Option<usize>,
+
+    /// Whether the content may be split into multiple OSC 52 sequences, which
+    /// the terminal concatenates back.
+    pub chunking: bool,
+
+    /// Whether the terminal can respond to paste requests at all. Terminals
+    /// that can be configured to respond are assumed to be.
+    pub paste: bool,
+
+    /// Whether the terminal accepts copy requests out of the box, rather than
+    /// ignoring them until they are allowed in its configuration, if at all.
+    pub write: bool,
+}
+
+impl Quirks {
+    const DEFAULT: Quirks = Quirks {
+        name: "",
+        max_bytes: None,
+        chunking: false,
+        paste: true,
+        write: true,
+    };
+
+    const ZELLIJ: Quirks = Quirks {
+        name: "Zellij",
+        paste: false,
+        ..Quirks::DEFAULT
+    };
+
+    const KITTY: Quirks = Quirks {
+        name: "kitty",
+        max_bytes: Some(KITTY_CHUNK_SIZE),
+        chunking: true,
+        ..Quirks::DEFAULT
+    };
+
+    const WEZTERM: Quirks = Quirks {
+        name: "WezTerm",
+        paste: false,
+        ..Quirks::DEFAULT
+    };
+
+    const ITERM2: Quirks = Quirks {
+        name: "iTerm2",
+        paste: false,
+        ..Quirks::DEFAULT
+    };
+
+    const VSCODE: Quirks = Quirks {
+        name: "Visual Studio Code",
+        paste: false,
+        ..Quirks::DEFAULT
+    };
+
+    const TERMINAL_APP: Quirks = Quirks {
+        name: "Terminal.app",
+        paste: false,
+        write: false,
+        ..Quirks::DEFAULT
+    };
+
+    const VTE: Quirks = Quirks {
+        name: "VTE",
+        paste: false,
+        write: false,
+        ..Quirks::DEFAULT
+    };
+
+    const XTERM: Quirks = Quirks {
+        name: "xterm",
+        max_bytes: Some(XTERM_MAX_PAYLOAD_SIZE),
+        // SetSelection is among the disallowedWindowOps by default.
+        write: false,
+        ..Quirks::DEFAULT
+    };
+
+    /// Detects the terminal emulator the process is running in by the
+    /// variables it sets in the environment, and returns its quirks if it's
+    /// known to have any. The environment isn't forwarded over SSH, so remote
+    /// processes usually get no quirks.
+    pub fn detect() -> Option<Self> {
+        // Zellij goes first, as it's the one the sequences reach, no matter
+        // what terminal it runs in.
+        if passthrough::is_zellij() {
+            return Some(Quirks::ZELLIJ);
+        }
+        identify::detect_env().and_then(|identity| Quirks::of(&identity))
+    }
+
+    /// Returns the quirks of the terminal emulator, if it's known to have any.
+    pub fn of(identity: &Identity) -> Option<Self> {
+        let quirks = match identity.name.as_str() {
+            "kitty" => Quirks::KITTY,
+            "WezTerm" => Quirks::WEZTERM,
+            "iTerm2" => Quirks::ITERM2,
+            "Visual Studio Code" => Quirks::VSCODE,
+            "Terminal.app" => Quirks::TERMINAL_APP,
+            "VTE" => Quirks::VTE,
+            "xterm" => Quirks::XTERM,
+            _ => return None,
+        };
+        Some(quirks)
+    }
+}
@@ -0,0 +1,204 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+use super::{Backend, Selection};
+
+// The protocol is a single request and a single response per connection. A
+// request is an opcode byte followed by a frame with the Pc parameters of the
+// selections and, for copying, a frame with the content. A response is a status
+// byte followed by a frame with either the pasted content or the error message.
+// A frame is a 32-bit big-endian size followed by that many bytes.
+const REQUEST_COPY: u8 = b'S';
+const REQUEST_PASTE: u8 = b'G';
+const RESPONSE_OK: u8 = 0;
+const RESPONSE_ERROR: u8 = 1;
+
+/// The maximum size of a frame either side accepts, so a bogus size doesn't
+/// make the peer allocate an arbitrary amount of memory. It's twice the
+/// default maximum size of the content to copy.
+const MAX_FRAME_SIZE: usize = 16 << 20;
+
+/// The size of the buffer a frame is read into at first, which grows as the
+/// frame arrives, so a peer can't make the other side allocate the maximum
+/// size without sending it.
+const FRAME_BUFFER_SIZE: usize = 64 << 10;
+
+/// A clipboard request received by the server.
+pub enum Request {
+    Copy(Vec<Selection>, Vec<u8>),
+    Paste(Vec<Selection>),
+}
+
+/// Serves the clipboard requests received on the Unix socket, passing them to
+/// the handler, which returns the pasted content if any. The requests are
+/// handled one at a time, since the terminal can't handle concurrent OSC 52
+/// queries anyway.
+pub fn serve<F>(socket: &Path, handler: F) -> io::Result<()>
+where
+    F: Fn(Request) -> io::Result<Vec<u8>>,
+{
+    let listener = bind(socket)?;
+
+    for stream in listener.incoming() {
+        let result = stream.and_then(|mut stream| {
+            stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+            handle(&mut stream, &handler)
+        });
+        // A misbehaving client must not bring the server down.
+        if let Err(e) = result {
+            eprintln!("ttybox: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// Binds the socket, replacing a stale one left behind by a server that's no
+/// longer running. The socket is accessible by the owner only, as anyone able
+/// to connect can read the clipboard.
+pub fn bind(socket: &Path) -> io::Result<UnixListener> {
+    if UnixStream::connect(socket).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("The socket '{}' is already served.", socket.display()),
+        ));
+    }
+    match fs::remove_file(socket) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    // The socket is created with the permissions umask allows, so umask is
+    // narrowed while binding, as otherwise others could connect before the
+    // permissions are changed.
+    let umask = unsafe { libc::umask(0o077) };
+    let listener = UnixListener::bind(socket);
+    unsafe { libc::umask(umask) };
+    listener
+}
+
+pub fn handle<F>(stream: &mut UnixStream, mut handler: F) -> io::Result<()>
+where
+    F: FnMut(Request) -> io::Result<Vec<u8>>,
+{
+    // Connections closed without a request, e.g. the ones checking whether
+    // the socket is served, are not errors.
+    let mut opcode = [0u8];
+    if stream.read(&mut opcode)? == 0 {
+        return Ok(());
+    }
+
+    let selections = read_frame(stream)?
+        .into_iter()
+        .map(|parameter| {
+            Selection::value_variants()
+                .iter()
+                .find(|selection| selection.parameter() == parameter)
+                .copied()
+                .ok_or_else(|| invalid_data("The request has an unknown selection."))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let request = match opcode[0] {
+        REQUEST_COPY => Request::Copy(selections, read_frame(stream)?),
+        REQUEST_PASTE => Request::Paste(selections),
+        _ => return Err(invalid_data("The request has an unknown opcode.")),
+    };
+
+    match handler(request) {
+        Ok(content) => {
+            stream.write_all(&[RESPONSE_OK])?;
+            write_frame(stream, &content)
+        }
+        Err(e) => {
+            stream.write_all(&[RESPONSE_ERROR])?;
+            write_frame(stream, e.to_string().as_bytes())
+        }
+    }
+}
+
+/// The backend that forwards the requests to a clipboard server listening on
+/// the Unix socket, i.e. to the terminal the server is running in.
+pub struct Remote<'a> {
+    pub socket: &'a Path,
+}
+
+impl Remote<'_> {
+    fn request(&self, opcode: u8, selections: &[Selection], content: &[u8]) -> io::Result<Vec<u8>> {
+        let mut stream = UnixStream::connect(self.socket).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot connect to '{}': {e}", self.socket.display()),
+            )
+        })?;
+
+        let parameters: Vec<u8> = selections.iter().map(Selection::parameter).collect();
+        stream.write_all(&[opcode])?;
+        write_frame(&mut stream, &parameters)?;
+        if opcode == REQUEST_COPY {
+            write_frame(&mut stream, content)?;
+        }
+
+        let mut status = [0u8];
+        stream.read_exact(&mut status)?;
+        let payload = read_frame(&mut stream)?;
+
+        match status[0] {
+            RESPONSE_OK => Ok(payload),
+            _ => Err(io::Error::other(
+                String::from_utf8_lossy(&payload).into_owned(),
+            )),
+        }
+    }
+}
+
+impl Backend for Remote<'_> {
+    fn is_available(&self) -> bool {
+        self.socket.exists()
+    }
+
+    fn copy(&self, content: &[u8], selections: &[Selection]) -> io::Result<()> {
+        self.request(REQUEST_COPY, selections, content)?;
+        Ok(())
+    }
+
+    fn paste(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        self.request(REQUEST_PASTE, selections, b"")
+    }
+}
+
+pub fn read_frame(stream: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut size = [0u8; 4];
+    stream.read_exact(&mut size)?;
+
+    let size = u32::from_be_bytes(size) as usize;
+    if size > MAX_FRAME_SIZE {
+        return Err(invalid_data("The frame exceeds the maximum size."));
+    }
+
+    let mut frame = Vec::with_capacity(size.min(FRAME_BUFFER_SIZE));
+    stream.take(size as u64).read_to_end(&mut frame)?;
+    if frame.len() < size {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(frame)
+}
+
+pub fn write_frame(stream: &mut impl Write, frame: &[u8]) -> io::Result<()> {
+    if frame.len() > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The content exceeds the maximum size of a frame.",
+        ));
+    }
+    stream.write_all(&(frame.len() as u32).to_be_bytes())?;
+    stream.write_all(frame)
+}
+
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
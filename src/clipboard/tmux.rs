@@ -0,0 +1,71 @@
+use std::env;
+use std::io::{self, BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use super::{check_selections, run_clipboard_tool, Backend, Selection};
+
+/// The backend that uses the paste buffers of the tmux server the process is
+/// running in. tmux has no notion of selections, and its most recent paste
+/// buffer acts as the clipboard.
+pub struct Tmux;
+
+impl Backend for Tmux {
+    fn is_available(&self) -> bool {
+        env::var_os("TMUX").is_some()
+    }
+
+    fn copy(&self, content: &[u8], selections: &[Selection]) -> io::Result<()> {
+        check_selections("tmux", selections, &[Selection::Clipboard])?;
+        run_clipboard_tool([("tmux", vec!["load-buffer", "-"])], Some(content))?;
+        Ok(())
+    }
+
+    fn paste(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        check_selections(
+            "tmux",
+            &selections[..selections.len().min(1)],
+            &[Selection::Clipboard],
+        )?;
+        run_clipboard_tool([("tmux", vec!["save-buffer", "-"])], None)
+    }
+}
+
+/// Returns the content of the named paste buffer.
+pub fn save_buffer(name: &str) -> io::Result<Vec<u8>> {
+    run_clipboard_tool([("tmux", vec!["save-buffer", "-b", name, "-"])], None)
+}
+
+/// Attaches to the current tmux session in control mode, and sends the name of
+/// every paste buffer that's created or changed to the returned channel. The
+/// channel is closed once the control client exits, while the control client
+/// exits once the returned child is dropped, as its standard input is closed.
+/// Only tmux 3.4 and later notify control clients of buffer changes.
+pub fn watch_buffers() -> io::Result<(Child, Receiver<String>)> {
+    let mut child = Command::new("tmux")
+        .args(["-C", "attach-session", "-r", "-f", "no-output"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+
+    let stdout = child.stdout.take().expect("the standard output is piped");
+    let (sender, receiver) = mpsc::channel();
+
+    // The notifications are read by a thread of their own, so the buffers can
+    // be waited for with a timeout.
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if let Some(name) = line.strip_prefix("%paste-buffer-changed ") {
+                if sender.send(name.to_owned()).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    Ok((child, receiver))
+}
@@ -0,0 +1,46 @@
+use std::env;
+use std::io;
+
+use super::{run_clipboard_tool, unsupported_selection, Backend, Selection};
+
+/// The backend that relies on the wl-clipboard tools to access the clipboard
+/// of the Wayland compositor.
+pub struct Wayland;
+
+impl Wayland {
+    /// Returns the command line options of wl-clipboard tools for the selection.
+    fn selection_args(selection: &Selection) -> io::Result<Vec<&'static str>> {
+        match selection {
+            Selection::Clipboard => Ok(vec![]),
+            Selection::Primary => Ok(vec!["--primary"]),
+            _ => Err(unsupported_selection("Wayland", selection)),
+        }
+    }
+}
+
+impl Backend for Wayland {
+    fn is_available(&self) -> bool {
+        env::var_os("WAYLAND_DISPLAY").is_some_and(|display| !display.is_empty())
+    }
+
+    fn copy(&self, content: &[u8], selections: &[Selection]) -> io::Result<()> {
+        for selection in selections {
+            let mut args = Wayland::selection_args(selection)?;
+
+            // wl-copy treats empty input as a regular content, and clearing the
+            // selection must be requested explicitly.
+            if content.is_empty() {
+                args.push("--clear");
+            }
+            run_clipboard_tool([("wl-copy", args)], Some(content))?;
+        }
+        Ok(())
+    }
+
+    fn paste(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        let mut args =
+            Wayland::selection_args(selections.first().unwrap_or(&Selection::Clipboard))?;
+        args.push("--no-newline");
+        run_clipboard_tool([("wl-paste", args)], None)
+    }
+}
@@ -0,0 +1,51 @@
+use std::env;
+use std::io;
+
+use super::{run_clipboard_tool, unsupported_selection, Backend, Selection};
+
+/// The backend that relies on the xclip or xsel tools to access the clipboard
+/// of the X11 display.
+pub struct X11;
+
+impl X11 {
+    /// Returns the name of the selection as known to X11 clipboard tools.
+    fn selection_name(selection: &Selection) -> io::Result<&'static str> {
+        match selection {
+            Selection::Clipboard => Ok("clipboard"),
+            Selection::Primary => Ok("primary"),
+            Selection::Secondary => Ok("secondary"),
+            _ => Err(unsupported_selection("X11", selection)),
+        }
+    }
+}
+
+impl Backend for X11 {
+    fn is_available(&self) -> bool {
+        env::var_os("DISPLAY").is_some_and(|display| !display.is_empty())
+    }
+
+    fn copy(&self, content: &[u8], selections: &[Selection]) -> io::Result<()> {
+        for selection in selections {
+            let name = X11::selection_name(selection)?;
+            run_clipboard_tool(
+                [
+                    ("xclip", vec!["-selection", name, "-in"]),
+                    ("xsel", vec![&format!("--{name}"), "--input"]),
+                ],
+                Some(content),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn paste(&self, selections: &[Selection]) -> io::Result<Vec<u8>> {
+        let name = X11::selection_name(selections.first().unwrap_or(&Selection::Clipboard))?;
+        run_clipboard_tool(
+            [
+                ("xclip", vec!["-selection", name, "-out"]),
+                ("xsel", vec![&format!("--{name}"), "--output"]),
+            ],
+            None,
+        )
+    }
+}
@@ -0,0 +1,567 @@
+mod convert;
+mod simulate;
+
+use std::ffi::OsString;
+use std::io;
+use std::process;
+use std::time::Duration;
+
+use clap::{Args, Subcommand, ValueEnum};
+
+use simulate::Deficiency;
+
+use crate::error::{self, Failure};
+use crate::query;
+use crate::terminal::{Terminal, TimeoutArgs};
+use crate::tty::{self, DEFAULT_TIMEOUT};
+
+/// The exit code of `contrast` for a ratio below the minimum, so it's not
+/// confused with the failures.
+const EXIT_LOW_CONTRAST: i32 = 10;
+
+#[derive(Subcommand, Debug)]
+pub enum ColorCommands {
+    /// Print the color of the terminal, as reported by the terminal, e.g. to
+    /// tell whether its background is dark or light.
+    Get(ColorGetArgs),
+    /// Change the color of the terminal, e.g. to tell the production machines
+    /// apart.
+    Set(ColorSetArgs),
+    /// Query and change the colors of the 256-color palette, by means of OSC
+    /// 4.
+    #[command(subcommand)]
+    Palette(PaletteCommands),
+    /// Query and change the color of the cursor, same as with "get cursor" and
+    /// "set cursor", e.g. to change it depending on the mode of an editor.
+    #[command(subcommand)]
+    Cursor(CursorCommands),
+    /// Reset the colors to the ones the terminal is configured with. If no
+    /// colors are given, all of them are reset.
+    Reset(ColorResetArgs),
+    /// Convert the color, e.g. to the nearest color of the 256-color palette
+    /// for the terminals without true color.
+    Convert(ColorConvertArgs),
+    /// Print the contrast ratio of the color against the background of the
+    /// terminal, as defined by WCAG, e.g. to tell whether text of the color
+    /// is readable. The exit code is 0 if the ratio is at least the minimum,
+    /// and 10 otherwise.
+    Contrast(ColorContrastArgs),
+    /// Copy the standard input to the standard output with the true colors
+    /// and the 256-color palette colors of the SGR sequences as seen with a
+    /// color vision deficiency, e.g. to check the output of a program for
+    /// accessibility. The 16 ANSI colors are left as they are, as they depend
+    /// on the terminal's theme.
+    Simulate(ColorSimulateArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CursorCommands {
+    /// Print the color of the cursor.
+    Get(CursorGetArgs),
+    /// Change the color of the cursor.
+    Set(CursorSetArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PaletteCommands {
+    /// Print the colors of the palette slots, one "SLOT COLOR" per line.
+    Get(PaletteGetArgs),
+    /// Change the colors of the palette slots.
+    Set(PaletteSetArgs),
+}
+
+/// The colors of the terminal that can be queried and changed, by means of
+/// the dynamic colors OSC sequences.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// The default foreground color, OSC 10.
+    #[value(alias = "foreground")]
+    Fg,
+    /// The default background color, OSC 11.
+    #[value(alias = "background")]
+    Bg,
+    /// The color of the cursor, OSC 12.
+    Cursor,
+}
+
+impl Target {
+    fn code(&self) -> u8 {
+        match self {
+            Target::Fg => 10,
+            Target::Bg => 11,
+            Target::Cursor => 12,
+        }
+    }
+
+    /// The sequence resetting the color to the one the terminal is configured
+    /// with.
+    pub fn reset_sequence(&self) -> Vec<u8> {
+        format!("\x1B]{}\x1B\\", 100 + self.code()).into_bytes()
+    }
+}
+
+/// The formats colors are printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// "#rrggbb".
+    Hex,
+    /// "rgb:rrrr/gggg/bbbb", as used by X11 and reported by terminals.
+    Rgb,
+    /// {"red": 0-255, "green": 0-255, "blue": 0-255}.
+    Json,
+}
+
+/// What colors are converted to.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConvertTarget {
+    /// The nearest slot of the 256-color palette, from 16 on, as the colors
+    /// of those slots are the same in most terminals.
+    #[value(name = "256")]
+    Palette,
+    /// The nearest slot of the 16 ANSI colors, as of the xterm defaults.
+    #[value(name = "16")]
+    Ansi,
+    /// "#rrggbb".
+    Hex,
+    /// "rgb:rrrr/gggg/bbbb".
+    Rgb,
+    /// "hsl(H, S%, L%)".
+    Hsl,
+}
+
+#[derive(Args, Debug)]
+pub struct ColorGetArgs {
+    /// The color to print.
+    target: Target,
+
+    /// The format to print the color in.
+    #[arg(long, value_enum, default_value_t = Format::Hex)]
+    format: Format,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ColorSetArgs {
+    /// The color to change.
+    target: Target,
+
+    /// The color to change to, either "#rgb", "#rrggbb", "#rrrrggggbbbb", or
+    /// "rgb:r/g/b" with 1 to 4 hex digits per channel.
+    #[arg(value_parser = parse_color)]
+    color: Color,
+
+    /// Run the command with the color changed, and restore the color once it
+    /// exits, however it exits. The exit code is the one of the command.
+    #[arg(long, default_value_t = false, requires = "command")]
+    restore_on_exit: bool,
+
+    /// The command to run, along with its arguments.
+    #[arg(
+        trailing_var_arg = true,
+        value_name = "COMMAND",
+        requires = "restore_on_exit"
+    )]
+    command: Vec<OsString>,
+}
+
+#[derive(Args, Debug)]
+pub struct CursorGetArgs {
+    /// The format to print the color in.
+    #[arg(long, value_enum, default_value_t = Format::Hex)]
+    format: Format,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct CursorSetArgs {
+    /// The color to change to, in any of the formats "set" accepts.
+    #[arg(value_parser = parse_color)]
+    color: Color,
+}
+
+#[derive(Args, Debug)]
+pub struct PaletteGetArgs {
+    /// The palette slots to print, from 0 to 255.
+    #[arg(required = true, value_name = "SLOT")]
+    slots: Vec<u8>,
+
+    /// The format to print the colors in.
+    #[arg(long, value_enum, default_value_t = Format::Hex)]
+    format: Format,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct PaletteSetArgs {
+    /// The palette slots to change along with the colors to change them to,
+    /// e.g. "1=#ff5555".
+    #[arg(required = true, value_name = "SLOT=COLOR", value_parser = parse_slot)]
+    slots: Vec<(u8, Color)>,
+}
+
+#[derive(Args, Debug)]
+pub struct ColorResetArgs {
+    /// Reset the palette, OSC 104.
+    #[arg(long, default_value_t = false)]
+    palette: bool,
+
+    /// Reset the foreground color, OSC 110.
+    #[arg(long, default_value_t = false)]
+    fg: bool,
+
+    /// Reset the background color, OSC 111.
+    #[arg(long, default_value_t = false)]
+    bg: bool,
+
+    /// Reset the cursor color, OSC 112.
+    #[arg(long, default_value_t = false)]
+    cursor: bool,
+
+    /// Reset all the colors.
+    #[arg(long, default_value_t = false)]
+    all: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ColorConvertArgs {
+    /// The color to convert, either in any of the formats "set" accepts, a
+    /// slot of the 256-color palette with the xterm default colors, or a CSS
+    /// color name such as "orange".
+    #[arg(value_parser = parse_any_color)]
+    color: Color,
+
+    /// What to convert the color to.
+    #[arg(long, value_enum, default_value_t = ConvertTarget::Hex)]
+    to: ConvertTarget,
+
+    /// Print a swatch of the converted color next to it.
+    #[arg(long, default_value_t = false)]
+    swatch: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ColorContrastArgs {
+    /// The color to check, in any of the formats "convert" accepts.
+    #[arg(value_parser = parse_any_color)]
+    color: Color,
+
+    /// The minimum contrast ratio to pass, e.g. 4.5 for normal text and 3 for
+    /// large text to meet WCAG AA, or 7 and 4.5 to meet WCAG AAA.
+    #[arg(long, value_name = "RATIO", default_value_t = 4.5)]
+    min: f64,
+
+    /// The background to check against instead of the terminal's, in any of
+    /// the formats "convert" accepts.
+    #[arg(long, value_name = "COLOR", value_parser = parse_any_color)]
+    background: Option<Color>,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ColorSimulateArgs {
+    /// The color vision deficiency to simulate.
+    #[arg(long, value_enum)]
+    mode: Deficiency,
+}
+
+/// Parses the palette slot along with its color.
+fn parse_slot(value: &str) -> Result<(u8, Color), String> {
+    let (slot, color) = value
+        .split_once('=')
+        .ok_or_else(|| format!("invalid palette slot '{value}', expected SLOT=COLOR"))?;
+    let slot = slot
+        .parse()
+        .map_err(|_| format!("invalid palette slot '{slot}'"))?;
+    Ok((slot, parse_color(color)?))
+}
+
+/// Parses the color in any of the formats terminals accept.
+pub fn parse_color(value: &str) -> Result<Color, String> {
+    let color = match value.strip_prefix('#') {
+        Some(hex)
+            if [3, 6, 12].contains(&hex.len()) && hex.bytes().all(|b| b.is_ascii_hexdigit()) =>
+        {
+            let width = hex.len() / 3;
+            Color::parse(&format!(
+                "rgb:{}/{}/{}",
+                &hex[..width],
+                &hex[width..2 * width],
+                &hex[2 * width..]
+            ))
+        }
+        Some(_) => None,
+        None => Color::parse(value),
+    };
+    color.ok_or_else(|| format!("invalid color '{value}'"))
+}
+
+/// Parses the color in any of the formats terminals accept, or as a palette
+/// slot or a name.
+fn parse_any_color(value: &str) -> Result<Color, String> {
+    if let Ok(slot) = value.parse() {
+        return Ok(convert::slot_color(slot));
+    }
+    convert::named_color(value).map_or_else(|| parse_color(value), Ok)
+}
+
+pub fn execute(command: ColorCommands, terminal: &Terminal) -> io::Result<()> {
+    match command {
+        ColorCommands::Get(args) => execute_get(args, terminal),
+        ColorCommands::Set(args) => execute_set(args, terminal),
+        ColorCommands::Palette(PaletteCommands::Get(args)) => execute_palette_get(args, terminal),
+        ColorCommands::Palette(PaletteCommands::Set(args)) => execute_palette_set(args, terminal),
+        ColorCommands::Cursor(CursorCommands::Get(args)) => execute_get(
+            ColorGetArgs {
+                target: Target::Cursor,
+                format: args.format,
+                timeout: args.timeout,
+            },
+            terminal,
+        ),
+        ColorCommands::Cursor(CursorCommands::Set(args)) => {
+            terminal.send([args.color.sequence(Target::Cursor)])
+        }
+        ColorCommands::Reset(args) => execute_reset(args, terminal),
+        ColorCommands::Convert(args) => execute_convert(args),
+        ColorCommands::Contrast(args) => execute_contrast(args, terminal),
+        ColorCommands::Simulate(args) => simulate::filter(args.mode),
+    }
+}
+
+fn execute_get(args: ColorGetArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    let color = query_color(args.target, timeout, terminal)?;
+    println!("{}", color.format(args.format));
+    Ok(())
+}
+
+fn execute_palette_get(args: PaletteGetArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    // All the slots are queried at once, and each one is reported on its own
+    // as `ESC ] 4 ; SLOT ; rgb:RRRR/GGGG/BBBB ST`.
+    let mut request = String::from("\x1B]4");
+    for slot in &args.slots {
+        request.push_str(&format!(";{slot};?"));
+    }
+    request.push_str("\x1B\\");
+    let response = query::query(terminal, request.as_bytes(), timeout)?;
+
+    for slot in args.slots {
+        let report =
+            query::find_osc(&response, format!("\x1B]4;{slot};").as_bytes()).ok_or_else(|| {
+                error::new(
+                    Failure::Unsupported,
+                    format!("The terminal emulator doesn't report the palette slot {slot}."),
+                )
+            })?;
+        println!("{slot} {}", parse_report(report)?.format(args.format));
+    }
+    Ok(())
+}
+
+fn execute_palette_set(args: PaletteSetArgs, terminal: &Terminal) -> io::Result<()> {
+    let mut sequence = String::from("\x1B]4");
+    for (slot, color) in args.slots {
+        sequence.push_str(&format!(";{slot};{}", color.format(Format::Rgb)));
+    }
+    sequence.push_str("\x1B\\");
+    terminal.send([sequence.into_bytes()])
+}
+
+fn execute_reset(args: ColorResetArgs, terminal: &Terminal) -> io::Result<()> {
+    let all = args.all || !(args.palette || args.fg || args.bg || args.cursor);
+    let sequences = [
+        (args.palette, 104),
+        (args.fg, 110),
+        (args.bg, 111),
+        (args.cursor, 112),
+    ]
+    .into_iter()
+    .filter(|(reset, _)| all || *reset)
+    .map(|(_, code)| format!("\x1B]{code}\x1B\\").into_bytes());
+    terminal.send(sequences)
+}
+
+fn execute_convert(args: ColorConvertArgs) -> io::Result<()> {
+    let (value, sgr) = match args.to {
+        ConvertTarget::Palette | ConvertTarget::Ansi => {
+            let slots = match args.to {
+                ConvertTarget::Palette => 16..=255,
+                _ => 0..=15,
+            };
+            let slot = convert::nearest_slot(args.color, slots);
+            (slot.to_string(), format!("5;{slot}"))
+        }
+        target => {
+            let value = match target {
+                ConvertTarget::Rgb => args.color.format(Format::Rgb),
+                ConvertTarget::Hsl => {
+                    let [hue, saturation, lightness] = convert::hsl(args.color);
+                    format!("hsl({hue:.0}, {saturation:.0}%, {lightness:.0}%)")
+                }
+                _ => args.color.format(Format::Hex),
+            };
+            let [red, green, blue] = args.color.rgb();
+            (value, format!("2;{red};{green};{blue}"))
+        }
+    };
+
+    // The swatch of a palette slot is drawn with the slot, so it shows the
+    // color the terminal actually has there.
+    if args.swatch {
+        println!("{value} \x1B[48;{sgr}m      \x1B[0m");
+    } else {
+        println!("{value}");
+    }
+    Ok(())
+}
+
+fn execute_contrast(args: ColorContrastArgs, terminal: &Terminal) -> io::Result<()> {
+    let background = match args.background {
+        Some(background) => background,
+        None => {
+            let timeout = args.timeout.duration();
+            query_color(Target::Bg, timeout, terminal)?
+        }
+    };
+
+    let ratio = args.color.contrast(background);
+    println!("{ratio:.2}");
+    if ratio < args.min {
+        process::exit(EXIT_LOW_CONTRAST);
+    }
+    Ok(())
+}
+
+/// Returns the color of the terminal, as reported by the terminal.
+pub fn query_color(target: Target, timeout: Duration, terminal: &Terminal) -> io::Result<Color> {
+    // The report looks like `ESC ] 11 ; rgb:RRRR/GGGG/BBBB ST`.
+    let code = target.code();
+    let request = format!("\x1B]{code};?\x1B\\");
+    let response = query::query(terminal, request.as_bytes(), timeout)?;
+    let report =
+        query::find_osc(&response, format!("\x1B]{code};").as_bytes()).ok_or_else(|| {
+            error::new(
+                Failure::Unsupported,
+                "The terminal emulator doesn't report its colors.",
+            )
+        })?;
+    parse_report(report)
+}
+
+/// Parses the color reported by the terminal.
+pub fn parse_report(report: &[u8]) -> io::Result<Color> {
+    Color::parse(&String::from_utf8_lossy(report)).ok_or_else(|| {
+        error::new(
+            Failure::Protocol,
+            format!(
+                "Malformed color report: {}.",
+                String::from_utf8_lossy(report).escape_debug()
+            ),
+        )
+    })
+}
+
+fn execute_set(args: ColorSetArgs, terminal: &Terminal) -> io::Result<()> {
+    if !args.restore_on_exit {
+        return terminal.send([args.color.sequence(args.target)]);
+    }
+
+    // The color is restored to the one in use, if the terminal reports it, as
+    // it may differ from the configured one, e.g. if set by a theme switcher.
+    let restore = match query_color(args.target, DEFAULT_TIMEOUT, terminal) {
+        Ok(color) => color.sequence(args.target),
+        Err(e) if error::failure(&e) == Some(Failure::Unsupported) => args.target.reset_sequence(),
+        Err(e) => return Err(e),
+    };
+    terminal.send([args.color.sequence(args.target)])?;
+
+    let status = tty::run_then(&args.command, || terminal.send([restore]))?;
+    process::exit(tty::exit_code(status))
+}
+
+/// A color, with 16 bits per channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Color([u16; 3]);
+
+impl Color {
+    /// Parses the color in the "rgb:R/G/B" format, where every channel is
+    /// given by 1 to 4 hex digits, as reported by terminals.
+    fn parse(value: &str) -> Option<Self> {
+        let mut channels = [0u16; 3];
+        let mut parts = value.strip_prefix("rgb:")?.split('/');
+        for channel in &mut channels {
+            let part = parts.next()?;
+            if part.is_empty()
+                || part.len() > 4
+                || !part.bytes().all(|byte| byte.is_ascii_hexdigit())
+            {
+                return None;
+            }
+            // The channels are scaled, e.g. "f" stands for "ffff".
+            let max = (1u32 << (4 * part.len())) - 1;
+            let value = u32::from_str_radix(part, 16).ok()?;
+            *channel = (value * 0xFFFF / max) as u16;
+        }
+        parts.next().is_none().then_some(Color(channels))
+    }
+
+    /// Returns the sequence changing the color of the target to this one.
+    pub fn sequence(self, target: Target) -> Vec<u8> {
+        format!("\x1B]{};{}\x1B\\", target.code(), self.format(Format::Rgb)).into_bytes()
+    }
+
+    /// Returns the relative luminance of the color, as defined by WCAG, from
+    /// 0 for black to 1 for white.
+    pub fn luminance(self) -> f64 {
+        let [red, green, blue] = self.0.map(|channel| {
+            // The channels are gamma-encoded sRGB values.
+            let value = f64::from(channel) / f64::from(u16::MAX);
+            if value <= 0.04045 {
+                value / 12.92
+            } else {
+                ((value + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        0.2126 * red + 0.7152 * green + 0.0722 * blue
+    }
+
+    /// Returns the contrast ratio of the colors, as defined by WCAG, from 1
+    /// for the same colors to 21 for black and white.
+    pub fn contrast(self, other: Color) -> f64 {
+        let (lighter, darker) = match (self.luminance(), other.luminance()) {
+            (a, b) if a >= b => (a, b),
+            (a, b) => (b, a),
+        };
+        (lighter + 0.05) / (darker + 0.05)
+    }
+
+    /// Returns the color of the 8-bit channels.
+    pub fn from_rgb(rgb: [u8; 3]) -> Self {
+        Color(rgb.map(|channel| u16::from(channel) * 0x101))
+    }
+
+    /// Returns the channels of the color, cut to 8 bits.
+    pub fn rgb(self) -> [u8; 3] {
+        self.0.map(|channel| (channel >> 8) as u8)
+    }
+
+    pub fn format(self, format: Format) -> String {
+        let [red, green, blue] = self.rgb();
+        match format {
+            Format::Hex => format!("#{red:02x}{green:02x}{blue:02x}"),
+            Format::Rgb => format!("rgb:{:04x}/{:04x}/{:04x}", self.0[0], self.0[1], self.0[2]),
+            Format::Json => format!(r#"{{"red": {red}, "green": {green}, "blue": {blue}}}"#),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::ops::RangeInclusive;
+
+use super::Color;
+
+/// The default colors of the 16 ANSI palette slots, as of xterm.
+const ANSI_COLORS: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+
+/// The levels of the channels of the 6x6x6 color cube, the palette slots 16
+/// to 231.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The named colors of CSS 2.
+const NAMED_COLORS: [(&str, [u8; 3]); 17] = [
+    ("black", [0x00, 0x00, 0x00]),
+    ("silver", [0xc0, 0xc0, 0xc0]),
+    ("gray", [0x80, 0x80, 0x80]),
+    ("white", [0xff, 0xff, 0xff]),
+    ("maroon", [0x80, 0x00, 0x00]),
+    ("red", [0xff, 0x00, 0x00]),
+    ("purple", [0x80, 0x00, 0x80]),
+    ("fuchsia", [0xff, 0x00, 0xff]),
+    ("green", [0x00, 0x80, 0x00]),
+    ("lime", [0x00, 0xff, 0x00]),
+    ("olive", [0x80, 0x80, 0x00]),
+    ("yellow", [0xff, 0xff, 0x00]),
+    ("navy", [0x00, 0x00, 0x80]),
+    ("blue", [0x00, 0x00, 0xff]),
+    ("teal", [0x00, 0x80, 0x80]),
+    ("aqua", [0x00, 0xff, 0xff]),
+    ("orange", [0xff, 0xa5, 0x00]),
+];
+
+/// Returns the default color of the palette slot, as of xterm.
+pub fn slot_color(slot: u8) -> Color {
+    let rgb = match slot {
+        0..=15 => ANSI_COLORS[slot as usize],
+        16..=231 => {
+            let index = slot - 16;
+            [index / 36, index / 6 % 6, index % 6].map(|level| CUBE_LEVELS[level as usize])
+        }
+        232..=255 => [8 + 10 * (slot - 232); 3],
+    };
+    Color::from_rgb(rgb)
+}
+
+/// Returns the palette slot of the range whose default color is the nearest
+/// to the color.
+pub fn nearest_slot(color: Color, slots: RangeInclusive<u8>) -> u8 {
+    let rgb = color.rgb();
+    slots
+        .min_by_key(|slot| {
+            let other = slot_color(*slot).rgb();
+            (0..3)
+                .map(|channel| (i32::from(rgb[channel]) - i32::from(other[channel])).pow(2))
+                .sum::<i32>()
+        })
+        .expect("the range isn't empty")
+}
+
+/// Returns the color of the name, as known to CSS 2.
+pub fn named_color(name: &str) -> Option<Color> {
+    NAMED_COLORS
+        .iter()
+        .find(|(other, _)| other.eq_ignore_ascii_case(name))
+        .map(|(_, rgb)| Color::from_rgb(*rgb))
+}
+
+/// Returns the hue in degrees, and the saturation and the lightness in
+/// percents, of the color.
+pub fn hsl(color: Color) -> [f64; 3] {
+    let [red, green, blue] = color.rgb().map(|channel| f64::from(channel) / 255.0);
+    let max = red.max(green).max(blue);
+    let min = red.min(green).min(blue);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return [0.0, 0.0, lightness * 100.0];
+    }
+
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == red {
+        ((green - blue) / delta).rem_euclid(6.0)
+    } else if max == green {
+        (blue - red) / delta + 2.0
+    } else {
+        (red - green) / delta + 4.0
+    };
+    [hue * 60.0, saturation * 100.0, lightness * 100.0]
+}
@@ -0,0 +1,208 @@
+use std::io::{self, Read, Write};
+
+use clap::ValueEnum;
+
+use super::convert;
+
+/// The color vision deficiencies colors are simulated for.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Deficiency {
+    /// No red cones.
+    Protanopia,
+    /// No green cones.
+    Deuteranopia,
+    /// No blue cones.
+    Tritanopia,
+    /// No color vision at all.
+    Achromatopsia,
+}
+
+impl Deficiency {
+    /// The matrix simulating the deficiency in linear RGB, as by Machado et
+    /// al., 2009, at full severity.
+    fn matrix(self) -> [[f64; 3]; 3] {
+        match self {
+            Deficiency::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            Deficiency::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            Deficiency::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+            Deficiency::Achromatopsia => [[0.2126, 0.7152, 0.0722]; 3],
+        }
+    }
+
+    /// Returns the color as seen with the deficiency.
+    pub fn simulate(self, rgb: [u8; 3]) -> [u8; 3] {
+        let linear = rgb.map(|channel| {
+            let value = f64::from(channel) / 255.0;
+            if value <= 0.04045 {
+                value / 12.92
+            } else {
+                ((value + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        self.matrix().map(|row| {
+            let value = (0..3)
+                .map(|channel| row[channel] * linear[channel])
+                .sum::<f64>()
+                .clamp(0.0, 1.0);
+            let value = if value <= 0.0031308 {
+                value * 12.92
+            } else {
+                1.055 * value.powf(1.0 / 2.4) - 0.055
+            };
+            (value * 255.0).round() as u8
+        })
+    }
+}
+
+/// Copies the standard input to the standard output, with the colors of the
+/// SGR sequences as seen with the deficiency. The colors of the palette slots
+/// 0 to 15 are left as they are, as they depend on the terminal's theme.
+pub fn filter(deficiency: Deficiency) -> io::Result<()> {
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut buffer = [0u8; 8192];
+    let mut pending = Vec::new();
+
+    loop {
+        let size = match stdin.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pending.extend_from_slice(&buffer[..size]);
+
+        let mut output = Vec::with_capacity(pending.len());
+        let mut start = 0;
+        while let Some(offset) = pending[start..].iter().position(|byte| *byte == 0x1B) {
+            let escape = start + offset;
+            output.extend_from_slice(&pending[start..escape]);
+            match csi_length(&pending[escape..]) {
+                // The sequence ends in the input yet to be read.
+                None => {
+                    start = escape;
+                    break;
+                }
+                Some(length) => {
+                    let sequence = &pending[escape..escape + length];
+                    match sequence
+                        .strip_prefix(b"\x1B[")
+                        .and_then(|s| s.strip_suffix(b"m"))
+                    {
+                        Some(parameters) => {
+                            output.extend_from_slice(b"\x1B[");
+                            output.extend(rewrite(parameters, deficiency));
+                            output.push(b'm');
+                        }
+                        None => output.extend_from_slice(sequence),
+                    }
+                    start = escape + length;
+                }
+            }
+        }
+        if !pending[start..].contains(&0x1B) {
+            output.extend_from_slice(&pending[start..]);
+            start = pending.len();
+        }
+        pending.drain(..start);
+
+        stdout.write_all(&output)?;
+        stdout.flush()?;
+    }
+
+    stdout.write_all(&pending)?;
+    stdout.flush()
+}
+
+/// Returns the length of the escape sequence at the start of the bytes, or
+/// none if it's incomplete. Anything but CSI sequences is 1 byte long, i.e.
+/// just the escape is skipped.
+fn csi_length(bytes: &[u8]) -> Option<usize> {
+    match bytes.get(1) {
+        None => None,
+        Some(b'[') => bytes[2..]
+            .iter()
+            .position(|byte| (0x40..=0x7E).contains(byte))
+            .map(|end| end + 3),
+        Some(_) => Some(1),
+    }
+}
+
+/// Returns the SGR parameters with the colors as seen with the deficiency.
+fn rewrite(parameters: &[u8], deficiency: Deficiency) -> Vec<u8> {
+    let parameters = String::from_utf8_lossy(parameters);
+    let mut parameters = parameters.split(';').map(str::to_owned).collect::<Vec<_>>();
+
+    let mut index = 0;
+    while index < parameters.len() {
+        let parameter = &parameters[index];
+        // The colors are given either by parameters of their own, e.g.
+        // `38;2;R;G;B` and `38;5;N`, or by subparameters, e.g. `38:2::R:G:B`.
+        if parameter.contains(':') {
+            let mut subparameters = parameter.split(':').collect::<Vec<_>>();
+            let color = match subparameters[..] {
+                [_, "2", _, red, green, blue] | [_, "2", red, green, blue] => {
+                    parse_rgb(red, green, blue)
+                }
+                [_, "5", slot] => parse_slot(slot),
+                _ => None,
+            };
+            if let (Some(rgb), "38" | "48" | "58") = (color, subparameters[0]) {
+                let [red, green, blue] = deficiency.simulate(rgb);
+                let (red, green, blue) = (red.to_string(), green.to_string(), blue.to_string());
+                subparameters.truncate(1);
+                subparameters.extend(["2", "", &red, &green, &blue]);
+                parameters[index] = subparameters.join(":");
+            }
+            index += 1;
+            continue;
+        }
+
+        if !matches!(parameter.as_str(), "38" | "48" | "58") {
+            index += 1;
+            continue;
+        }
+        let rest = &parameters[index + 1..];
+        let (color, length) = match rest.first().map(String::as_str) {
+            Some("2") if rest.len() >= 4 => (parse_rgb(&rest[1], &rest[2], &rest[3]), 5),
+            Some("5") if rest.len() >= 2 => (parse_slot(&rest[1]), 3),
+            _ => (None, 1),
+        };
+        if let Some(rgb) = color {
+            let [red, green, blue] = deficiency.simulate(rgb);
+            let color = [
+                "2".to_owned(),
+                red.to_string(),
+                green.to_string(),
+                blue.to_string(),
+            ];
+            parameters.splice(index + 1..index + length, color);
+            index += 5;
+        } else {
+            index += length;
+        }
+    }
+    parameters.join(";").into_bytes()
+}
+
+fn parse_rgb(red: &str, green: &str, blue: &str) -> Option<[u8; 3]> {
+    Some([red.parse().ok()?, green.parse().ok()?, blue.parse().ok()?])
+}
+
+/// Returns the color of the palette slot, unless it depends on the theme.
+fn parse_slot(slot: &str) -> Option<[u8; 3]> {
+    let slot: u8 = slot.parse().ok()?;
+    (slot >= 16).then(|| convert::slot_color(slot).rgb())
+}
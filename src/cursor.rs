@@ -0,0 +1,263 @@
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::thread;
+use std::time::Duration;
+
+use clap::{Args, Subcommand, ValueEnum};
+
+use crate::color::{self, Color, Target};
+use crate::error::{self, Failure};
+use crate::query;
+use crate::terminal::{Terminal, TimeoutArgs};
+use crate::tty::{self, DEFAULT_TIMEOUT};
+
+/// The request to report the cursor position, DSR 6.
+const POSITION_REQUEST: &[u8] = b"\x1B[6n";
+
+/// The requests to report the cursor color, OSC 12, and the cursor shape,
+/// DECRQSS for DECSCUSR.
+const STYLE_REQUEST: &[u8] = b"\x1B]12;?\x1B\\\x1BP$q q\x1B\\";
+
+#[derive(Subcommand, Debug)]
+pub enum CursorCommands {
+    /// Print the position of the cursor, as reported by the terminal, as "ROW
+    /// COLUMN" counted from 1.
+    Pos(CursorPosArgs),
+    /// Change the shape of the cursor by means of DECSCUSR, e.g. to tell the
+    /// modes of an editor or a shell apart.
+    Shape(CursorShapeArgs),
+    /// Hide the cursor by means of DECTCEM.
+    Hide,
+    /// Show the cursor by means of DECTCEM.
+    Show,
+    /// Save the position and the attributes of the cursor by means of DECSC.
+    Save,
+    /// Restore the position and the attributes of the cursor saved by "save",
+    /// by means of DECRC.
+    Restore,
+    /// Move the cursor to the row and the column, counted from 1.
+    Move(CursorMoveArgs),
+    /// Move the cursor up by the number of rows.
+    Up(CursorStepArgs),
+    /// Move the cursor down by the number of rows.
+    Down(CursorStepArgs),
+    /// Move the cursor left by the number of columns.
+    Left(CursorStepArgs),
+    /// Move the cursor right by the number of columns.
+    Right(CursorStepArgs),
+    /// Flash the cursor a few times, so it's easy to find on a large screen.
+    /// The color and the shape of the cursor are restored afterwards.
+    Beacon(CursorBeaconArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CursorBeaconArgs {
+    /// The color to flash the cursor in, in any of the formats "color set"
+    /// accepts.
+    #[arg(long, default_value = "#ff0000", value_parser = color::parse_color)]
+    color: Color,
+
+    /// The number of flashes.
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    count: u32,
+
+    /// The time the cursor is flashed for, and the time between the flashes,
+    /// in milliseconds.
+    #[arg(long, value_name = "MS", default_value_t = 150)]
+    interval: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct CursorMoveArgs {
+    /// The row to move to, counted from 1.
+    #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+    row: u16,
+
+    /// The column to move to, counted from 1.
+    #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+    column: u16,
+}
+
+#[derive(Args, Debug)]
+pub struct CursorStepArgs {
+    /// The number of rows or columns to move by. The cursor stops at the edge
+    /// of the screen.
+    #[arg(default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    count: u16,
+}
+
+/// The shapes of the cursor.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Block,
+    Underline,
+    Bar,
+}
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct CursorShapeArgs {
+    #[command(subcommand)]
+    command: Option<ShapeCommands>,
+
+    /// The shape to change to.
+    #[arg(required = true)]
+    shape: Option<Shape>,
+
+    /// Make the cursor blink, instead of being steady.
+    #[arg(long, default_value_t = false)]
+    blink: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ShapeCommands {
+    /// Reset the shape of the cursor to the one the terminal is configured
+    /// with.
+    Reset,
+}
+
+/// The formats the cursor position is printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PosFormat {
+    /// "ROW COLUMN".
+    Text,
+    /// {"row": ROW, "column": COLUMN}.
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct CursorPosArgs {
+    /// Count the rows and the columns from 0 instead of 1.
+    #[arg(long, default_value_t = false)]
+    zero_based: bool,
+
+    /// The format to print the position in.
+    #[arg(long, value_enum, default_value_t = PosFormat::Text)]
+    format: PosFormat,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(command: CursorCommands, terminal: &Terminal) -> io::Result<()> {
+    match command {
+        CursorCommands::Pos(args) => execute_pos(args, terminal),
+        CursorCommands::Shape(args) => execute_shape(args, terminal),
+        CursorCommands::Hide => terminal.send([b"\x1B[?25l".to_vec()]),
+        CursorCommands::Show => terminal.send([b"\x1B[?25h".to_vec()]),
+        CursorCommands::Save => terminal.send([b"\x1B7".to_vec()]),
+        CursorCommands::Restore => terminal.send([b"\x1B8".to_vec()]),
+        CursorCommands::Move(args) => {
+            terminal.send([format!("\x1B[{};{}H", args.row, args.column).into_bytes()])
+        }
+        CursorCommands::Beacon(args) => execute_beacon(args, terminal),
+        CursorCommands::Up(args) => terminal.send([format!("\x1B[{}A", args.count).into_bytes()]),
+        CursorCommands::Down(args) => terminal.send([format!("\x1B[{}B", args.count).into_bytes()]),
+        CursorCommands::Right(args) => {
+            terminal.send([format!("\x1B[{}C", args.count).into_bytes()])
+        }
+        CursorCommands::Left(args) => terminal.send([format!("\x1B[{}D", args.count).into_bytes()]),
+    }
+}
+
+fn execute_pos(args: CursorPosArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    let [row, column] = query_position(timeout, terminal)?;
+    let offset = u32::from(args.zero_based);
+    let (row, column) = (row - offset, column - offset);
+    match args.format {
+        PosFormat::Text => println!("{row} {column}"),
+        PosFormat::Json => println!(r#"{{"row": {row}, "column": {column}}}"#),
+    }
+    Ok(())
+}
+
+fn execute_shape(args: CursorShapeArgs, terminal: &Terminal) -> io::Result<()> {
+    // The shapes are numbered from 1 for a blinking block on, with the steady
+    // one following the blinking one, and 0 standing for the default.
+    let code = match (args.command, args.shape) {
+        (Some(ShapeCommands::Reset), _) => 0,
+        (None, shape) => {
+            let code = match shape.expect("the shape is required") {
+                Shape::Block => 1,
+                Shape::Underline => 3,
+                Shape::Bar => 5,
+            };
+            if args.blink {
+                code
+            } else {
+                code + 1
+            }
+        }
+    };
+    terminal.send([format!("\x1B[{code} q").into_bytes()])
+}
+
+fn execute_beacon(args: CursorBeaconArgs, terminal: &Terminal) -> io::Result<()> {
+    // The cursor is restored to the color and the shape in use, if the
+    // terminal reports them, and reset to the configured ones otherwise.
+    let response = query::query(terminal, STYLE_REQUEST, DEFAULT_TIMEOUT)?;
+    let mut restore = match query::find_osc(&response, b"\x1B]12;") {
+        Some(report) => color::parse_report(report)?.sequence(Target::Cursor),
+        None => Target::Cursor.reset_sequence(),
+    };
+    // The valid shape report looks like `DCS 1 $ r Ps SP q ST`.
+    let shape = query::find_osc(&response, b"\x1BP1$r")
+        .and_then(|report| report.strip_suffix(b" q"))
+        .filter(|code| !code.is_empty() && code.iter().all(u8::is_ascii_digit))
+        .unwrap_or(b"0");
+    restore.extend_from_slice(b"\x1B[");
+    restore.extend_from_slice(shape);
+    restore.extend_from_slice(b" q");
+
+    // The steady block is the most visible shape.
+    let mut flash = args.color.sequence(Target::Cursor);
+    flash.extend_from_slice(b"\x1B[2 q");
+
+    let mut tty = terminal.open()?;
+    let flash = terminal.wrap(flash)?;
+    let restore = terminal.wrap(restore)?;
+    let interval = Duration::from_millis(args.interval);
+    tty::with_noecho_cbreak_mode(tty.as_raw_fd(), || {
+        tty::set_reset_sequence(restore.clone());
+        for _ in 0..args.count {
+            tty.write_all(&flash)?;
+            tty.flush()?;
+            thread::sleep(interval);
+            tty.write_all(&restore)?;
+            tty.flush()?;
+            thread::sleep(interval);
+        }
+        Ok(())
+    })
+}
+
+/// Returns the row and the column of the cursor, counted from 1, as reported
+/// by the terminal.
+pub fn query_position(timeout: Duration, terminal: &Terminal) -> io::Result<[u32; 2]> {
+    // The report looks like `CSI ROW ; COLUMN R`.
+    let response = query::query(terminal, POSITION_REQUEST, timeout)?;
+    let report = query::find_csi(&response, b"\x1B[", b'R').ok_or_else(|| {
+        error::new(
+            Failure::Unsupported,
+            "The terminal emulator doesn't report the cursor position.",
+        )
+    })?;
+
+    let malformed = || {
+        error::new(
+            Failure::Protocol,
+            format!(
+                "Malformed cursor position report: {}.",
+                String::from_utf8_lossy(report).escape_debug()
+            ),
+        )
+    };
+    let report = String::from_utf8_lossy(report);
+    let (row, column) = report.split_once(';').ok_or_else(malformed)?;
+    match (row.parse(), column.parse()) {
+        (Ok(row), Ok(column)) if row > 0 && column > 0 => Ok([row, column]),
+        _ => Err(malformed()),
+    }
+}
@@ -0,0 +1,82 @@
+use std::env;
+use std::ffi::CStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+use crate::terminal::Terminal;
+
+#[derive(Args, Debug)]
+pub struct CwdArgs {
+    /// The directory to report. If omitted, the current working directory is
+    /// reported, as the shell sees it.
+    path: Option<PathBuf>,
+}
+
+pub fn execute(args: CwdArgs, terminal: &Terminal) -> io::Result<()> {
+    let current_dir = env::current_dir()?;
+
+    // The shell keeps the logical path of the current working directory, i.e.
+    // with the symbolic links it's been reached through, in PWD.
+    let current_dir = env::var_os("PWD")
+        .map(PathBuf::from)
+        .filter(|pwd| pwd.is_absolute() && same_file(pwd, &current_dir))
+        .unwrap_or(current_dir);
+    let path = match args.path {
+        Some(path) => current_dir.join(path),
+        None => current_dir,
+    };
+
+    terminal.send([format!("\x1B]7;{}\x1B\\", file_url(&path)?).into_bytes()])
+}
+
+/// Returns the file URL of the absolute path on this machine.
+pub fn file_url(path: &Path) -> io::Result<String> {
+    Ok(format!(
+        "file://{}{}",
+        percent_encode(&hostname()?),
+        percent_encode(path.as_os_str().as_bytes())
+    ))
+}
+
+/// Returns whether both paths point to the same file.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Returns the name of the machine, which the terminal compares against its
+/// own to tell whether the directory is local.
+fn hostname() -> io::Result<Vec<u8>> {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The name is truncated without the terminating NUL if it's too long.
+    let last = buffer.len() - 1;
+    buffer[last] = 0;
+    Ok(CStr::from_bytes_until_nul(&buffer)
+        .expect("the name is NUL terminated")
+        .to_bytes()
+        .to_vec())
+}
+
+/// Returns the bytes percent-encoded as a part of a file URL, i.e. with all
+/// but the unreserved characters and slashes encoded.
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(*byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
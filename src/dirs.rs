@@ -0,0 +1,19 @@
+use std::env;
+use std::io;
+use std::path::PathBuf;
+
+/// Returns the directory the persistent data is stored in, i.e.
+/// `$XDG_DATA_HOME/ttybox`.
+pub fn data_dir() -> io::Result<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "Neither XDG_DATA_HOME nor HOME is set.",
+            )
+        })?;
+    Ok(data_home.join("ttybox"))
+}
@@ -0,0 +1,310 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::process;
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use clap::{Args, ValueEnum};
+
+use crate::dirs::data_dir;
+use crate::identify;
+use crate::json;
+use crate::notify;
+use crate::terminal::Terminal;
+use crate::tty;
+use crate::tty::DEFAULT_TIMEOUT;
+
+/// The name the answers are stored under when the terminal can't be
+/// identified.
+pub const UNKNOWN_TERMINAL: &str = "unknown";
+
+/// The tests that need a human to tell whether they've passed.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Test {
+    /// A smooth gradient drawn with 24-bit colors.
+    Truecolor,
+    /// Text underlined with a curly line, SGR 4:3.
+    Undercurl,
+    /// A hyperlink, OSC 8.
+    Hyperlinks,
+    /// A desktop notification.
+    Notifications,
+    /// Copying to the clipboard via OSC 52, verified by pasting it back.
+    Osc52Write,
+    /// An emoji taking two cells.
+    WideEmoji,
+    /// An emoji ZWJ sequence taking two cells as a single grapheme cluster.
+    GraphemeClusters,
+}
+
+impl Test {
+    const ALL: [Test; 7] = [
+        Test::Truecolor,
+        Test::Undercurl,
+        Test::Hyperlinks,
+        Test::Notifications,
+        Test::Osc52Write,
+        Test::WideEmoji,
+        Test::GraphemeClusters,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Test::Truecolor => "truecolor",
+            Test::Undercurl => "undercurl",
+            Test::Hyperlinks => "hyperlinks",
+            Test::Notifications => "notifications",
+            Test::Osc52Write => "osc52-write",
+            Test::WideEmoji => "wide-emoji",
+            Test::GraphemeClusters => "grapheme-clusters",
+        }
+    }
+}
+
+/// The formats the answers are printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoctorFormat {
+    /// "NAME: yes", "NAME: no", or "NAME: skipped" per line.
+    Text,
+    /// {"terminal": …, "results": {"NAME": true, …}}, with null for the
+    /// skipped tests.
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct DoctorArgs {
+    /// The tests to run, all of them by default.
+    #[arg(long = "test", value_name = "TEST", value_enum)]
+    tests: Vec<Test>,
+
+    /// Don't save the answers to the profile of the terminal.
+    #[arg(long)]
+    no_save: bool,
+
+    /// The format to print the answers in once the tests are over.
+    #[arg(long, value_enum, default_value_t = DoctorFormat::Text)]
+    format: DoctorFormat,
+}
+
+pub fn execute(args: DoctorArgs, terminal: &Terminal) -> io::Result<()> {
+    let tests = if args.tests.is_empty() {
+        Test::ALL.to_vec()
+    } else {
+        args.tests
+    };
+    let name = identify::identify(terminal, DEFAULT_TIMEOUT)?
+        .map_or(UNKNOWN_TERMINAL.to_owned(), |identity| identity.name);
+
+    let mut tty = terminal.open()?;
+    writeln!(
+        tty,
+        "Testing {name}. Answer with 'y' for yes, 'n' for no, or 's' to skip."
+    )?;
+    let mut answers = Vec::new();
+    for test in tests {
+        writeln!(tty)?;
+        let answer = run(test, &mut tty, terminal)?;
+        answers.push((test, answer));
+    }
+
+    if !args.no_save {
+        Profile::open()?.record(&name, &answers)?;
+    }
+    match args.format {
+        DoctorFormat::Text => {
+            for (test, answer) in &answers {
+                println!("{}: {}", test.name(), label(*answer));
+            }
+        }
+        DoctorFormat::Json => {
+            let results = answers
+                .iter()
+                .map(|(test, answer)| {
+                    let answer = answer.map_or("null".to_owned(), |answer| answer.to_string());
+                    format!("{}: {answer}", json::quote(test.name()))
+                })
+                .collect::<Vec<_>>();
+            println!(
+                r#"{{"terminal": {}, "results": {{{}}}}}"#,
+                json::quote(&name),
+                results.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Runs the test, and returns whether it's passed, or none if it's skipped.
+fn run(test: Test, tty: &mut File, terminal: &Terminal) -> io::Result<Option<bool>> {
+    match test {
+        Test::Truecolor => {
+            let gradient = (0..64)
+                .map(|step| format!("\x1B[48;2;{};0;{}m ", step * 4, 255 - step * 4))
+                .collect::<String>();
+            writeln!(tty, "  {gradient}\x1B[0m")?;
+            ask(
+                tty,
+                "Is the gradient from blue to red smooth, without bands?",
+            )
+        }
+        Test::Undercurl => {
+            writeln!(tty, "  \x1B[4:3mcurly underline\x1B[0m")?;
+            ask(tty, "Is the text underlined with a curly line?")
+        }
+        Test::Hyperlinks => {
+            writeln!(
+                tty,
+                "  \x1B]8;;https://example.com\x1B\\example.com\x1B]8;;\x1B\\"
+            )?;
+            ask(
+                tty,
+                "Does the text open https://example.com when clicked, e.g. with Ctrl or Cmd held?",
+            )
+        }
+        Test::Notifications => {
+            notify::post_default("ttybox doctor", "Can you see this notification?", terminal)?;
+            ask(tty, "Has a desktop notification appeared?")
+        }
+        Test::Osc52Write => {
+            let sentinel = format!("ttybox-doctor-{}", process::id());
+            terminal.send([
+                format!("\x1B]52;c;{}\x1B\\", BASE64_STANDARD.encode(&sentinel)).into_bytes(),
+            ])?;
+            write!(
+                tty,
+                "Paste the clipboard here and press Enter, or just press Enter if \
+                 pasting does nothing: "
+            )?;
+            tty.flush()?;
+            let mut line = String::new();
+            BufReader::new(&*tty).read_line(&mut line)?;
+            let passed = line.trim() == sentinel;
+            writeln!(tty, "  {}", label(Some(passed)))?;
+            Ok(Some(passed))
+        }
+        Test::WideEmoji => {
+            writeln!(tty, "  |\u{1F600}|\n  |ab|")?;
+            ask(tty, "Are the bars of both lines aligned?")
+        }
+        Test::GraphemeClusters => {
+            writeln!(tty, "  |\u{1F469}\u{200D}\u{1F4BB}|\n  |ab|")?;
+            ask(
+                tty,
+                "Is there a single emoji, and are the bars of both lines aligned?",
+            )
+        }
+    }
+}
+
+/// Asks the question, and returns the answer read as a single key.
+fn ask(tty: &mut File, question: &str) -> io::Result<Option<bool>> {
+    write!(tty, "{question} [y/n/s] ")?;
+    tty.flush()?;
+    let answer = tty::with_noecho_cbreak_mode(tty.as_raw_fd(), || loop {
+        let mut key = [0u8; 1];
+        if tty.read(&mut key)? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        match key[0].to_ascii_lowercase() {
+            b'y' => return Ok(Some(true)),
+            b'n' => return Ok(Some(false)),
+            b's' => return Ok(None),
+            _ => {}
+        }
+    })?;
+    writeln!(tty, "{}", label(answer))?;
+    Ok(answer)
+}
+
+fn label(answer: Option<bool>) -> &'static str {
+    match answer {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "skipped",
+    }
+}
+
+/// Returns the answer given to the test for the terminal by "doctor", if
+/// any. The answers are what the user has seen, so they prevail over what
+/// the terminal reports and what it's known for.
+pub fn lookup(terminal: &str, test: Test) -> Option<bool> {
+    Profile::open()
+        .and_then(|profile| profile.records())
+        .ok()?
+        .into_iter()
+        .find(|(name, recorded, _)| name == terminal && recorded == test.name())
+        .map(|(_, _, answer)| answer)
+}
+
+/// The answers given to the tests, stored per terminal, one test per line:
+/// the name of the terminal, the name of the test, and either "yes" or "no",
+/// separated by tabs, as the names of terminals may contain spaces.
+struct Profile {
+    path: PathBuf,
+}
+
+impl Profile {
+    /// Opens the profile stored under `$XDG_DATA_HOME/ttybox`.
+    fn open() -> io::Result<Self> {
+        Ok(Profile {
+            path: data_dir()?.join("profile"),
+        })
+    }
+
+    /// Records the answers given for the terminal, replacing the ones given
+    /// to the same tests before. The skipped tests leave the answers as they
+    /// are.
+    fn record(&self, terminal: &str, answers: &[(Test, Option<bool>)]) -> io::Result<()> {
+        let mut records = self.records()?;
+        for (test, answer) in answers {
+            let Some(answer) = answer else {
+                continue;
+            };
+            records.retain(|(name, recorded, _)| !(name == terminal && recorded == test.name()));
+            records.push((terminal.to_owned(), test.name().to_owned(), *answer));
+        }
+
+        if let Some(directory) = self.path.parent() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(directory)?;
+        }
+        let mut file = File::options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.path)?;
+        for (name, test, answer) in records {
+            let answer = if answer { "yes" } else { "no" };
+            writeln!(file, "{name}\t{test}\t{answer}")?;
+        }
+        Ok(())
+    }
+
+    /// Returns the terminal, the test, and the answer of every record.
+    /// Malformed records are ignored.
+    fn records(&self) -> io::Result<Vec<(String, String, bool)>> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        Ok(text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                let (name, test) = (fields.next()?, fields.next()?);
+                let answer = match fields.next()? {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return None,
+                };
+                Some((name.to_owned(), test.to_owned(), answer))
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,72 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// The exit status contract, as shown by `--help`.
+pub const EXIT_STATUS_HELP: &str = "\
+Exit status:
+  0   Success.
+  1   Any failure not listed below.
+  2   The terminal, a multiplexer, or a backend doesn't support the operation.
+  3   The terminal hasn't responded in time.
+  4   The terminal has responded with something that can't be understood.
+  5   There's no terminal to talk to.
+  64  The command line is invalid.";
+
+/// The exit status of an invalid command line, as in sysexits.h.
+pub const EXIT_USAGE: i32 = 64;
+
+/// The classes of failures scripts may want to tell apart, along with the exit
+/// codes they are reported with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    Unsupported = 2,
+    Timeout = 3,
+    Protocol = 4,
+    NoTty = 5,
+}
+
+#[derive(Debug)]
+struct Error {
+    failure: Failure,
+    message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl error::Error for Error {}
+
+/// Returns an error of the given class. The error is carried by an I/O error,
+/// so it passes through the code built around `io::Result` as is.
+pub fn new(failure: Failure, message: impl Into<String>) -> io::Error {
+    let kind = match failure {
+        Failure::Unsupported => io::ErrorKind::Unsupported,
+        Failure::Timeout => io::ErrorKind::TimedOut,
+        Failure::Protocol => io::ErrorKind::InvalidData,
+        Failure::NoTty => io::ErrorKind::NotFound,
+    };
+    io::Error::new(
+        kind,
+        Error {
+            failure,
+            message: message.into(),
+        },
+    )
+}
+
+/// Returns the class of the error, if it's one of the known ones.
+pub fn failure(error: &io::Error) -> Option<Failure> {
+    error
+        .get_ref()
+        .and_then(|error| error.downcast_ref::<Error>())
+        .map(|error| error.failure)
+}
+
+/// Returns the exit code to report the error with.
+pub fn exit_code(error: &io::Error) -> i32 {
+    failure(error).map_or(1, |failure| failure as i32)
+}
@@ -0,0 +1,251 @@
+use std::env;
+use std::io;
+use std::time::Duration;
+
+use clap::{Args, ValueEnum};
+
+use crate::json;
+use crate::passthrough::{self, Multiplexer};
+use crate::query;
+use crate::terminal::{Terminal, TimeoutArgs};
+
+/// The requests to report the name and the version of the terminal,
+/// XTVERSION, and the secondary device attributes, DA2.
+pub const IDENTIFY_REQUEST: &[u8] = b"\x1B[>0q\x1B[>c";
+
+/// Where the identity of the terminal comes from, from the most reliable to
+/// the least reliable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// The name and the version the terminal reports, XTVERSION.
+    Xtversion,
+    /// The secondary device attributes the terminal reports, DA2, which a few
+    /// terminals fill with values of their own.
+    DeviceAttributes,
+    /// The variables the terminal sets in the environment, which don't reach
+    /// remote processes.
+    Environment,
+}
+
+impl Source {
+    fn name(self) -> &'static str {
+        match self {
+            Source::Xtversion => "xtversion",
+            Source::DeviceAttributes => "device attributes",
+            Source::Environment => "environment",
+        }
+    }
+}
+
+/// The terminal emulator the process is running in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    /// The name of the terminal emulator, e.g. "kitty" or "WezTerm".
+    pub name: String,
+    pub version: Option<String>,
+    pub source: Source,
+}
+
+/// The formats the identity is printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdentifyFormat {
+    /// "NAME: VALUE" per line, leaving out the unknown values but the
+    /// terminal.
+    Text,
+    /// {"terminal": …, "version": …, "source": …, "multiplexers": […]}, with
+    /// null for the unknown values.
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct IdentifyArgs {
+    /// The format to print the identity in.
+    #[arg(long, value_enum, default_value_t = IdentifyFormat::Text)]
+    format: IdentifyFormat,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(args: IdentifyArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    let identity = identify(terminal, timeout)?;
+    let multiplexers = multiplexers(terminal);
+    match args.format {
+        IdentifyFormat::Text => {
+            match &identity {
+                Some(identity) => {
+                    println!("terminal: {}", identity.name);
+                    if let Some(version) = &identity.version {
+                        println!("version: {version}");
+                    }
+                    println!("source: {}", identity.source.name());
+                }
+                None => println!("terminal: unknown"),
+            }
+            if !multiplexers.is_empty() {
+                println!("multiplexers: {}", multiplexers.join(", "));
+            }
+        }
+        IdentifyFormat::Json => {
+            let quote = |value: Option<&str>| value.map_or("null".to_owned(), json::quote);
+            let multiplexers = multiplexers
+                .iter()
+                .map(|multiplexer| json::quote(multiplexer))
+                .collect::<Vec<_>>();
+            println!(
+                r#"{{"terminal": {}, "version": {}, "source": {}, "multiplexers": [{}]}}"#,
+                quote(identity.as_ref().map(|identity| identity.name.as_str())),
+                quote(
+                    identity
+                        .as_ref()
+                        .and_then(|identity| identity.version.as_deref())
+                ),
+                quote(identity.as_ref().map(|identity| identity.source.name())),
+                multiplexers.join(", "),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Identifies the terminal emulator by asking it, falling back to the
+/// environment if it doesn't tell. The terminal is asked through the
+/// multiplexers, if any, so the outermost terminal is the one identified.
+pub fn identify(terminal: &Terminal, timeout: Duration) -> io::Result<Option<Identity>> {
+    // The reports look like `DCS > | NAME VERSION ST` and `CSI > Pp ; Pv ;
+    // Pc c`.
+    let response = query::query(terminal, IDENTIFY_REQUEST, timeout)?;
+    Ok(from_response(&response))
+}
+
+/// Identifies the terminal emulator by its response to the identification
+/// requests, which may be sent along with other ones, falling back to the
+/// environment.
+pub fn from_response(response: &[u8]) -> Option<Identity> {
+    if let Some(report) = query::find_osc(response, b"\x1BP>|") {
+        return Some(parse_version(&String::from_utf8_lossy(report)));
+    }
+    query::find_csi(response, b"\x1B[>", b'c')
+        .map(|report| String::from_utf8_lossy(report).into_owned())
+        .and_then(|report| from_device_attributes(&report))
+        .or_else(detect_env)
+}
+
+/// Returns the identity by the name and the version the terminal reports to
+/// XTVERSION, e.g. `kitty(0.31.0)` or `WezTerm 20240203-110809-5046fc22`.
+pub fn parse_version(report: &str) -> Identity {
+    let report = report.trim();
+    let (name, version) = match report.split_once(['(', ' ']) {
+        Some((name, version)) => (name, Some(version.trim_end_matches(')').trim())),
+        None => (report, None),
+    };
+    let name = match name {
+        "XTerm" => "xterm",
+        name => name,
+    };
+    Identity {
+        name: name.to_owned(),
+        version: version
+            .filter(|version| !version.is_empty())
+            .map(str::to_owned),
+        source: Source::Xtversion,
+    }
+}
+
+/// Returns the identity by the secondary device attributes, e.g. `41;388;0`,
+/// for the terminals that report values of their own rather than the ones of
+/// a VT terminal.
+fn from_device_attributes(report: &str) -> Option<Identity> {
+    let mut parameters = report.split(';').map(|value| value.parse::<u32>().ok());
+    let (kind, version) = (parameters.next()??, parameters.next()??);
+    let name = match kind {
+        1 if version >= 4000 => "kitty",
+        41 => "xterm",
+        65 if version >= 5000 => "VTE",
+        77 => "mintty",
+        _ => return None,
+    };
+    let version = match name {
+        "kitty" => None,
+        _ => Some(version.to_string()),
+    };
+    Some(Identity {
+        name: name.to_owned(),
+        version,
+        source: Source::DeviceAttributes,
+    })
+}
+
+/// Detects the terminal emulator by the variables it sets in the environment.
+/// The environment isn't forwarded over SSH, so remote processes usually
+/// can't tell the terminal this way. Multiplexers inherit the environment
+/// of the shell they have been started from, on the contrary, so the
+/// terminal is often known within them too.
+pub fn detect_env() -> Option<Identity> {
+    let term_program = env::var("TERM_PROGRAM").unwrap_or_default();
+    let term = env::var("TERM").unwrap_or_default();
+    let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+
+    // kitty goes first, as it's the only one recognized by TERM, which
+    // survives SSH, while the rest are recognized by their own variables.
+    let (name, version) = if var("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" {
+        ("kitty", None)
+    } else if var("WEZTERM_PANE").is_some() || term_program == "WezTerm" {
+        ("WezTerm", var("TERM_PROGRAM_VERSION"))
+    } else if term_program == "iTerm.app" {
+        ("iTerm2", var("TERM_PROGRAM_VERSION"))
+    } else if term_program == "vscode" {
+        ("Visual Studio Code", var("TERM_PROGRAM_VERSION"))
+    } else if term_program == "Apple_Terminal" {
+        ("Terminal.app", var("TERM_PROGRAM_VERSION"))
+    } else if term_program == "ghostty" || var("GHOSTTY_RESOURCES_DIR").is_some() {
+        ("ghostty", var("TERM_PROGRAM_VERSION"))
+    } else if var("ALACRITTY_WINDOW_ID").is_some() || var("ALACRITTY_SOCKET").is_some() {
+        ("Alacritty", None)
+    } else if var("KONSOLE_VERSION").is_some() {
+        ("Konsole", var("KONSOLE_VERSION"))
+    } else if var("WT_SESSION").is_some() {
+        ("Windows Terminal", None)
+    } else if term.starts_with("foot") {
+        ("foot", None)
+    } else if term.starts_with("rxvt") {
+        ("rxvt", None)
+    } else if var("VTE_VERSION").is_some() {
+        ("VTE", var("VTE_VERSION"))
+    } else if var("XTERM_VERSION").is_some() {
+        let version = var("XTERM_VERSION").map(|version| parse_version(&version));
+        ("xterm", version.and_then(|version| version.version))
+    } else {
+        return None;
+    };
+    Some(Identity {
+        name: name.to_owned(),
+        version,
+        source: Source::Environment,
+    })
+}
+
+/// Returns whether the process is running in kitty, as told by the
+/// environment.
+pub fn is_kitty() -> bool {
+    detect_env().is_some_and(|identity| identity.name == "kitty")
+}
+
+/// Returns the names of the multiplexers the process is running in, from the
+/// innermost to the outermost one, as given with "--passthrough" or detected.
+pub fn multiplexers(terminal: &Terminal) -> Vec<&'static str> {
+    if passthrough::is_zellij() {
+        return vec!["Zellij"];
+    }
+    terminal
+        .passthrough
+        .multiplexers()
+        .iter()
+        .map(|multiplexer| match multiplexer {
+            Multiplexer::Tmux => "tmux",
+            Multiplexer::Screen => "screen",
+        })
+        .collect()
+}
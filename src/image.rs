@@ -0,0 +1,392 @@
+mod iterm2;
+mod kitty;
+mod sixel;
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Subcommand, ValueEnum};
+
+use self::kitty::Transfer;
+use crate::error::{self, Failure};
+use crate::query;
+use crate::size::{self, Size};
+use crate::terminal::{Terminal, TimeoutArgs};
+
+/// The size of a character cell in pixels assumed when the terminal doesn't
+/// report it.
+const DEFAULT_CELL_SIZE: [u32; 2] = [10, 20];
+
+/// The protocols images are displayed by.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// Sixel graphics, supported by xterm, foot, WezTerm, mlterm, and others.
+    Sixel,
+    /// The kitty graphics protocol, supported by kitty, ghostty, WezTerm,
+    /// and Konsole. Images are transmitted losslessly and scaled by the
+    /// terminal.
+    Kitty,
+    /// The inline images of iTerm2, supported by iTerm2, WezTerm, mintty, and
+    /// others. The file is passed as it is, so animations play.
+    Iterm2,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ImageCommands {
+    /// Display the PNG, JPEG, or GIF image, scaled down to fit the terminal
+    /// unless the size is given. Only the first frame of animations is shown.
+    Show(ImageShowArgs),
+    /// Delete the images displayed by the kitty graphics protocol, along with
+    /// their data.
+    Delete(ImageDeleteArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ImageShowArgs {
+    /// The image file, or "-" to read it from the standard input.
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+
+    /// The protocol to display the image by.
+    #[arg(long, value_enum, default_value_t = Protocol::Sixel)]
+    protocol: Protocol,
+
+    /// The width to fit the image into, in columns. Defaults to the width of
+    /// the terminal.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    width: Option<u32>,
+
+    /// The height to fit the image into, in rows. Defaults to the height of
+    /// the terminal, less the line of the prompt.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    height: Option<u32>,
+
+    /// The maximum number of colors of sixel images, which is capped by the
+    /// number of color registers the terminal reports.
+    #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u16).range(2..=256))]
+    colors: u16,
+
+    /// How kitty images reach the terminal.
+    #[arg(long, value_enum, default_value_t = Transfer::Auto)]
+    transfer: Transfer,
+
+    /// The ID of the kitty image, to place or delete it later on.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    id: Option<u32>,
+
+    /// The ID of the kitty image placement, to tell the placements of the
+    /// same image apart.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    placement_id: Option<u32>,
+
+    /// The stacking order of the kitty image. Negative values put it under
+    /// the text.
+    #[arg(long, allow_negative_numbers = true)]
+    z_index: Option<i32>,
+
+    /// Stretch the iTerm2 image to fill the width and the height, instead of
+    /// keeping its aspect ratio.
+    #[arg(long)]
+    stretch: bool,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ImageDeleteArgs {
+    /// The ID of the image to delete. Defaults to all the images on the
+    /// screen.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    id: Option<u32>,
+}
+
+/// An image decoded into 8-bit RGBA pixels, row by row.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+pub fn execute(command: ImageCommands, terminal: &Terminal) -> io::Result<()> {
+    match command {
+        ImageCommands::Show(args) => execute_show(args, terminal),
+        ImageCommands::Delete(args) => terminal.send([kitty::delete(args.id)]),
+    }
+}
+
+fn execute_show(args: ImageShowArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    let data = read_file(&args.file)?;
+    let image = Image::decode(&data).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Cannot decode '{}': {e}", args.file.display()),
+        )
+    })?;
+
+    // The terminal can't be asked when printing escape sequences, so the
+    // image is then sized by the options only.
+    let size = match size::query_size(timeout, terminal) {
+        Ok(size) => size,
+        Err(e) if error::failure(&e) == Some(Failure::Unsupported) => Size::default(),
+        Err(e) => return Err(e),
+    };
+    let [cell_width, cell_height] = match (size.cell_width, size.cell_height) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => [width, height],
+        _ => DEFAULT_CELL_SIZE,
+    };
+    let columns = args.width.or(size.columns);
+    let rows = args
+        .height
+        .or(size.rows.map(|rows| rows.saturating_sub(1).max(1)));
+    let mut bounds = [
+        columns.map(|columns| columns * cell_width),
+        rows.map(|rows| rows * cell_height),
+    ];
+
+    let upscale = args.width.is_some() || args.height.is_some();
+    match args.protocol {
+        Protocol::Sixel => {
+            let graphics = match query::query_graphics(terminal, timeout) {
+                Ok(graphics) => graphics,
+                Err(e) if error::failure(&e) == Some(Failure::Unsupported) => Default::default(),
+                Err(e) => return Err(e),
+            };
+            if let Some([width, height]) = graphics.sixel_geometry.maximum {
+                bounds[0] = Some(bounds[0].map_or(width, |bound| bound.min(width)));
+                bounds[1] = Some(bounds[1].map_or(height, |bound| bound.min(height)));
+            }
+            let colors = graphics
+                .color_registers
+                .current
+                .map_or(args.colors, |registers| {
+                    args.colors.min(registers.clamp(2, 256) as u16)
+                });
+
+            let image = image.fit(bounds, upscale);
+            terminal.send([sixel::encode(&image, usize::from(colors))])
+        }
+        Protocol::Kitty => {
+            // The image is transmitted as it is, and scaled by the terminal
+            // into the cells it's fitted into.
+            let placement = kitty::Placement {
+                id: args.id,
+                placement_id: args.placement_id,
+                z_index: args.z_index,
+                cells: fitted_cells(&image, bounds, upscale, [cell_width, cell_height]),
+            };
+            terminal.send(kitty::encode(
+                &image,
+                &data,
+                &args.file,
+                args.transfer,
+                placement,
+            )?)
+        }
+        Protocol::Iterm2 => {
+            // Stretched images fill the cells they are given, while the
+            // others are fitted into them like with the other protocols.
+            let cells = match fitted_cells(&image, bounds, upscale, [cell_width, cell_height]) {
+                _ if args.stretch => [columns, rows],
+                Some([columns, rows]) => [Some(columns), Some(rows)],
+                None => [None, None],
+            };
+            terminal.send([iterm2::encode(&data, &args.file, cells, args.stretch)])
+        }
+    }
+}
+
+/// Returns the number of columns and rows the image scaled to fit the bounds
+/// takes, if it's scaled at all.
+fn fitted_cells(
+    image: &Image,
+    bounds: [Option<u32>; 2],
+    upscale: bool,
+    [cell_width, cell_height]: [u32; 2],
+) -> Option<[u32; 2]> {
+    let [width, height] = image.fitted_size(bounds, upscale);
+    ([width, height] != [image.width, image.height])
+        .then(|| [width.div_ceil(cell_width), height.div_ceil(cell_height)])
+}
+
+/// Reads the file, or the standard input if it's "-".
+fn read_file(file: &Path) -> io::Result<Vec<u8>> {
+    if file == Path::new("-") {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    fs::read(file)
+        .map_err(|e| io::Error::new(e.kind(), format!("Cannot read '{}': {e}", file.display())))
+}
+
+impl Image {
+    /// Decodes the PNG, JPEG, or GIF image, telling the format by the
+    /// signature. Only the first frame of animations is decoded.
+    pub fn decode(data: &[u8]) -> io::Result<Self> {
+        let invalid =
+            |e: &dyn std::fmt::Display| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+        if data.starts_with(b"\x89PNG") {
+            let mut decoder = png::Decoder::new(data);
+            decoder.set_transformations(png::Transformations::normalize_to_color8());
+            let mut reader = decoder.read_info().map_err(|e| invalid(&e))?;
+            let mut buffer = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut buffer).map_err(|e| invalid(&e))?;
+            buffer.truncate(info.buffer_size());
+            // The transformations leave 8-bit grayscale or RGB samples, with
+            // or without alpha.
+            let pixels = match info.color_type.samples() {
+                1 => gray(&buffer),
+                2 => buffer
+                    .chunks_exact(2)
+                    .map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+                    .collect(),
+                3 => rgb(&buffer),
+                _ => buffer
+                    .chunks_exact(4)
+                    .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+                    .collect(),
+            };
+            Image::new(info.width, info.height, pixels)
+        } else if data.starts_with(b"\xFF\xD8") {
+            let mut decoder = jpeg_decoder::Decoder::new(data);
+            let buffer = decoder.decode().map_err(|e| invalid(&e))?;
+            let info = decoder.info().expect("the image is decoded");
+            let pixels = match info.pixel_format {
+                jpeg_decoder::PixelFormat::L8 => gray(&buffer),
+                // The samples are big-endian, the most significant byte going
+                // first.
+                jpeg_decoder::PixelFormat::L16 => buffer
+                    .chunks_exact(2)
+                    .map(|sample| [sample[0], sample[0], sample[0], 255])
+                    .collect(),
+                jpeg_decoder::PixelFormat::RGB24 => rgb(&buffer),
+                // The samples of Adobe's CMYK JPEG images are inverted.
+                jpeg_decoder::PixelFormat::CMYK32 => buffer
+                    .chunks_exact(4)
+                    .map(|pixel| {
+                        let key = u16::from(pixel[3]);
+                        let channel = |value: u8| (u16::from(value) * key / 255) as u8;
+                        [channel(pixel[0]), channel(pixel[1]), channel(pixel[2]), 255]
+                    })
+                    .collect(),
+            };
+            Image::new(u32::from(info.width), u32::from(info.height), pixels)
+        } else if data.starts_with(b"GIF8") {
+            let mut options = gif::DecodeOptions::new();
+            options.set_color_output(gif::ColorOutput::RGBA);
+            let mut decoder = options.read_info(data).map_err(|e| invalid(&e))?;
+            let (width, height) = (u32::from(decoder.width()), u32::from(decoder.height()));
+            let mut pixels = vec![[0; 4]; (width * height) as usize];
+            if let Some(frame) = decoder.read_next_frame().map_err(|e| invalid(&e))? {
+                // The frame is a rectangle of its own within the canvas.
+                let (left, top) = (u32::from(frame.left), u32::from(frame.top));
+                for (index, pixel) in frame.buffer.chunks_exact(4).enumerate() {
+                    let x = left + index as u32 % u32::from(frame.width);
+                    let y = top + index as u32 / u32::from(frame.width);
+                    if x < width && y < height {
+                        pixels[(y * width + x) as usize] = [pixel[0], pixel[1], pixel[2], pixel[3]];
+                    }
+                }
+            }
+            Image::new(width, height, pixels)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the format isn't supported, expected PNG, JPEG, or GIF.",
+            ))
+        }
+    }
+
+    fn new(width: u32, height: u32, pixels: Vec<[u8; 4]>) -> io::Result<Self> {
+        if width == 0 || height == 0 || pixels.len() != (width * height) as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the image is empty or truncated.",
+            ));
+        }
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Returns the image scaled to fit the bounds, given in pixels, keeping
+    /// the aspect ratio. Unknown bounds don't limit the size, and the image
+    /// is only scaled up if asked to.
+    pub fn fit(self, bounds: [Option<u32>; 2], upscale: bool) -> Self {
+        let [width, height] = self.fitted_size(bounds, upscale);
+        if [width, height] == [self.width, self.height] {
+            return self;
+        }
+        self.resize(width, height)
+    }
+
+    /// Returns the size, in pixels, the image is scaled to by fit().
+    pub fn fitted_size(&self, bounds: [Option<u32>; 2], upscale: bool) -> [u32; 2] {
+        let scale = [(bounds[0], self.width), (bounds[1], self.height)]
+            .into_iter()
+            .filter_map(|(bound, size)| Some(f64::from(bound?) / f64::from(size)))
+            .fold(f64::INFINITY, f64::min);
+        let scale = match scale {
+            scale if scale.is_infinite() => return [self.width, self.height],
+            scale if !upscale => scale.min(1.0),
+            scale => scale,
+        };
+        let width = ((f64::from(self.width) * scale).round() as u32).max(1);
+        let height = ((f64::from(self.height) * scale).round() as u32).max(1);
+        [width, height]
+    }
+
+    /// Returns the image resized by averaging the pixels each target pixel
+    /// covers, which is as good as it gets for scaling down.
+    fn resize(&self, width: u32, height: u32) -> Self {
+        let span = |target: u32, size: u32, target_size: u32| {
+            let start = u64::from(target) * u64::from(size) / u64::from(target_size);
+            let end = (u64::from(target) + 1) * u64::from(size) / u64::from(target_size);
+            start as u32..(end as u32).max(start as u32 + 1)
+        };
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            let rows = span(y, self.height, height);
+            for x in 0..width {
+                let columns = span(x, self.width, width);
+                let mut sum = [0u32; 4];
+                let mut count = 0;
+                for row in rows.clone() {
+                    for column in columns.clone() {
+                        let pixel = self.pixels[(row * self.width + column) as usize];
+                        for channel in 0..4 {
+                            sum[channel] += u32::from(pixel[channel]);
+                        }
+                        count += 1;
+                    }
+                }
+                pixels.push(sum.map(|channel| (channel / count) as u8));
+            }
+        }
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+fn gray(buffer: &[u8]) -> Vec<[u8; 4]> {
+    buffer
+        .iter()
+        .map(|value| [*value, *value, *value, 255])
+        .collect()
+}
+
+fn rgb(buffer: &[u8]) -> Vec<[u8; 4]> {
+    buffer
+        .chunks_exact(3)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+        .collect()
+}
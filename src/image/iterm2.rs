@@ -0,0 +1,31 @@
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+
+/// Returns the sequence that displays the image file at the cursor. The file
+/// is passed as it is, so the terminal decodes it, animations included, and
+/// scales it into the number of columns and rows if they are given.
+pub fn encode(data: &[u8], file: &Path, cells: [Option<u32>; 2], stretch: bool) -> Vec<u8> {
+    let mut arguments = format!("inline=1;size={}", data.len());
+    if file != Path::new("-") {
+        if let Some(name) = file.file_name() {
+            arguments.push_str(&format!(
+                ";name={}",
+                BASE64_STANDARD.encode(name.as_bytes())
+            ));
+        }
+    }
+    if let Some(columns) = cells[0] {
+        arguments.push_str(&format!(";width={columns}"));
+    }
+    if let Some(rows) = cells[1] {
+        arguments.push_str(&format!(";height={rows}"));
+    }
+    arguments.push_str(&format!(";preserveAspectRatio={}", u8::from(!stretch)));
+
+    let mut sequence = format!("\x1B]1337;File={arguments}:").into_bytes();
+    sequence.extend_from_slice(BASE64_STANDARD.encode(data).as_bytes());
+    sequence.push(b'\x07');
+    sequence
+}
@@ -0,0 +1,214 @@
+use std::borrow::Cow;
+use std::env;
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use clap::ValueEnum;
+
+use super::Image;
+
+/// The maximum size of the base64 encoded payload of a single sequence, as
+/// kitty requires the bigger ones to be split into chunks.
+const CHUNK_SIZE: usize = 4096;
+
+/// The number of the temporary files and shared memory objects created so
+/// far, which tells apart the ones of the same process.
+static TRANSFERS: AtomicU32 = AtomicU32::new(0);
+
+/// The ways the image data reaches the terminal.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transfer {
+    /// Within the escape sequences, which works over SSH too.
+    Direct,
+    /// By the path of the image file, which the terminal reads itself. Only
+    /// PNG files can be passed as they are, the others are decoded into a
+    /// temporary file.
+    File,
+    /// By the path of a temporary file the terminal deletes once read.
+    Temp,
+    /// By the name of a POSIX shared memory object the terminal unlinks once
+    /// read.
+    Shm,
+    /// By a temporary file, or the image file itself if it's PNG, unless the
+    /// process runs over SSH, in which case the data is sent directly.
+    Auto,
+}
+
+/// Where and how the image is placed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Placement {
+    /// The ID of the image, so it can be placed again or deleted later on.
+    pub id: Option<u32>,
+    /// The ID of the placement, to tell the placements of the same image
+    /// apart.
+    pub placement_id: Option<u32>,
+    /// The stacking order of the image relative to the text and the other
+    /// images. Negative values put it under the text.
+    pub z_index: Option<i32>,
+    /// The number of columns and rows the image is scaled into.
+    pub cells: Option<[u32; 2]>,
+}
+
+/// Returns the sequences that transmit the image and display it at the
+/// cursor. PNG images given by their data are transmitted as they are, so the
+/// terminal decodes them losslessly, while the others are transmitted as RGBA
+/// pixels.
+pub fn encode(
+    image: &Image,
+    data: &[u8],
+    file: &Path,
+    transfer: Transfer,
+    placement: Placement,
+) -> io::Result<Vec<Vec<u8>>> {
+    let is_png = data.starts_with(b"\x89PNG");
+    let transfer = match transfer {
+        Transfer::Auto if is_remote() => Transfer::Direct,
+        Transfer::Auto if is_png && file != Path::new("-") => Transfer::File,
+        Transfer::Auto => Transfer::Temp,
+        transfer => transfer,
+    };
+
+    let content = if is_png {
+        Cow::Borrowed(data)
+    } else {
+        Cow::Owned(image.pixels.iter().flatten().copied().collect())
+    };
+    let mut control = if is_png {
+        String::from("a=T,f=100")
+    } else {
+        format!("a=T,f=32,s={},v={}", image.width, image.height)
+    };
+    let payload = match transfer {
+        Transfer::Direct | Transfer::Auto => {
+            control.push_str(",t=d");
+            content.into_owned()
+        }
+        Transfer::File if is_png && file != Path::new("-") => {
+            control.push_str(",t=f");
+            fs::canonicalize(file)?.as_os_str().as_bytes().to_vec()
+        }
+        Transfer::File | Transfer::Temp => {
+            // The terminal only deletes the temporary files whose paths
+            // contain "tty-graphics-protocol", so it can't be tricked into
+            // deleting others.
+            let path =
+                env::temp_dir().join(format!("tty-graphics-protocol-ttybox-{}", unique_suffix()));
+            let mut temp = File::options()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)?;
+            if let Err(e) = temp.write_all(&content) {
+                let _ = fs::remove_file(&path);
+                return Err(e);
+            }
+            control.push_str(",t=t");
+            path.as_os_str().as_bytes().to_vec()
+        }
+        Transfer::Shm => {
+            let name = format!("/ttybox-{}", unique_suffix());
+            write_shared_memory(&name, &content)?;
+            control.push_str(",t=s");
+            name.into_bytes()
+        }
+    };
+
+    if let Some(id) = placement.id {
+        control.push_str(&format!(",i={id}"));
+    }
+    if let Some(placement_id) = placement.placement_id {
+        control.push_str(&format!(",p={placement_id}"));
+    }
+    if let Some(z_index) = placement.z_index {
+        control.push_str(&format!(",z={z_index}"));
+    }
+    if let Some([columns, rows]) = placement.cells {
+        control.push_str(&format!(",c={columns},r={rows}"));
+    }
+    // The responses are suppressed, as nobody reads them.
+    control.push_str(",q=2");
+
+    // The first chunk carries the control data, and the following ones only
+    // whether more chunks follow.
+    let payload = BASE64_STANDARD.encode(payload);
+    let chunks = payload.as_bytes().chunks(CHUNK_SIZE).collect::<Vec<_>>();
+    let count = chunks.len();
+    Ok(chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let more = u8::from(index + 1 < count);
+            let mut sequence = if index == 0 {
+                format!("\x1B_G{control},m={more};")
+            } else {
+                format!("\x1B_Gm={more};")
+            }
+            .into_bytes();
+            sequence.extend_from_slice(chunk);
+            sequence.extend_from_slice(b"\x1B\\");
+            sequence
+        })
+        .collect())
+}
+
+/// Returns the sequence that deletes the image with the ID, or all the
+/// images on the screen, along with their data.
+pub fn delete(id: Option<u32>) -> Vec<u8> {
+    match id {
+        Some(id) => format!("\x1B_Ga=d,d=I,i={id},q=2\x1B\\").into_bytes(),
+        None => b"\x1B_Ga=d,d=A,q=2\x1B\\".to_vec(),
+    }
+}
+
+/// Returns whether the process runs over SSH, where the terminal can't read
+/// the files and the shared memory of the process.
+fn is_remote() -> bool {
+    ["SSH_CONNECTION", "SSH_CLIENT", "SSH_TTY"]
+        .iter()
+        .any(|name| env::var_os(name).is_some())
+}
+
+/// Returns the suffix of a name no other transfer uses: neither of this
+/// process, nor of an earlier one with the same ID whose leftovers the
+/// terminal never deleted.
+fn unique_suffix() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos());
+    let count = TRANSFERS.fetch_add(1, Ordering::Relaxed);
+    format!("{}-{nanos:x}-{count}", process::id())
+}
+
+fn write_shared_memory(name: &str, data: &[u8]) -> io::Result<()> {
+    let name = CString::new(name).expect("the name has no NUL bytes");
+    // SAFETY: The name is a valid C string, and the returned descriptor is
+    // owned by the file, which closes it.
+    let fd = unsafe {
+        libc::shm_open(
+            name.as_ptr(),
+            libc::O_CREAT | libc::O_EXCL | libc::O_RDWR,
+            0o600,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    let written = file
+        .set_len(data.len() as u64)
+        .and_then(|()| file.write_all(data));
+    if written.is_err() {
+        // SAFETY: The name is a valid C string.
+        unsafe { libc::shm_unlink(name.as_ptr()) };
+    }
+    written
+}
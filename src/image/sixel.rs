@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use super::Image;
+
+/// The pixels with less opacity are left transparent, as sixel images have no
+/// partial transparency.
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Returns the image encoded as a sixel sequence, with the colors reduced to
+/// at most the given number. Transparent pixels leave what's underneath as is.
+pub fn encode(image: &Image, colors: usize) -> Vec<u8> {
+    let (palette, indices) = quantize(image, colors);
+    let width = image.width as usize;
+
+    // The sequence starts with `DCS P1 ; P2 ; P3 q`, P2 being 1 for the
+    // pixels that aren't drawn to stay transparent, followed by the raster
+    // attributes telling the size, and the palette in RGB percentages.
+    let mut sequence = format!("\x1BP0;1;0q\"1;1;{};{}", image.width, image.height);
+    for (index, color) in palette.iter().enumerate() {
+        let [red, green, blue] = color.map(|channel| (u32::from(channel) * 100 + 127) / 255);
+        let _ = write!(sequence, "#{index};2;{red};{green};{blue}");
+    }
+
+    // The image is drawn in bands of six rows, each color of a band on its
+    // own, with the columns given as characters holding six bits each, one
+    // per row.
+    let mut bands = vec![Vec::new(); palette.len()];
+    for top in (0..image.height as usize).step_by(6) {
+        let rows = 6.min(image.height as usize - top);
+        for band in &mut bands {
+            band.clear();
+        }
+        for row in 0..rows {
+            for column in 0..width {
+                if let Some(index) = indices[(top + row) * width + column] {
+                    let band = &mut bands[index];
+                    if band.is_empty() {
+                        band.resize(width, 0u8);
+                    }
+                    band[column] |= 1 << row;
+                }
+            }
+        }
+
+        for (index, band) in bands.iter().enumerate() {
+            if band.is_empty() {
+                continue;
+            }
+            let _ = write!(sequence, "#{index}");
+            run_length_encode(&mut sequence, band);
+            // The carriage return goes back to draw the next color over.
+            sequence.push('$');
+        }
+        sequence.push('-');
+    }
+    sequence.push_str("\x1B\\");
+    sequence.into_bytes()
+}
+
+/// Appends the sixels, with runs of the same sixel given by a repeat count,
+/// and the trailing empty ones left out.
+fn run_length_encode(sequence: &mut String, band: &[u8]) {
+    let end = band
+        .iter()
+        .rposition(|bits| *bits != 0)
+        .map_or(0, |end| end + 1);
+    let mut column = 0;
+    while column < end {
+        let bits = band[column];
+        let run = band[column..end]
+            .iter()
+            .take_while(|other| **other == bits)
+            .count();
+        let sixel = char::from(0x3F + bits);
+        if run > 3 {
+            let _ = write!(sequence, "!{run}{sixel}");
+        } else {
+            sequence.extend(std::iter::repeat_n(sixel, run));
+        }
+        column += run;
+    }
+}
+
+/// Reduces the colors of the image by the median cut, and returns the
+/// palette along with the palette index of every pixel, none for the
+/// transparent ones.
+fn quantize(image: &Image, colors: usize) -> (Vec<[u8; 3]>, Vec<Option<usize>>) {
+    let mut histogram = HashMap::new();
+    for [red, green, blue, alpha] in &image.pixels {
+        if *alpha >= ALPHA_THRESHOLD {
+            *histogram.entry([*red, *green, *blue]).or_insert(0u64) += 1;
+        }
+    }
+
+    // The box whose colors spread the most along any channel is split in two
+    // halves of the same number of pixels along that channel, until there
+    // are as many boxes as colors, or no box can be split.
+    let mut boxes = vec![histogram.into_iter().collect::<Vec<_>>()];
+    while boxes.len() < colors {
+        let Some((index, channel, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(index, colors)| {
+                let (channel, spread) = (0..3)
+                    .map(|channel| {
+                        let values = colors.iter().map(|(color, _)| color[channel]);
+                        let spread = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+                        (channel, spread)
+                    })
+                    .max_by_key(|(_, spread)| *spread)
+                    .expect("there are three channels");
+                (index, channel, spread)
+            })
+            .max_by_key(|(_, _, spread)| *spread)
+        else {
+            break;
+        };
+
+        let colors = &mut boxes[index];
+        colors.sort_unstable_by_key(|(color, _)| color[channel]);
+        let total = colors.iter().map(|(_, count)| count).sum::<u64>();
+        let mut seen = 0;
+        let median = colors
+            .iter()
+            .position(|(_, count)| {
+                seen += count;
+                seen * 2 >= total
+            })
+            .unwrap_or(0);
+        let half = colors.split_off((median + 1).min(colors.len() - 1));
+        boxes.push(half);
+    }
+
+    let palette = boxes
+        .iter()
+        .map(|colors| {
+            let total = colors.iter().map(|(_, count)| count).sum::<u64>().max(1);
+            [0, 1, 2].map(|channel| {
+                let sum = colors
+                    .iter()
+                    .map(|(color, count)| u64::from(color[channel]) * count)
+                    .sum::<u64>();
+                ((sum + total / 2) / total) as u8
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut nearest = HashMap::new();
+    let indices = image
+        .pixels
+        .iter()
+        .map(|[red, green, blue, alpha]| {
+            (*alpha >= ALPHA_THRESHOLD).then(|| {
+                let color = [*red, *green, *blue];
+                *nearest
+                    .entry(color)
+                    .or_insert_with(|| nearest_index(&palette, color))
+            })
+        })
+        .collect();
+    (palette, indices)
+}
+
+fn nearest_index(palette: &[[u8; 3]], color: [u8; 3]) -> usize {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| {
+            (0..3)
+                .map(|channel| {
+                    let difference = i32::from(entry[channel]) - i32::from(color[channel]);
+                    difference * difference
+                })
+                .sum::<i32>()
+        })
+        .map_or(0, |(index, _)| index)
+}
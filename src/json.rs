@@ -0,0 +1,321 @@
+use std::fmt::Write as _;
+use std::io;
+
+/// A JSON value. Numbers are kept as written, as they are used for integers
+/// only and any conversion is up to the caller.
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Returns the value of the object's member, if it's an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the string as a JSON string literal.
+pub fn quote(string: &str) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push('"');
+    for char in string.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            char if char.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", char as u32);
+            }
+            char => quoted.push(char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The maximum number of arrays and objects nested in each other, which keeps
+/// the recursive parser from overflowing the stack on hostile input.
+const MAX_DEPTH: usize = 128;
+
+/// Parses the text as a single JSON value.
+pub fn parse(text: &str) -> io::Result<Value> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        offset: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.offset < parser.text.len() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    offset: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> io::Result<Value> {
+        self.skip_whitespace();
+        match self.text.get(self.offset) {
+            Some(b'{' | b'[') if self.depth == MAX_DEPTH => Err(self.error("nested too deeply")),
+            Some(b'{') => self.nested(Self::object),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'-' | b'0'..=b'9') => Ok(self.number()),
+            Some(_) if self.literal("null") => Ok(Value::Null),
+            Some(_) if self.literal("true") => Ok(Value::Bool(true)),
+            Some(_) if self.literal("false") => Ok(Value::Bool(false)),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> io::Result<Value>) -> io::Result<Value> {
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> io::Result<Value> {
+        let mut members = Vec::new();
+        self.offset += 1;
+        if self.consume(b'}') {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.text.get(self.offset) != Some(&b'"') {
+                return Err(self.error("expected a member name"));
+            }
+            let name = self.string()?;
+            if !self.consume(b':') {
+                return Err(self.error("expected ':'"));
+            }
+            members.push((name, self.value()?));
+            if self.consume(b'}') {
+                return Ok(Value::Object(members));
+            }
+            if !self.consume(b',') {
+                return Err(self.error("expected ',' or '}'"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> io::Result<Value> {
+        let mut items = Vec::new();
+        self.offset += 1;
+        if self.consume(b']') {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.consume(b']') {
+                return Ok(Value::Array(items));
+            }
+            if !self.consume(b',') {
+                return Err(self.error("expected ',' or ']'"));
+            }
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let mut string = Vec::new();
+        self.offset += 1;
+        loop {
+            let byte = *self
+                .text
+                .get(self.offset)
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.offset += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = *self
+                        .text
+                        .get(self.offset)
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.offset += 1;
+                    let char = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\x08',
+                        b'f' => '\x0C',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    string.extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte => string.push(byte),
+            }
+        }
+        // The text is a string slice, so the bytes between escapes are UTF-8.
+        String::from_utf8(string).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    /// Decodes the `\uXXXX` escape, combining surrogate pairs.
+    fn unicode_escape(&mut self) -> io::Result<char> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !(self.consume_raw(b'\\') && self.consume_raw(b'u')) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid escape"))
+    }
+
+    fn hex4(&mut self) -> io::Result<u32> {
+        let digits = self
+            .text
+            .get(self.offset..self.offset + 4)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid escape"))?;
+        self.offset += 4;
+        Ok(digits)
+    }
+
+    fn number(&mut self) -> Value {
+        let start = self.offset;
+        while self
+            .text
+            .get(self.offset)
+            .is_some_and(|byte| byte.is_ascii_digit() || b"+-.eE".contains(byte))
+        {
+            self.offset += 1;
+        }
+        Value::Number(String::from_utf8_lossy(&self.text[start..self.offset]).into_owned())
+    }
+
+    fn literal(&mut self, literal: &str) -> bool {
+        let matches = self.text[self.offset..].starts_with(literal.as_bytes());
+        if matches {
+            self.offset += literal.len();
+        }
+        matches
+    }
+
+    /// Consumes the byte, skipping the whitespace before it, if it's next.
+    fn consume(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        self.consume_raw(byte)
+    }
+
+    fn consume_raw(&mut self, byte: u8) -> bool {
+        let matches = self.text.get(self.offset) == Some(&byte);
+        if matches {
+            self.offset += 1;
+        }
+        matches
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .text
+            .get(self.offset)
+            .is_some_and(|byte| b" \t\r\n".contains(byte))
+        {
+            self.offset += 1;
+        }
+    }
+
+    fn error(&self, message: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid JSON at offset {}: {message}.", self.offset),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(text: &str) -> io::Result<String> {
+        match parse(text)? {
+            Value::String(string) => Ok(string),
+            value => panic!("not a string: {value:?}"),
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            string(r#""\"\\\/\b\f\n\r\t""#).unwrap(),
+            "\"\\/\x08\x0C\n\r\t"
+        );
+        assert_eq!(string(r#""\u00e9\u00E9""#).unwrap(), "\u{e9}\u{e9}");
+        assert!(string(r#""\x""#).is_err());
+        assert!(string(r#""\u12""#).is_err());
+        assert!(string(r#""\u+123""#).is_err());
+        assert!(string(r#""\u-123""#).is_err());
+    }
+
+    #[test]
+    fn surrogates() {
+        assert_eq!(string(r#""\ud83d\ude00""#).unwrap(), "\u{1F600}");
+        assert!(string(r#""\ud83d""#).is_err());
+        assert!(string(r#""\ud83dx""#).is_err());
+        assert!(string(r#""\ud83d\u0041""#).is_err());
+        assert!(string(r#""\ude00""#).is_err());
+    }
+
+    #[test]
+    fn quote_round_trip() {
+        let text = "\"\\\n\r\t\x01\u{e9}\u{1F600}";
+        assert_eq!(string(&quote(text)).unwrap(), text);
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+        let objects = "{\"a\":".repeat(MAX_DEPTH + 1) + "null" + &"}".repeat(MAX_DEPTH + 1);
+        assert!(parse(&objects).is_err());
+    }
+}
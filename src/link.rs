@@ -0,0 +1,481 @@
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, BufRead, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Subcommand};
+
+use crate::cwd;
+
+/// The schemes of the URLs recognized in text.
+const SCHEMES: [&str; 4] = ["https://", "http://", "ftp://", "file://"];
+
+#[derive(Subcommand, Debug)]
+pub enum LinkCommands {
+    /// Copy the standard input to the standard output with the URLs found in
+    /// it linked, leaving the escape sequences and existing links intact.
+    Filter(LinkFilterArgs),
+}
+
+/// The patterns of the references to lines of files, as printed by compilers
+/// and linters, recognized by default.
+const FILE_PATTERNS: [&str; 4] = [
+    "{path}:{line}:{column}",
+    "{path}:{line}",
+    "{path}({line},{column})",
+    "File \"{path}\", line {line}",
+];
+
+#[derive(Args, Debug)]
+pub struct LinkFilterArgs {
+    /// Link the email addresses as well.
+    #[arg(long, default_value_t = false)]
+    emails: bool,
+
+    /// Link the references to lines of existing files as well, e.g.
+    /// "src/main.rs:12:5" as printed by compilers.
+    #[arg(long, default_value_t = false)]
+    files: bool,
+
+    /// The pattern of the references to lines of files, where "{path}" stands
+    /// for the path, "{line}" for the line, and "{column}" for the column,
+    /// and the rest is matched as is. Can be given multiple times, the first
+    /// matching pattern wins. Replaces the default patterns, which recognize
+    /// the references of most compilers.
+    #[arg(long, value_name = "PATTERN", value_parser = parse_pattern, requires = "files")]
+    pattern: Vec<Pattern>,
+
+    /// The URL to link the references to lines of files to, where "{path}"
+    /// stands for the absolute path, "{line}" for the line, and "{column}"
+    /// for the column, e.g. "vscode://file{path}:{line}:{column}". If
+    /// omitted, the file URL is used, which has no notion of lines.
+    #[arg(long, value_name = "TEMPLATE", requires = "files")]
+    template: Option<String>,
+}
+
+/// The pattern of a reference to a line of a file, as a sequence of literal
+/// text and placeholders.
+#[derive(Clone, Debug)]
+pub struct Pattern(Vec<Token>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Text(Vec<u8>),
+    Path,
+    Line,
+    Column,
+}
+
+/// Parses the pattern, which must refer to both the path and the line.
+fn parse_pattern(value: &str) -> Result<Pattern, String> {
+    let mut tokens = Vec::new();
+    let mut rest = value;
+    while !rest.is_empty() {
+        let (token, length) = [
+            ("{path}", Token::Path),
+            ("{line}", Token::Line),
+            ("{column}", Token::Column),
+        ]
+        .into_iter()
+        .find(|(placeholder, _)| rest.starts_with(placeholder))
+        .map(|(placeholder, token)| (token, placeholder.len()))
+        .unwrap_or_else(|| {
+            let first = rest.chars().next().map_or(1, char::len_utf8);
+            let length = rest[first..]
+                .find('{')
+                .map_or(rest.len(), |end| end + first);
+            (Token::Text(rest.as_bytes()[..length].to_vec()), length)
+        });
+
+        // Two placeholders in a row can't be told apart.
+        if !matches!(token, Token::Text(_)) && !matches!(tokens.last(), Some(Token::Text(_)) | None)
+        {
+            return Err(format!("adjacent placeholders in pattern '{value}'"));
+        }
+        match (tokens.last_mut(), token) {
+            (Some(Token::Text(text)), Token::Text(more)) => text.extend(more),
+            (_, token) => tokens.push(token),
+        }
+        rest = &rest[length..];
+    }
+
+    if !tokens.contains(&Token::Path) || !tokens.contains(&Token::Line) {
+        return Err(format!(
+            "pattern '{value}' lacks either {{path}} or {{line}}"
+        ));
+    }
+    Ok(Pattern(tokens))
+}
+
+/// A reference to a line of a file found in text.
+struct Reference {
+    path: String,
+    line: String,
+    column: String,
+}
+
+impl Pattern {
+    /// Returns the reference the text starts with, and its length, if it
+    /// matches the pattern.
+    fn find(&self, text: &[u8]) -> Option<(Reference, usize)> {
+        let mut reference = Reference {
+            path: String::new(),
+            line: String::new(),
+            column: String::new(),
+        };
+        let mut offset = 0;
+        for token in &self.0 {
+            let rest = &text[offset..];
+            let length = match token {
+                Token::Text(literal) => rest.starts_with(literal).then_some(literal.len())?,
+                Token::Path => rest
+                    .iter()
+                    .position(|byte| !is_path_char(*byte))
+                    .unwrap_or(rest.len()),
+                Token::Line | Token::Column => rest
+                    .iter()
+                    .position(|byte| !byte.is_ascii_digit())
+                    .unwrap_or(rest.len()),
+            };
+            if length == 0 {
+                return None;
+            }
+            let value = String::from_utf8_lossy(&rest[..length]).into_owned();
+            match token {
+                Token::Text(_) => {}
+                Token::Path => reference.path = value,
+                Token::Line => reference.line = value,
+                Token::Column => reference.column = value,
+            }
+            offset += length;
+        }
+        Some((reference, offset))
+    }
+}
+
+/// Returns whether the byte may be a part of a path in text. Spaces and
+/// colons, which are rarely used in paths, are not.
+fn is_path_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"._-/~+@%".contains(&byte) || byte >= 0x80
+}
+
+/// Recognizes the references to lines of files in text, and tells the URLs to
+/// link them to.
+struct FileLinker {
+    patterns: Vec<Pattern>,
+    template: Option<String>,
+    current_dir: PathBuf,
+}
+
+impl FileLinker {
+    /// Returns the length of the reference to a line of an existing file the
+    /// text starts with, if any, along with the URL to link it to.
+    fn find(&self, text: &[u8]) -> io::Result<Option<(usize, String)>> {
+        for pattern in &self.patterns {
+            let Some((reference, length)) = pattern.find(text) else {
+                continue;
+            };
+            let path = self.current_dir.join(&reference.path);
+            if !path.is_file() {
+                continue;
+            }
+            let url = match &self.template {
+                Some(template) => sanitize(
+                    template
+                        .replace("{path}", &path.to_string_lossy())
+                        .replace("{line}", &reference.line)
+                        .replace("{column}", &reference.column)
+                        .as_bytes(),
+                    b"",
+                ),
+                None => cwd::file_url(&path)?,
+            };
+            return Ok(Some((length, url)));
+        }
+        Ok(None)
+    }
+}
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct LinkArgs {
+    #[command(subcommand)]
+    command: Option<LinkCommands>,
+
+    /// The URL to link to. A path, i.e. anything without a scheme, is turned
+    /// into a file URL on this machine.
+    #[arg(required = true)]
+    url: Option<OsString>,
+
+    /// The text of the link. If omitted, every line of the standard input is
+    /// linked, unless it's a terminal, in which case the URL is the text.
+    text: Option<OsString>,
+
+    /// The identifier of the link, so the cells of the text printed apart,
+    /// e.g. across multiple lines, are known to be one link.
+    #[arg(long)]
+    id: Option<String>,
+}
+
+pub fn execute(args: LinkArgs) -> io::Result<()> {
+    if let Some(LinkCommands::Filter(filter_args)) = args.command {
+        return execute_filter(filter_args);
+    }
+
+    let target = args.url.expect("the URL is required");
+    let url = url(&target)?;
+    let id = args.id.as_deref();
+    let mut stdout = io::stdout().lock();
+
+    match args.text {
+        Some(text) => {
+            stdout.write_all(&hyperlink(id, &url, text.as_bytes()))?;
+            stdout.write_all(b"\n")?;
+        }
+        None if unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 => {
+            stdout.write_all(&hyperlink(id, &url, target.as_bytes()))?;
+            stdout.write_all(b"\n")?;
+        }
+        None => {
+            // Every line is linked on its own, so the link doesn't span the
+            // newlines, which some terminals don't expect.
+            for line in io::stdin().lock().split(b'\n') {
+                stdout.write_all(&hyperlink(id, &url, &line?))?;
+                stdout.write_all(b"\n")?;
+            }
+        }
+    }
+    stdout.flush()
+}
+
+fn execute_filter(args: LinkFilterArgs) -> io::Result<()> {
+    let files = if args.files {
+        let patterns = if args.pattern.is_empty() {
+            FILE_PATTERNS
+                .iter()
+                .map(|pattern| parse_pattern(pattern).expect("the pattern is valid"))
+                .collect()
+        } else {
+            args.pattern
+        };
+        Some(FileLinker {
+            patterns,
+            template: args.template,
+            current_dir: env::current_dir()?,
+        })
+    } else {
+        None
+    };
+
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut line = Vec::new();
+    let mut in_link = false;
+
+    // URLs never span lines, so the input is processed line by line, and
+    // every line is written as soon as it's read, e.g. to follow logs.
+    loop {
+        line.clear();
+        if stdin.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        let mut offset = 0;
+        while offset < line.len() {
+            if line[offset] == 0x1B {
+                let end = escape_sequence_end(&line, offset);
+                let sequence = &line[offset..end];
+                // Text that's linked already is left as is.
+                if let Some(parameters) = sequence.strip_prefix(b"\x1B]8;") {
+                    let uri = parameters.splitn(2, |byte| *byte == b';').nth(1);
+                    in_link =
+                        uri.is_some_and(|uri| !uri.is_empty() && uri[0] != 0x1B && uri[0] != 0x07);
+                }
+                stdout.write_all(sequence)?;
+                offset = end;
+                continue;
+            }
+
+            let end = line[offset..]
+                .iter()
+                .position(|byte| *byte == 0x1B)
+                .map_or(line.len(), |end| offset + end);
+            if in_link {
+                stdout.write_all(&line[offset..end])?;
+            } else {
+                stdout.write_all(&linkify(&line[offset..end], args.emails, files.as_ref())?)?;
+            }
+            offset = end;
+        }
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// Returns the text with the URLs, and optionally the email addresses and the
+/// references to lines of files, found in it linked.
+fn linkify(text: &[u8], emails: bool, files: Option<&FileLinker>) -> io::Result<Vec<u8>> {
+    let mut linked = Vec::with_capacity(text.len());
+    let mut offset = 0;
+    let mut plain = 0;
+
+    while offset < text.len() {
+        // Matches start at word boundaries only, e.g. not in "xhttp://".
+        if offset > 0 && text[offset - 1].is_ascii_alphanumeric() {
+            offset += 1;
+            continue;
+        }
+
+        let mut found =
+            find_url(&text[offset..]).map(|end| (end, sanitize(&text[offset..offset + end], b"")));
+
+        // Paths are matched only as a whole, e.g. not "b/c" in "a/b/c".
+        if let Some(files) = files {
+            if found.is_none() && (offset == 0 || !is_path_char(text[offset - 1])) {
+                found = files.find(&text[offset..])?;
+            }
+        }
+        if emails && found.is_none() {
+            found = find_email(&text[offset..]).map(|end| {
+                let address = &text[offset..offset + end];
+                (end, format!("mailto:{}", sanitize(address, b"")))
+            });
+        }
+        match found {
+            Some((end, url)) => {
+                linked.extend_from_slice(&text[plain..offset]);
+                linked.extend(hyperlink(None, &url, &text[offset..offset + end]));
+                offset += end;
+                plain = offset;
+            }
+            None => offset += 1,
+        }
+    }
+    linked.extend_from_slice(&text[plain..]);
+    Ok(linked)
+}
+
+/// Returns the length of the URL the text starts with, if any.
+fn find_url(text: &[u8]) -> Option<usize> {
+    let scheme = SCHEMES.iter().find(|scheme| {
+        text.len() > scheme.len() && text[..scheme.len()].eq_ignore_ascii_case(scheme.as_bytes())
+    })?;
+
+    let mut end = text
+        .iter()
+        .position(|byte| {
+            !(byte.is_ascii_alphanumeric() || b"-._~:/?#[]@!$&'()*+,;=%".contains(byte))
+        })
+        .unwrap_or(text.len());
+
+    // Punctuation at the end belongs to the surrounding text rather than to
+    // the URL, and so does a closing parenthesis with no opening one, as in
+    // "(see https://example.com)".
+    loop {
+        match text[end - 1] {
+            b'.' | b',' | b':' | b';' | b'!' | b'?' | b'\'' | b'*' => end -= 1,
+            b')' if count(&text[..end], b'(') < count(&text[..end], b')') => end -= 1,
+            b']' if count(&text[..end], b'[') < count(&text[..end], b']') => end -= 1,
+            _ => break,
+        }
+    }
+    (end > scheme.len()).then_some(end)
+}
+
+/// Returns the length of the email address the text starts with, if any.
+fn find_email(text: &[u8]) -> Option<usize> {
+    let at = text
+        .iter()
+        .position(|byte| !(byte.is_ascii_alphanumeric() || b"._%+-".contains(byte)))?;
+    if at == 0 || text[at] != b'@' {
+        return None;
+    }
+
+    let domain = &text[at + 1..];
+    let mut end = domain
+        .iter()
+        .position(|byte| !(byte.is_ascii_alphanumeric() || b".-".contains(byte)))
+        .unwrap_or(domain.len());
+    while end > 0 && matches!(domain[end - 1], b'.' | b'-') {
+        end -= 1;
+    }
+
+    // The domain has at least two labels, the last one being a word.
+    let domain = &domain[..end];
+    let tld = domain.rsplit(|byte| *byte == b'.').next()?;
+    (domain.contains(&b'.') && tld.len() >= 2 && tld.iter().all(u8::is_ascii_alphabetic))
+        .then_some(at + 1 + end)
+}
+
+fn count(text: &[u8], byte: u8) -> usize {
+    text.iter().filter(|candidate| **candidate == byte).count()
+}
+
+/// Returns the position right after the escape sequence that starts at the
+/// offset, or the end of the text if the sequence doesn't end in it.
+fn escape_sequence_end(text: &[u8], offset: usize) -> usize {
+    let end = match text.get(offset + 1) {
+        // CSI sequences end with a byte in the 0x40-0x7E range.
+        Some(b'[') => text[offset + 2..]
+            .iter()
+            .position(|byte| (0x40..=0x7E).contains(byte))
+            .map(|end| offset + 2 + end + 1),
+        // OSC, DCS, APC, and PM sequences end with ST, and OSC with BEL too.
+        Some(b']' | b'P' | b'_' | b'^') => {
+            (offset + 2..text.len()).find_map(|index| match text[index] {
+                0x07 => Some(index + 1),
+                0x1B if text.get(index + 1) == Some(&b'\\') => Some(index + 2),
+                _ => None,
+            })
+        }
+        Some(_) => Some(offset + 2),
+        None => None,
+    };
+    end.unwrap_or(text.len())
+}
+
+/// Returns the text linked to the URL by means of OSC 8.
+fn hyperlink(id: Option<&str>, url: &str, text: &[u8]) -> Vec<u8> {
+    let mut linked = b"\x1B]8;".to_vec();
+    if let Some(id) = id {
+        linked.extend(format!("id={}", sanitize(id.as_bytes(), b":;")).as_bytes());
+    }
+    linked.extend(format!(";{url}\x1B\\").as_bytes());
+    linked.extend_from_slice(text);
+    linked.extend(b"\x1B]8;;\x1B\\");
+    linked
+}
+
+/// Returns the URL, with paths turned into file URLs, and the characters OSC
+/// 8 doesn't allow percent-encoded.
+fn url(url: &OsString) -> io::Result<String> {
+    let bytes = url.as_bytes();
+    let is_url = bytes
+        .iter()
+        .position(|byte| *byte == b':')
+        .is_some_and(|colon| {
+            colon > 0
+                && bytes[0].is_ascii_alphabetic()
+                && bytes[..colon]
+                    .iter()
+                    .all(|byte| byte.is_ascii_alphanumeric() || b"+-.".contains(byte))
+        });
+    if is_url {
+        return Ok(sanitize(bytes, b""));
+    }
+    cwd::file_url(&env::current_dir()?.join(Path::new(url)))
+}
+
+/// Returns the bytes with the ones outside of the printable ASCII range, as
+/// well as the given ones, percent-encoded.
+fn sanitize(bytes: &[u8], reserved: &[u8]) -> String {
+    let mut sanitized = String::with_capacity(bytes.len());
+    for byte in bytes {
+        match byte {
+            b'!'..=b'~' if !reserved.contains(byte) => sanitized.push(*byte as char),
+            _ => sanitized.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    sanitized
+}
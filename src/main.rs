@@ -1,28 +1,161 @@
+mod caps;
 mod clipboard;
+mod color;
+mod cursor;
+mod cwd;
+mod dirs;
+mod doctor;
+mod error;
+mod identify;
+mod image;
+mod json;
+mod link;
+mod mode;
+mod notify;
+mod passthrough;
+mod query;
+mod size;
+mod terminal;
+mod theme;
+mod title;
+mod tty;
+mod window;
 
 use std::io;
+use std::path::PathBuf;
+use std::process;
 
 use clap::{Parser, Subcommand};
 
+use passthrough::{Multiplexer, Passthrough};
+use terminal::Terminal;
+
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = error::EXIT_STATUS_HELP)]
 struct AppArgs {
     #[command(subcommand)]
     command: AppCommands,
+
+    /// Wrap escape sequences to pass through the given terminal multiplexers,
+    /// ordered from the innermost to the outermost one. If omitted, the
+    /// multiplexers are detected from the environment.
+    #[arg(long, global = true, value_delimiter = ',')]
+    passthrough: Option<Vec<Multiplexer>>,
+
+    /// Print escape sequences to the standard output instead of sending them
+    /// to the terminal, e.g. to embed them in a shell prompt or debug them.
+    /// Commands that read the terminal's response fail in this mode.
+    #[arg(long, global = true, default_value_t = false)]
+    print_sequence: bool,
+
+    /// The terminal device to talk to, e.g. "/dev/pts/3" for a process that
+    /// isn't attached to the terminal.
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        default_value = terminal::TTY_DEVICE,
+        env = "TTYBOX_TTY"
+    )]
+    tty: PathBuf,
 }
 
 #[derive(Subcommand, Debug)]
 enum AppCommands {
+    /// Probe the terminal for what it supports, e.g. true color, sixel, or
+    /// the kitty keyboard protocol, in a single round trip.
+    Caps(caps::CapsArgs),
     #[command(subcommand)]
     Clipboard(clipboard::ClipboardCommands),
+    /// Post a desktop notification by means of the terminal, which works on
+    /// remote machines as well.
+    Notify(notify::NotifyArgs),
+    /// Query and change the colors of the terminal.
+    #[command(subcommand)]
+    Color(color::ColorCommands),
+    /// Query and change the cursor.
+    #[command(subcommand)]
+    Cursor(cursor::CursorCommands),
+    /// Report the working directory to the terminal by means of OSC 7, so new
+    /// tabs and windows open in the same directory. Meant to be run by the
+    /// shell whenever the directory changes.
+    Cwd(cwd::CwdArgs),
+    /// Walk through the tests only a human can tell the result of, e.g.
+    /// whether a notification has appeared, and save the answers to the
+    /// profile of the terminal, which "caps" consults.
+    Doctor(doctor::DoctorArgs),
+    /// Identify the terminal emulator and the multiplexers the process is
+    /// running in, asking the terminal first and looking at the environment
+    /// if it doesn't tell.
+    Identify(identify::IdentifyArgs),
+    /// Display images in the terminal.
+    #[command(subcommand)]
+    Image(image::ImageCommands),
+    /// Print text linked to the URL by means of OSC 8, so it can be opened by
+    /// clicking it.
+    Link(link::LinkArgs),
+    /// Set and reset the DEC private modes of the terminal, e.g. bracketed
+    /// paste or mouse reporting, by number or by name.
+    #[command(subcommand)]
+    Mode(mode::ModeCommands),
+    /// Query the terminal for what it is and what it supports.
+    #[command(subcommand)]
+    Query(query::QueryCommands),
+    /// Print the size of the terminal, in characters and in pixels, and resize
+    /// it.
+    Size(size::SizeArgs),
+    /// Tell the color scheme of the terminal.
+    #[command(subcommand)]
+    Theme(theme::ThemeCommands),
+    /// Manage the title of the terminal window.
+    #[command(subcommand)]
+    Title(title::TitleCommands),
+    /// Manage the terminal window by means of XTWINOPS. Many terminals ignore
+    /// it, or do only if configured to.
+    #[command(subcommand)]
+    Window(window::WindowCommands),
 }
 
 fn execute(args: AppArgs) -> io::Result<()> {
+    let terminal = Terminal {
+        device: args.tty,
+        passthrough: match args.passthrough {
+            Some(multiplexers) => Passthrough::new(multiplexers),
+            None => Passthrough::detect(),
+        },
+        print_sequence: args.print_sequence,
+    };
+
     match args.command {
-        AppCommands::Clipboard(clipboard_args) => clipboard::execute(clipboard_args),
+        AppCommands::Caps(caps_args) => caps::execute(caps_args, &terminal),
+        AppCommands::Clipboard(clipboard_args) => clipboard::execute(clipboard_args, &terminal),
+        AppCommands::Notify(notify_args) => notify::execute(notify_args, &terminal),
+        AppCommands::Color(color_args) => color::execute(color_args, &terminal),
+        AppCommands::Cursor(cursor_args) => cursor::execute(cursor_args, &terminal),
+        AppCommands::Cwd(cwd_args) => cwd::execute(cwd_args, &terminal),
+        AppCommands::Doctor(doctor_args) => doctor::execute(doctor_args, &terminal),
+        AppCommands::Identify(identify_args) => identify::execute(identify_args, &terminal),
+        AppCommands::Image(image_args) => image::execute(image_args, &terminal),
+        AppCommands::Link(link_args) => link::execute(link_args),
+        AppCommands::Mode(mode_args) => mode::execute(mode_args, &terminal),
+        AppCommands::Query(query_args) => query::execute(query_args, &terminal),
+        AppCommands::Size(size_args) => size::execute(size_args, &terminal),
+        AppCommands::Theme(theme_args) => theme::execute(theme_args, &terminal),
+        AppCommands::Title(title_args) => title::execute(title_args, &terminal),
+        AppCommands::Window(window_args) => window::execute(window_args, &terminal),
     }
 }
 
-fn main() -> io::Result<()> {
-    execute(AppArgs::parse())
+fn main() {
+    // Invalid command lines are reported with a status of their own, so it's
+    // not confused with the failure classes above.
+    let args = AppArgs::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        process::exit(if e.use_stderr() { error::EXIT_USAGE } else { 0 })
+    });
+
+    if let Err(e) = execute(args) {
+        eprintln!("ttybox: {e}");
+        process::exit(error::exit_code(&e));
+    }
 }
@@ -0,0 +1,152 @@
+use std::io;
+
+use clap::{Args, Subcommand};
+
+use crate::error::{self, Failure};
+use crate::query;
+use crate::terminal::Terminal;
+use crate::tty::DEFAULT_TIMEOUT;
+
+/// The names the DEC private modes may be given by instead of their numbers.
+const ALIASES: &[(&str, u16)] = &[
+    ("cursor-keys", 1),
+    ("origin", 6),
+    ("autowrap", 7),
+    ("mouse-x10", 9),
+    ("cursor-blink", 12),
+    ("cursor", 25),
+    ("mouse", 1000),
+    ("mouse-drag", 1002),
+    ("mouse-motion", 1003),
+    ("focus", 1004),
+    ("mouse-utf8", 1005),
+    ("mouse-sgr", 1006),
+    ("alternate-scroll", 1007),
+    ("mouse-urxvt", 1015),
+    ("mouse-pixels", 1016),
+    ("alt-screen", 1049),
+    ("bracketed-paste", 2004),
+    ("sync-output", 2026),
+    ("grapheme-clusters", 2027),
+    ("color-scheme-reports", 2031),
+    ("in-band-resize", 2048),
+];
+
+#[derive(Subcommand, Debug)]
+pub enum ModeCommands {
+    /// Set the modes by means of DECSET.
+    Set(ModeChangeArgs),
+    /// Reset the modes by means of DECRST.
+    Reset(ModeChangeArgs),
+    /// Set the modes that are reset and reset the ones that are set, as
+    /// reported by the terminal.
+    Toggle(ModeChangeArgs),
+    /// Restore the modes saved by "--save", by means of XTRESTORE.
+    Restore(ModeArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ModeArgs {
+    /// The numbers of the DEC private modes, or their names: cursor-keys,
+    /// origin, autowrap, mouse-x10, cursor-blink, cursor, mouse, mouse-drag,
+    /// mouse-motion, focus, mouse-utf8, mouse-sgr, alternate-scroll,
+    /// mouse-urxvt, mouse-pixels, alt-screen, bracketed-paste, sync-output,
+    /// grapheme-clusters, color-scheme-reports, or in-band-resize.
+    #[arg(value_name = "MODE", required = true, value_parser = parse_mode)]
+    modes: Vec<u16>,
+}
+
+#[derive(Args, Debug)]
+pub struct ModeChangeArgs {
+    #[command(flatten)]
+    modes: ModeArgs,
+
+    /// Save the modes before changing them by means of XTSAVE, so "restore"
+    /// can change them back.
+    #[arg(long)]
+    save: bool,
+}
+
+pub fn execute(command: ModeCommands, terminal: &Terminal) -> io::Result<()> {
+    let (args, final_byte) = match command {
+        ModeCommands::Set(args) => (args, 'h'),
+        ModeCommands::Reset(args) => (args, 'l'),
+        ModeCommands::Toggle(args) => return execute_toggle(args, terminal),
+        ModeCommands::Restore(args) => {
+            return terminal.send([sequence(&args.modes, 'r').into_bytes()])
+        }
+    };
+    let mut sequences = Vec::new();
+    if args.save {
+        sequences.push(sequence(&args.modes.modes, 's').into_bytes());
+    }
+    sequences.push(sequence(&args.modes.modes, final_byte).into_bytes());
+    terminal.send(sequences)
+}
+
+fn execute_toggle(args: ModeChangeArgs, terminal: &Terminal) -> io::Result<()> {
+    let modes = &args.modes.modes;
+    let states = query::query_modes(terminal, modes, DEFAULT_TIMEOUT)?;
+    if states.is_empty() {
+        return Err(error::new(
+            Failure::Unsupported,
+            "The terminal emulator doesn't report the state of modes.",
+        ));
+    }
+
+    // Only the modes that are set or reset, as opposed to the permanent or
+    // unrecognized ones, can be toggled.
+    let state = |mode: &u16| {
+        states
+            .iter()
+            .find(|(number, _)| number == mode)
+            .map(|(_, state)| *state)
+    };
+    let unchangeable = modes
+        .iter()
+        .filter(|mode| !matches!(state(mode), Some(1 | 2)))
+        .map(u16::to_string)
+        .collect::<Vec<_>>();
+    if !unchangeable.is_empty() {
+        return Err(error::new(
+            Failure::Unsupported,
+            format!(
+                "The modes are either unrecognized or permanent: {}.",
+                unchangeable.join(", ")
+            ),
+        ));
+    }
+
+    let (set, reset): (Vec<u16>, Vec<u16>) = modes.iter().partition(|mode| state(mode) == Some(2));
+    let mut sequences = Vec::new();
+    if args.save {
+        sequences.push(sequence(modes, 's').into_bytes());
+    }
+    if !set.is_empty() {
+        sequences.push(sequence(&set, 'h').into_bytes());
+    }
+    if !reset.is_empty() {
+        sequences.push(sequence(&reset, 'l').into_bytes());
+    }
+    terminal.send(sequences)
+}
+
+/// Returns the `CSI ? Pm FINAL` sequence for the modes.
+fn sequence(modes: &[u16], final_byte: char) -> String {
+    let modes = modes
+        .iter()
+        .map(u16::to_string)
+        .collect::<Vec<_>>()
+        .join(";");
+    format!("\x1B[?{modes}{final_byte}")
+}
+
+/// Parses the DEC private mode given by either its number or its name.
+pub fn parse_mode(value: &str) -> Result<u16, String> {
+    ALIASES
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, mode)| *mode)
+        .or_else(|| value.parse().ok())
+        .ok_or_else(|| format!("'{value}' is neither a mode number nor a known mode name"))
+}
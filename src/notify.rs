@@ -0,0 +1,813 @@
+mod dbus;
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::io::AsRawFd;
+use std::process::{self, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use clap::{Args, Subcommand, ValueEnum};
+
+use crate::identify;
+use crate::query::{find, OSC_TERMINATORS};
+use crate::terminal::Terminal;
+use crate::tty;
+
+/// The maximum size of a part of an OSC 99 notification sent in a single
+/// sequence, before base64 encoding.
+const CHUNK_SIZE: usize = 3072;
+
+/// The maximum size of an OSC sequence held by the bridge until it ends.
+const MAX_SEQUENCE_SIZE: usize = 65536;
+
+/// The escape sequences terminals post desktop notifications on.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// OSC 9, introduced by iTerm2 and supported by WezTerm, foot, ghostty and
+    /// others. It has no notion of a title.
+    #[value(name = "9")]
+    Osc9,
+    /// OSC 777, introduced by urxvt and supported by foot, ghostty and others.
+    #[value(name = "777")]
+    Osc777,
+    /// OSC 99, kitty's own protocol, which supports identifiers, buttons, and
+    /// reporting the activation back.
+    #[value(name = "99")]
+    Osc99,
+    /// Pick the protocol the terminal is known to support best.
+    Auto,
+}
+
+impl Protocol {
+    /// Detects the protocol of the terminal emulator the process is running in,
+    /// falling back to the most widely supported one.
+    fn detect() -> Self {
+        match identify::detect_env()
+            .as_ref()
+            .map(|identity| identity.name.as_str())
+        {
+            Some("kitty") => Protocol::Osc99,
+            Some("rxvt" | "foot") => Protocol::Osc777,
+            _ => Protocol::Osc9,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum NotifyCommands {
+    /// Run the command, and post a notification once it exits, telling how it
+    /// went and how long it took. The exit code is the one of the command.
+    Run(NotifyRunArgs),
+    /// Bridge the notifications between D-Bus and the terminal. Without a
+    /// command, the notifications posted on the session bus, e.g. on a remote
+    /// machine with no notification daemon, are posted by means of the
+    /// terminal. With a command, the notifications it posts by means of OSC 9
+    /// or OSC 777 are posted on the session bus instead, for terminals that
+    /// don't support them.
+    Bridge(NotifyBridgeArgs),
+    /// Close the notification posted with the given identifier, e.g. once
+    /// whatever it tells about is over. OSC 99 only, other terminals ignore
+    /// it.
+    Close(NotifyCloseArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct NotifyRunArgs {
+    /// The command to run, along with its arguments.
+    #[arg(required = true, trailing_var_arg = true, value_name = "COMMAND")]
+    command: Vec<OsString>,
+
+    /// The message to post, where "{command}" stands for the command line,
+    /// "{status}" for the exit code, "{result}" for either "succeeded" or
+    /// "failed", and "{duration}" for the time the command took.
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        default_value = "'{command}' {result} in {duration}"
+    )]
+    format: String,
+
+    /// Post the notification only if the command fails.
+    #[arg(long, default_value_t = false)]
+    only_on_failure: bool,
+
+    /// Post the notification only if the command takes at least the given
+    /// number of seconds, as there's no point in being notified about
+    /// commands that are watched anyway.
+    #[arg(long, value_name = "SECONDS", default_value_t = 0)]
+    min_duration: u64,
+
+    /// The escape sequence to post the notification with.
+    #[arg(long, value_enum, default_value_t = Protocol::Auto)]
+    protocol: Protocol,
+}
+
+#[derive(Args, Debug)]
+pub struct NotifyBridgeArgs {
+    /// The command to run, along with its arguments. Its standard output is
+    /// passed through, with the notifications cut out.
+    #[arg(trailing_var_arg = true, value_name = "COMMAND")]
+    command: Vec<OsString>,
+
+    /// The escape sequence to post the notifications from the session bus
+    /// with.
+    #[arg(long, value_enum, default_value_t = Protocol::Auto)]
+    protocol: Protocol,
+}
+
+#[derive(Args, Debug)]
+pub struct NotifyCloseArgs {
+    /// The identifier of the notification to close.
+    #[arg(long, value_parser = parse_id)]
+    id: String,
+}
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct NotifyArgs {
+    #[command(subcommand)]
+    command: Option<NotifyCommands>,
+
+    /// The message to show. If omitted, the message is read from the standard
+    /// input.
+    message: Option<OsString>,
+
+    /// The title of the notification. OSC 9 has no title, so it's prepended
+    /// to the message instead.
+    #[arg(long)]
+    title: Option<OsString>,
+
+    /// The escape sequence to post the notification with.
+    #[arg(long, value_enum, default_value_t = Protocol::Auto)]
+    protocol: Protocol,
+
+    /// The identifier of the notification, so posting another one with the
+    /// same identifier updates it in place, and it can be closed by the
+    /// identifier later. OSC 99 only.
+    #[arg(long, value_parser = parse_id)]
+    id: Option<String>,
+
+    /// Add a button with the given label, can be given multiple times. OSC 99
+    /// only.
+    #[arg(long, value_name = "LABEL")]
+    button: Vec<String>,
+
+    /// Wait until the notification is activated, and print the label of the
+    /// chosen button, if any. Fails if the notification is closed instead.
+    /// OSC 99 only.
+    #[arg(long, default_value_t = false)]
+    wait: bool,
+
+    /// The urgency of the notification. Terminals that don't support it, as
+    /// well as OSC 9 and OSC 777, ignore it.
+    #[arg(long, value_enum)]
+    urgency: Option<Urgency>,
+
+    /// The icon of the notification, either the name of an icon of the icon
+    /// theme, or the path to an image file. Terminals that don't support it,
+    /// as well as OSC 9 and OSC 777, ignore it.
+    #[arg(long, value_name = "NAME|PATH")]
+    icon: Option<String>,
+
+    /// Post the notification after the given duration, e.g. "90s", "25m", or
+    /// "1h30m", instead of right away. The process detaches from the shell
+    /// and waits in the background, unless asked to stay in the foreground.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    after: Option<Duration>,
+
+    /// Wait in the foreground when posting the notification later, showing
+    /// the time left.
+    #[arg(long, default_value_t = false, requires = "after")]
+    foreground: bool,
+}
+
+/// The urgency of a notification, as defined by the desktop notifications
+/// specification.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Urgency {
+    Low = 0,
+    Normal = 1,
+    Critical = 2,
+}
+
+/// Ensures the identifier consists of the characters OSC 99 allows.
+fn parse_id(value: &str) -> Result<String, String> {
+    if !value.is_empty()
+        && value
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"-_+.".contains(&byte))
+    {
+        Ok(value.to_owned())
+    } else {
+        Err(format!("invalid identifier '{value}'"))
+    }
+}
+
+/// Parses a duration made of numbers followed by the h, m, or s suffix, e.g.
+/// "1h30m". A number without a suffix stands for seconds.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{value}'");
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for char in value.chars() {
+        let multiplier = match char {
+            '0'..='9' => {
+                number.push(char);
+                continue;
+            }
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        seconds = number
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| number.checked_mul(multiplier))
+            .and_then(|number| seconds.checked_add(number))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        seconds = number
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| seconds.checked_add(number))
+            .ok_or_else(invalid)?;
+    } else if value.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+pub fn execute(args: NotifyArgs, terminal: &Terminal) -> io::Result<()> {
+    match args.command {
+        Some(NotifyCommands::Run(run_args)) => return execute_run(run_args, terminal),
+        Some(NotifyCommands::Bridge(bridge_args)) => return execute_bridge(bridge_args, terminal),
+        Some(NotifyCommands::Close(close_args)) => {
+            return terminal
+                .send([format!("\x1B]99;i={}:p=close;\x1B\\", close_args.id).into_bytes()])
+        }
+        None => {}
+    }
+
+    let message = match args.message {
+        Some(message) => message.into_vec(),
+        None => {
+            let mut message = Vec::new();
+            io::stdin().read_to_end(&mut message)?;
+            if message.ends_with(b"\n") {
+                message.pop();
+            }
+            message
+        }
+    };
+    let title = args.title.map(OsString::into_vec);
+
+    let protocol = match args.protocol {
+        Protocol::Auto => Protocol::detect(),
+        protocol => protocol,
+    };
+    if protocol != Protocol::Osc99 && (args.id.is_some() || !args.button.is_empty() || args.wait) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Identifiers, buttons, and waiting are supported by OSC 99 only.",
+        ));
+    }
+    if args.wait && args.after.is_some() && !args.foreground {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Waiting for a notification posted later requires --foreground.",
+        ));
+    }
+
+    let icon = match args.icon {
+        // Names of icons in icon themes have no slashes, unlike paths.
+        Some(icon) if icon.contains('/') => {
+            Some(Icon::Data(fs::read(&icon).map_err(|e| {
+                io::Error::new(e.kind(), format!("Cannot read '{icon}': {e}"))
+            })?))
+        }
+        Some(icon) => Some(Icon::Name(icon)),
+        None => None,
+    };
+
+    match args.after {
+        Some(delay) if args.foreground => count_down(delay)?,
+        Some(delay) => {
+            detach(terminal)?;
+            thread::sleep(delay);
+        }
+        None => {}
+    }
+
+    if protocol == Protocol::Osc99 {
+        // Waiting requires an identifier to tell the report apart.
+        let id = args
+            .id
+            .or_else(|| args.wait.then(|| format!("ttybox-{}", process::id())));
+        let notification = Osc99 {
+            id,
+            title,
+            body: message,
+            buttons: args.button,
+            wait: args.wait,
+            urgency: args.urgency,
+            icon,
+        };
+        return notification.post(terminal);
+    }
+    post(protocol, title, message, terminal)
+}
+
+/// Waits for the given duration, showing the time left on the standard error
+/// if it's a terminal.
+fn count_down(delay: Duration) -> io::Result<()> {
+    let due = Instant::now() + delay;
+    let show = unsafe { libc::isatty(libc::STDERR_FILENO) } == 1;
+    let mut stderr = io::stderr();
+
+    loop {
+        let left = due.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        if show {
+            let seconds = left.as_secs_f64().ceil() as u64;
+            write!(
+                stderr,
+                "\r\x1B[KPosting the notification in {:02}:{:02}:{:02}",
+                seconds / 3600,
+                seconds % 3600 / 60,
+                seconds % 60
+            )?;
+        }
+        // The time left is updated as soon as the next second starts.
+        let tick = Duration::from_nanos(left.subsec_nanos().into());
+        thread::sleep(if tick.is_zero() {
+            Duration::from_secs(1)
+        } else {
+            tick
+        });
+    }
+    if show {
+        write!(stderr, "\r\x1B[K")?;
+    }
+    Ok(())
+}
+
+/// Detaches from the shell, so the process keeps running in the background
+/// while the shell moves on. The process keeps the controlling terminal to
+/// post the notification to, yet no longer holds the standard input and
+/// output, so e.g. a command substitution doesn't wait for it.
+fn detach(terminal: &Terminal) -> io::Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => {}
+        _ => process::exit(0),
+    }
+
+    // The shell hangs the background jobs up on exit, yet the notification
+    // can be posted as long as the terminal is still around, e.g. after
+    // `exec`.
+    unsafe { libc::signal(libc::SIGHUP, libc::SIG_IGN) };
+
+    let null = File::options().read(true).write(true).open("/dev/null")?;
+    let mut fds = vec![libc::STDIN_FILENO];
+    // The escape sequences are printed to the standard output on request.
+    if !terminal.print_sequence {
+        fds.push(libc::STDOUT_FILENO);
+    }
+    for fd in fds {
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Posts the notification by means of the protocol the terminal is known to
+/// support best.
+pub fn post_default(title: &str, message: &str, terminal: &Terminal) -> io::Result<()> {
+    let (title, message) = (title.as_bytes().to_vec(), message.as_bytes().to_vec());
+    match Protocol::detect() {
+        Protocol::Osc99 => Osc99 {
+            id: None,
+            title: Some(title),
+            body: message,
+            buttons: Vec::new(),
+            wait: false,
+            urgency: None,
+            icon: None,
+        }
+        .post(terminal),
+        protocol => post(protocol, Some(title), message, terminal),
+    }
+}
+
+/// Posts the notification by means of either OSC 9 or OSC 777.
+fn post(
+    protocol: Protocol,
+    title: Option<Vec<u8>>,
+    message: Vec<u8>,
+    terminal: &Terminal,
+) -> io::Result<()> {
+    let mut sequence = match protocol {
+        Protocol::Osc9 => {
+            let mut sequence = b"\x1B]9;".to_vec();
+            if let Some(title) = title {
+                sequence.extend(sanitize(&title));
+                sequence.extend(b": ");
+            }
+            sequence.extend(sanitize(&message));
+            sequence
+        }
+        // The title ends at the first semicolon, so the ones within the title
+        // are replaced, while the body extends to the end of the sequence and
+        // may contain them as is. Without a title, the message becomes the
+        // title, as notification daemons require one.
+        Protocol::Osc777 => {
+            let (title, body) = match title {
+                Some(title) => (title, message),
+                None => (message, Vec::new()),
+            };
+            let mut sequence = b"\x1B]777;notify;".to_vec();
+            sequence.extend(sanitize(&title).into_iter().map(|byte| {
+                if byte == b';' {
+                    b','
+                } else {
+                    byte
+                }
+            }));
+            sequence.push(b';');
+            sequence.extend(sanitize(&body));
+            sequence
+        }
+        Protocol::Osc99 | Protocol::Auto => unreachable!(),
+    };
+    sequence.push(b'\x07');
+    terminal.send([sequence])
+}
+
+fn execute_run(args: NotifyRunArgs, terminal: &Terminal) -> io::Result<()> {
+    let command_line = args
+        .command
+        .iter()
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let started = Instant::now();
+    let status = Command::new(&args.command[0])
+        .args(&args.command[1..])
+        .status()
+        .map_err(|e| io::Error::new(e.kind(), format!("Cannot run '{command_line}': {e}")))?;
+    let duration = started.elapsed();
+
+    let code = tty::exit_code(status);
+
+    if !(args.only_on_failure && status.success())
+        && duration >= Duration::from_secs(args.min_duration)
+    {
+        let message = args
+            .format
+            .replace("{command}", &command_line)
+            .replace("{status}", &code.to_string())
+            .replace(
+                "{result}",
+                if status.success() {
+                    "succeeded"
+                } else {
+                    "failed"
+                },
+            )
+            .replace("{duration}", &format_duration(duration))
+            .into_bytes();
+
+        let protocol = match args.protocol {
+            Protocol::Auto => Protocol::detect(),
+            protocol => protocol,
+        };
+        match protocol {
+            Protocol::Osc99 => Osc99 {
+                id: None,
+                title: None,
+                body: message,
+                buttons: Vec::new(),
+                wait: false,
+                urgency: (!status.success()).then_some(Urgency::Critical),
+                icon: None,
+            }
+            .post(terminal),
+            protocol => post(protocol, None, message, terminal),
+        }?;
+    }
+    process::exit(code)
+}
+
+fn execute_bridge(args: NotifyBridgeArgs, terminal: &Terminal) -> io::Result<()> {
+    if !args.command.is_empty() {
+        return bridge_command(&args.command);
+    }
+
+    let protocol = match args.protocol {
+        Protocol::Auto => Protocol::detect(),
+        protocol => protocol,
+    };
+    dbus::watch(|notification| {
+        let title = Some(notification.summary.into_bytes());
+        let message = notification.body.into_bytes();
+        match protocol {
+            Protocol::Osc99 => Osc99 {
+                id: None,
+                title,
+                body: message,
+                buttons: Vec::new(),
+                wait: false,
+                urgency: match notification.urgency {
+                    Some(0) => Some(Urgency::Low),
+                    Some(1) => Some(Urgency::Normal),
+                    Some(2) => Some(Urgency::Critical),
+                    _ => None,
+                },
+                icon: None,
+            }
+            .post(terminal),
+            protocol => post(protocol, title, message, terminal),
+        }
+    })
+}
+
+/// Runs the command, and posts the notifications it sends to the terminal on
+/// the session bus, while passing the rest of its output through.
+fn bridge_command(command: &[OsString]) -> io::Result<()> {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot run '{}': {e}", command[0].to_string_lossy()),
+            )
+        })?;
+
+    let mut stdout = child.stdout.take().expect("the standard output is piped");
+    let mut output = io::stdout();
+    let mut pending = Vec::new();
+    let mut buffer = [0u8; 8192];
+
+    loop {
+        let size = match stdout.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pending.extend_from_slice(&buffer[..size]);
+
+        // The output is passed through up to the next OSC sequence, which is
+        // held until it's complete. Sequences that never end, within reason,
+        // are passed through as they are.
+        loop {
+            let Some(start) = find(&pending, b"\x1B]")
+                .or_else(|| (pending.last() == Some(&0x1B)).then_some(pending.len() - 1))
+            else {
+                output.write_all(&pending)?;
+                pending.clear();
+                break;
+            };
+            output.write_all(&pending[..start])?;
+            pending.drain(..start);
+
+            let Some((end, terminator)) = OSC_TERMINATORS
+                .iter()
+                .filter_map(|terminator| {
+                    find(&pending, terminator).map(|end| (end, terminator.len()))
+                })
+                .min()
+            else {
+                if pending.len() > MAX_SEQUENCE_SIZE {
+                    output.write_all(&pending)?;
+                    pending.clear();
+                }
+                break;
+            };
+
+            let sequence: Vec<u8> = pending.drain(..end + terminator).collect();
+            match parse_notification(&sequence[2..end]) {
+                Some((title, message)) => dbus::notify(&title, &message)?,
+                None => output.write_all(&sequence)?,
+            }
+        }
+        output.flush()?;
+    }
+    output.write_all(&pending)?;
+    output.flush()?;
+
+    let status = child.wait()?;
+    process::exit(tty::exit_code(status))
+}
+
+/// Returns the title and the message of the notification posted by the OSC
+/// sequence, given without the introducer and the terminator, if it's an
+/// OSC 9 or OSC 777 notification. OSC 9 has no title, so the message is the
+/// title.
+fn parse_notification(sequence: &[u8]) -> Option<(String, String)> {
+    let text = String::from_utf8_lossy(sequence);
+    if let Some(message) = text.strip_prefix("9;") {
+        // ConEmu uses OSC 9 followed by a number for other purposes, such as
+        // reporting the progress, which is widely supported too.
+        let (number, _) = message.split_once(';').unwrap_or_default();
+        if !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        return Some((message.to_owned(), String::new()));
+    }
+    let (title, message) = text.strip_prefix("777;notify;")?.split_once(';')?;
+    Some((title.to_owned(), message.to_owned()))
+}
+
+/// Returns the duration in a human readable form, e.g. "1h 2m 3s".
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{:.1}s", duration.as_secs_f64()),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!(
+            "{}h {}m {}s",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        ),
+    }
+}
+
+/// A notification posted by means of kitty's OSC 99 protocol.
+///
+/// See https://sw.kovidgoyal.net/kitty/desktop-notifications/ for details.
+struct Osc99 {
+    id: Option<String>,
+    title: Option<Vec<u8>>,
+    body: Vec<u8>,
+    buttons: Vec<String>,
+    wait: bool,
+    urgency: Option<Urgency>,
+    icon: Option<Icon>,
+}
+
+/// The icon of a notification, either a name looked up by the desktop or the
+/// content of an image file.
+enum Icon {
+    Name(String),
+    Data(Vec<u8>),
+}
+
+impl Osc99 {
+    fn post(&self, terminal: &Terminal) -> io::Result<()> {
+        if !self.wait {
+            return terminal.send(self.sequences());
+        }
+
+        let mut tty = terminal.open()?;
+        let report = tty::with_noecho_cbreak_mode(tty.as_raw_fd(), || {
+            for sequence in self.sequences() {
+                tty.write_all(&terminal.wrap(sequence)?)?;
+            }
+            tty.flush()?;
+            self.read_report(&tty)
+        })?;
+
+        match report {
+            Report::Activated(0) => Ok(()),
+            Report::Activated(button) => match self.buttons.get(button - 1) {
+                Some(label) => writeln!(io::stdout(), "{label}"),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("The terminal has reported an unknown button {button}."),
+                )),
+            },
+            Report::Closed => Err(io::Error::other(
+                "The notification has been closed without being activated.",
+            )),
+        }
+    }
+
+    /// Returns the sequences posting the notification, one per part. The
+    /// parts are base64 encoded, so they may contain any characters, and
+    /// the last one tells the terminal the notification is complete.
+    fn sequences(&self) -> Vec<Vec<u8>> {
+        let mut parts = Vec::new();
+        if let Some(title) = &self.title {
+            parts.push(("title", title.clone()));
+        }
+        parts.push(("body", self.body.clone()));
+        if !self.buttons.is_empty() {
+            parts.push(("buttons", self.buttons.join("\u{2028}").into_bytes()));
+        }
+        if let Some(Icon::Data(data)) = &self.icon {
+            parts.push(("icon", data.clone()));
+        }
+
+        let mut metadata = String::from("e=1");
+        if let Some(id) = &self.id {
+            metadata.push_str(&format!(":i={id}"));
+        }
+        if self.wait {
+            metadata.push_str(":a=focus,report:c=1");
+        }
+
+        if let Some(urgency) = self.urgency {
+            metadata.push_str(&format!(":u={}", urgency as u8));
+        }
+        if let Some(Icon::Name(name)) = &self.icon {
+            metadata.push_str(&format!(":n={}", BASE64_STANDARD.encode(name)));
+        }
+
+        // Big parts such as icons are split into chunks, which kitty
+        // concatenates back.
+        let chunks: Vec<(&str, &[u8])> = parts
+            .iter()
+            .flat_map(|(kind, payload)| {
+                payload
+                    .chunks(CHUNK_SIZE)
+                    .chain(payload.is_empty().then_some(&[][..]))
+                    .map(move |chunk| (*kind, chunk))
+            })
+            .collect();
+        let count = chunks.len();
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, (kind, payload))| {
+                let done = u8::from(index + 1 == count);
+                let mut sequence = format!("\x1B]99;{metadata}:d={done}:p={kind};").into_bytes();
+                sequence.extend(BASE64_STANDARD.encode(payload).into_bytes());
+                sequence.extend(b"\x1B\\");
+                sequence
+            })
+            .collect()
+    }
+
+    /// Reads the input of the terminal until it reports the activation or the
+    /// closing of the notification. Anything else is discarded, including
+    /// whatever is typed meanwhile.
+    fn read_report(&self, mut tty: &File) -> io::Result<Report> {
+        let id = self.id.as_deref().unwrap_or_default();
+        let mut input = Vec::new();
+        let mut buffer = [0u8; 1024];
+
+        loop {
+            let size = match tty.read(&mut buffer) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                Ok(size) => size,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            input.extend_from_slice(&buffer[..size]);
+
+            // A report looks like `ESC ] 99 ; i=ID[:p=close] ; [BUTTON] ST`,
+            // where the empty button stands for the notification itself.
+            while let Some(start) = find(&input, b"\x1B]99;") {
+                let Some(end) = find(&input[start..], b"\x1B\\") else {
+                    break;
+                };
+                let sequence = input[start + 5..start + end].to_vec();
+                input.drain(..start + end + 2);
+
+                let text = String::from_utf8_lossy(&sequence);
+                let Some((metadata, payload)) = text.split_once(';') else {
+                    continue;
+                };
+                let metadata: Vec<&str> = metadata.split(':').collect();
+                if !metadata.contains(&format!("i={id}").as_str()) {
+                    continue;
+                }
+                if metadata.contains(&"p=close") {
+                    return Ok(Report::Closed);
+                }
+                return Ok(Report::Activated(payload.parse().unwrap_or(0)));
+            }
+        }
+    }
+}
+
+/// What the user has done to the notification, as reported by the terminal.
+enum Report {
+    /// The notification has been activated, either by clicking it, or by
+    /// pressing the button with the given 1-based number, or 0 for none.
+    Activated(usize),
+    Closed,
+}
+
+/// Returns the text with the control characters, which would end the sequence
+/// prematurely or confuse the terminal, replaced with spaces.
+fn sanitize(text: &[u8]) -> Vec<u8> {
+    text.iter()
+        .map(|byte| if byte.is_ascii_control() { b' ' } else { *byte })
+        .collect()
+}
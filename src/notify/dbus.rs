@@ -0,0 +1,142 @@
+use std::io::{self, BufRead, BufReader};
+use std::process::{Command, Stdio};
+
+use crate::error::{self, Failure};
+
+/// The match rule of the calls posting desktop notifications.
+const NOTIFY_MATCH_RULE: &str =
+    "type='method_call',interface='org.freedesktop.Notifications',member='Notify'";
+
+/// The beginnings of the headers of the messages printed by dbus-monitor.
+const HEADERS: [&str; 4] = ["method call ", "method return ", "signal ", "error "];
+
+/// A desktop notification posted on the session bus.
+#[derive(Debug)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    /// The urgency hint: 0 for low, 1 for normal, and 2 for critical.
+    pub urgency: Option<u8>,
+}
+
+/// Watches the session bus for the notifications posted on it, and calls the
+/// function for every one of them until it fails or the bus goes away.
+///
+/// The calls are observed by means of dbus-monitor, so they are seen whether
+/// or not there's a notification daemon to handle them.
+pub fn watch<F>(mut func: F) -> io::Result<()>
+where
+    F: FnMut(Notification) -> io::Result<()>,
+{
+    let mut child = Command::new("dbus-monitor")
+        .args(["--session", NOTIFY_MATCH_RULE])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => error::new(
+                Failure::Unsupported,
+                "dbus-monitor is not installed, and is needed to watch D-Bus.",
+            ),
+            _ => e,
+        })?;
+
+    let stdout = child.stdout.take().expect("the standard output is piped");
+    let mut reader = BufReader::new(stdout);
+    let mut line = Vec::new();
+    let mut message: Option<Vec<String>> = None;
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&line);
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+
+        // Every message starts with an unindented header, followed by the
+        // arguments indented by 3 spaces. Strings are printed as is, so a
+        // multiline string continues on unindented lines.
+        if HEADERS.iter().any(|header| line.starts_with(header)) {
+            message = line.ends_with("member=Notify").then(Vec::new);
+        } else if let Some(arguments) = message.as_mut() {
+            // Arrays close on a line of their own, indented as the argument.
+            match line.strip_prefix("   ") {
+                Some(argument) if !argument.starts_with([' ', ']']) && arguments.len() < 8 => {
+                    arguments.push(argument.to_owned());
+                }
+                _ => {
+                    if let Some(argument) = arguments.last_mut() {
+                        argument.push('\n');
+                        argument.push_str(line);
+                    }
+                }
+            }
+
+            // The expiration timeout is the last argument, and is printed on
+            // a line of its own, so the notification is complete once it's
+            // read.
+            if arguments.len() == 8 && arguments[7].starts_with("int32 ") {
+                let arguments = message.take().expect("the message is being read");
+                func(parse_notify(&arguments))?;
+            }
+        }
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("dbus-monitor failed: {status}")));
+    }
+    Ok(())
+}
+
+/// Returns the notification posted by the call with the given arguments, which
+/// are: app_name, replaces_id, app_icon, summary, body, actions, hints, and
+/// expire_timeout.
+fn parse_notify(arguments: &[String]) -> Notification {
+    let string = |argument: &str| {
+        argument
+            .strip_prefix("string \"")
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or_default()
+            .to_owned()
+    };
+
+    // The hints are printed as dictionary entries, a key per line followed
+    // by its value, e.g. `string "urgency"` and `variant   byte 2`.
+    let mut hint_lines = arguments[6].lines().map(str::trim);
+    let urgency = hint_lines
+        .find(|line| *line == "string \"urgency\"")
+        .and_then(|_| hint_lines.next())
+        .and_then(|line| line.strip_prefix("variant"))
+        .and_then(|line| line.trim_start().strip_prefix("byte "))
+        .and_then(|value| value.parse().ok());
+
+    Notification {
+        summary: string(&arguments[3]),
+        body: string(&arguments[4]),
+        urgency,
+    }
+}
+
+/// Posts the desktop notification on the session bus by means of notify-send.
+pub fn notify(summary: &str, body: &str) -> io::Result<()> {
+    let status = Command::new("notify-send")
+        .args(["--app-name=ttybox", "--", summary, body])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .status()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => error::new(
+                Failure::Unsupported,
+                "notify-send is not installed, and is needed to post to D-Bus.",
+            ),
+            _ => e,
+        })?;
+    if !status.success() {
+        return Err(io::Error::other(format!("notify-send failed: {status}")));
+    }
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use std::cell::OnceCell;
+use std::env;
+use std::io;
+use std::process::Command;
+
+use clap::ValueEnum;
+
+use crate::error::{self, Failure};
+
+/// GNU screen can't handle DCS strings longer than its string buffer, which is
+/// 768 bytes in recent versions but 256 bytes in older ones, so sequences
+/// passed through screen have to be chunked to the smaller of the two. Each
+/// chunk is wrapped into its own DCS sequence, and screen concatenates them
+/// back when forwarding to the outer terminal.
+const SCREEN_DCS_CHUNK_SIZE: usize = 256;
+
+/// Returns whether the process is running in Zellij. Zellij has no passthrough
+/// sequence, yet it handles OSC 52 copy sequences itself by forwarding them to
+/// the outer terminal, while paste requests aren't supported at all.
+pub fn is_zellij() -> bool {
+    env::var_os("ZELLIJ").is_some()
+}
+
+/// Terminal multiplexers run a terminal emulator of their own, and hence
+/// intercept escape sequences that are meant for the outer terminal emulator.
+/// In order to reach the outer terminal, a sequence has to be wrapped into a
+/// multiplexer specific passthrough sequence.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Multiplexer {
+    Tmux,
+    Screen,
+}
+
+impl Multiplexer {
+    /// Wraps the escape sequence into the passthrough sequence, so the
+    /// multiplexer forwards it to the outer terminal as is.
+    pub fn wrap(&self, sequence: &[u8]) -> Vec<u8> {
+        match self {
+            // tmux passes through the content of the `ESC P tmux; ... ESC \`
+            // DCS sequence, provided that all the ESC characters within the
+            // content are doubled.
+            Multiplexer::Tmux => {
+                let mut wrapped = b"\x1BPtmux;".to_vec();
+                for byte in sequence {
+                    if *byte == b'\x1B' {
+                        wrapped.push(b'\x1B');
+                    }
+                    wrapped.push(*byte);
+                }
+                wrapped.extend(b"\x1B\\");
+                wrapped
+            }
+            // GNU screen passes through the content of the `ESC P ... ESC \`
+            // DCS sequence verbatim, though the content is limited in length.
+            Multiplexer::Screen => {
+                let mut wrapped = Vec::with_capacity(sequence.len() * 2);
+                let mut rest = sequence;
+                while !rest.is_empty() {
+                    // A chunk never ends in the middle of an `ESC \` pair, as
+                    // the ESC would otherwise end up before the terminator of
+                    // the chunk, and the backslash lost on its own.
+                    let mut end = rest.len().min(SCREEN_DCS_CHUNK_SIZE);
+                    if end < rest.len() && rest[end - 1] == b'\x1B' && rest[end] == b'\\' {
+                        end -= 1;
+                    }
+                    let (chunk, tail) = rest.split_at(end);
+                    wrapped.extend(b"\x1BP");
+                    wrapped.extend(chunk);
+                    wrapped.extend(b"\x1B\\");
+                    rest = tail;
+                }
+                wrapped
+            }
+        }
+    }
+
+    /// Returns whether the multiplexer is configured to let passthrough
+    /// sequences through, as otherwise they are silently swallowed.
+    pub fn allows_passthrough(&self) -> bool {
+        match self {
+            // tmux >= 3.3 drops passthrough sequences unless `allow-passthrough`
+            // is on. Older versions have no such option and let them through
+            // unconditionally, hence a failure to query the option is fine.
+            Multiplexer::Tmux => {
+                let mut command = Command::new("tmux");
+                command.args(["display-message", "-p"]);
+                if let Some(pane) = env::var_os("TMUX_PANE") {
+                    command.arg("-t").arg(pane);
+                }
+                let output = command.arg("#{allow-passthrough}").output();
+                !matches!(output, Ok(output) if matches!(output.stdout.trim_ascii(), b"off" | b"0"))
+            }
+            Multiplexer::Screen => true,
+        }
+    }
+}
+
+/// The stack of multiplexers an escape sequence has to pass through in order to
+/// reach the terminal emulator, ordered from the innermost multiplexer (the one
+/// the process is running in) to the outermost one.
+#[derive(Debug, Default)]
+pub struct Passthrough {
+    multiplexers: Vec<Multiplexer>,
+    /// Whether the innermost multiplexer lets passthrough sequences through,
+    /// once checked, since checking spawns a process.
+    allowed: OnceCell<bool>,
+}
+
+impl Passthrough {
+    pub fn new(multiplexers: Vec<Multiplexer>) -> Self {
+        Passthrough {
+            multiplexers,
+            allowed: OnceCell::new(),
+        }
+    }
+
+    /// Detects the multiplexers the process is running in. Multiplexers
+    /// inherit the environment of the shell they have been started from, hence
+    /// the environment contains hints of the outer multiplexers too, though
+    /// nested sessions started over SSH are invisible and have to be specified
+    /// explicitly.
+    pub fn detect() -> Self {
+        // Zellij swallows passthrough sequences, and the hints of the
+        // multiplexers it may run in are of no use as it's the innermost one.
+        if is_zellij() {
+            return Passthrough::default();
+        }
+
+        let term_program = env::var("TERM_PROGRAM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        let mut multiplexers = Vec::new();
+
+        if env::var_os("TMUX").is_some() || term_program == "tmux" {
+            multiplexers.push(Multiplexer::Tmux);
+        }
+
+        // tmux sets `TERM` to `screen` by default too, thus `TERM` is a hint
+        // only when the process doesn't run within tmux.
+        if env::var_os("STY").is_some() || (multiplexers.is_empty() && term.starts_with("screen")) {
+            multiplexers.push(Multiplexer::Screen);
+        }
+        Passthrough::new(multiplexers)
+    }
+
+    /// Wraps the escape sequence into the passthrough sequences of all the
+    /// multiplexers, so it reaches the terminal emulator as is. The sequence
+    /// is wrapped for the outermost multiplexer first, as each multiplexer
+    /// unwraps one level only.
+    pub fn wrap(&self, sequence: Vec<u8>) -> io::Result<Vec<u8>> {
+        self.check()?;
+        Ok(self.enclose(sequence))
+    }
+
+    /// Wraps the escape sequence the same way as `wrap()`, yet without
+    /// checking the multiplexers, e.g. when wrapping many pieces of a single
+    /// sequence.
+    pub fn enclose(&self, sequence: Vec<u8>) -> Vec<u8> {
+        self.multiplexers
+            .iter()
+            .rev()
+            .fold(sequence, |sequence, multiplexer| {
+                multiplexer.wrap(&sequence)
+            })
+    }
+
+    /// Ensures the innermost multiplexer lets passthrough sequences through.
+    pub fn check(&self) -> io::Result<()> {
+        let allowed = *self.allowed.get_or_init(|| {
+            self.multiplexers
+                .first()
+                .is_none_or(Multiplexer::allows_passthrough)
+        });
+        if !allowed {
+            return Err(error::new(
+                Failure::Unsupported,
+                "tmux doesn't allow passthrough sequences, \
+                 run `tmux set -g allow-passthrough on` to enable them.",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the multiplexers, from the innermost to the outermost one.
+    pub fn multiplexers(&self) -> &[Multiplexer] {
+        &self.multiplexers
+    }
+
+    /// Returns whether there are no multiplexers to pass through.
+    pub fn is_empty(&self) -> bool {
+        self.multiplexers.is_empty()
+    }
+}
@@ -0,0 +1,195 @@
+mod da;
+mod decrqss;
+mod graphics;
+mod mode;
+mod termcap;
+mod unicode;
+mod version;
+
+pub use graphics::query_graphics;
+pub use mode::query_modes;
+pub use termcap::{parse_termcap, termcap_request};
+pub use version::query_version;
+
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use clap::Subcommand;
+
+use crate::error::{self, Failure};
+use crate::terminal::Terminal;
+use crate::tty::{self, read_response, set_nonblocking};
+
+/// The request to report the primary device attributes, DA1. Every terminal
+/// responds to it, so it's sent after a request that may go unanswered, and
+/// once its response arrives, the other one is known to be missing without
+/// waiting for the timeout.
+const DEVICE_ATTRIBUTES_REQUEST: &[u8] = b"\x1B[c";
+
+/// The terminators an OSC sequence may end with: BEL, which is what xterm
+/// traditionally uses, and ST (String Terminator) in its 7-bit form, which is
+/// what the standard mandates and what terminals such as foot or wezterm reply
+/// with.
+pub(crate) const OSC_TERMINATORS: [&[u8]; 2] = [b"\x07", b"\x1B\\"];
+
+#[derive(Subcommand, Debug)]
+pub enum QueryCommands {
+    /// Print the device attributes the terminal reports, DA1, DA2, and DA3,
+    /// decoded: the terminal class, the claimed features, the terminal type
+    /// and its firmware version, and the unit ID.
+    Da(da::QueryDaArgs),
+
+    /// Print the name and the version the terminal reports, XTVERSION, e.g.
+    /// "kitty(0.31.0)". It's the most reliable way to identify the terminal,
+    /// as it reaches remote processes too.
+    Version(version::QueryVersionArgs),
+
+    /// Print the values of the terminfo capabilities the terminal itself
+    /// claims, XTGETTCAP, e.g. "RGB" or "Smulx", rather than the ones of the
+    /// terminfo database. Fails if any of them isn't reported.
+    Termcap(termcap::QueryTermcapArgs),
+
+    /// Print the state of the terminal's settings, DECRQSS, e.g. the graphic
+    /// rendition or the scroll margins, or the sequences that restore it.
+    /// Fails if any of them isn't reported.
+    Decrqss(decrqss::QueryDecrqssArgs),
+
+    /// Print whether the DEC private modes are set, DECRQM, e.g. 2004 for
+    /// bracketed paste or 1049 for the alternate screen, which helps to tell
+    /// what a crashed program left behind.
+    Mode(mode::QueryModeArgs),
+
+    /// Print the number of color registers and the sixel and ReGIS geometry
+    /// limits the terminal reports, XTSMGRAPHICS, so images can be sized not
+    /// to be clipped.
+    Graphics(graphics::QueryGraphicsArgs),
+
+    /// Print how wide the terminal draws tricky strings, e.g. emoji ZWJ
+    /// sequences and flags, as measured by the cursor movement, along with
+    /// the state of the grapheme clustering mode 2027, and tell the width
+    /// convention it follows.
+    Unicode(unicode::QueryUnicodeArgs),
+}
+
+pub fn execute(command: QueryCommands, terminal: &Terminal) -> io::Result<()> {
+    match command {
+        QueryCommands::Da(args) => da::execute(args, terminal),
+        QueryCommands::Version(args) => version::execute(args, terminal),
+        QueryCommands::Termcap(args) => termcap::execute(args, terminal),
+        QueryCommands::Decrqss(args) => decrqss::execute(args, terminal),
+        QueryCommands::Mode(args) => mode::execute(args, terminal),
+        QueryCommands::Graphics(args) => graphics::execute(args, terminal),
+        QueryCommands::Unicode(args) => unicode::execute(args, terminal),
+    }
+}
+
+/// Sends the request to the terminal, and returns the response to it, which
+/// is empty if the terminal doesn't respond to such requests.
+pub fn query(terminal: &Terminal, request: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+    let mut response = exchange(terminal, request, timeout)?;
+
+    // The response to the request precedes the device attributes.
+    response.truncate(find_device_attributes(&response).unwrap_or(response.len()));
+    Ok(response)
+}
+
+/// Sends the request to the terminal followed by the device attributes
+/// request, and returns the whole response, the device attributes included.
+pub fn exchange(terminal: &Terminal, request: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+    let mut requests = request.to_vec();
+    requests.extend_from_slice(DEVICE_ATTRIBUTES_REQUEST);
+    send_and_receive(terminal, &terminal.wrap(requests)?, timeout)
+}
+
+/// Sends the request the same way as `exchange()`, yet without passing it
+/// through the multiplexers, so the innermost one responds, e.g. when the
+/// request draws on the screen.
+pub fn exchange_local(
+    terminal: &Terminal,
+    request: &[u8],
+    timeout: Duration,
+) -> io::Result<Vec<u8>> {
+    let mut requests = request.to_vec();
+    requests.extend_from_slice(DEVICE_ATTRIBUTES_REQUEST);
+    send_and_receive(terminal, &requests, timeout)
+}
+
+fn send_and_receive(
+    terminal: &Terminal,
+    requests: &[u8],
+    timeout: Duration,
+) -> io::Result<Vec<u8>> {
+    let mut tty = terminal.open()?;
+    tty::with_noecho_cbreak_mode(tty.as_raw_fd(), || {
+        tty.write_all(requests)?;
+        tty.flush()?;
+
+        set_nonblocking(tty.as_raw_fd())?;
+        read_response(&tty, timeout, |response| {
+            find_device_attributes(response).is_some()
+        })
+    })
+    .map_err(|e| match e.kind() {
+        io::ErrorKind::TimedOut if error::failure(&e).is_none() => error::new(
+            Failure::Timeout,
+            "The terminal emulator hasn't responded in time.",
+        ),
+        _ => e,
+    })
+}
+
+/// Returns the payload of the OSC sequence that starts with the prefix, e.g.
+/// `ESC ] l` for the window title, without the prefix and the terminator.
+pub fn find_osc<'a>(response: &'a [u8], prefix: &[u8]) -> Option<&'a [u8]> {
+    let start = find(response, prefix)? + prefix.len();
+    let payload = &response[start..];
+    OSC_TERMINATORS
+        .iter()
+        .filter_map(|terminator| find(payload, terminator))
+        .min()
+        .map(|end| &payload[..end])
+}
+
+/// Returns the parameters of the CSI sequence that starts with the prefix and
+/// ends with the final byte, e.g. `ESC [` and `R` for the cursor position,
+/// without the prefix and the final byte.
+pub fn find_csi<'a>(response: &'a [u8], prefix: &[u8], final_byte: u8) -> Option<&'a [u8]> {
+    let mut offset = 0;
+    while let Some(start) = find(&response[offset..], prefix) {
+        let parameters = &response[offset + start + prefix.len()..];
+        let end = parameters
+            .iter()
+            .position(|byte| !byte.is_ascii_digit() && *byte != b';')?;
+        if parameters[end] == final_byte {
+            return Some(&parameters[..end]);
+        }
+        offset += start + prefix.len();
+    }
+    None
+}
+
+/// Returns the position of the response to the device attributes request,
+/// which looks like `CSI ? Ps ; ... c`, once it's complete. Other reports may
+/// start alike, e.g. the ones of the modes, `CSI ? Pd ; Ps $ y`.
+pub fn find_device_attributes(response: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(start) = find(&response[offset..], b"\x1B[?") {
+        let parameters = &response[offset + start + 3..];
+        let end = parameters
+            .iter()
+            .position(|byte| !byte.is_ascii_digit() && *byte != b';')?;
+        if parameters[end] == b'c' {
+            return Some(offset + start);
+        }
+        offset += start + 3;
+    }
+    None
+}
+
+/// Returns the position of the first occurrence of the needle in the haystack.
+pub(crate) fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,183 @@
+use std::io;
+
+use clap::{Args, ValueEnum};
+
+use super::{exchange, find_csi, find_device_attributes, find_osc};
+use crate::json;
+use crate::terminal::{Terminal, TimeoutArgs};
+
+/// The requests to report the secondary and the tertiary device attributes,
+/// DA2 and DA3. The primary ones are requested by every query anyway.
+const DEVICE_ATTRIBUTES_REQUESTS: &[u8] = b"\x1B[>c\x1B[=c";
+
+/// The terminal classes of DA1, as of the VT terminals claimed to be.
+const CLASSES: &[(u32, &str)] = &[
+    (1, "VT100"),
+    (6, "VT102"),
+    (62, "VT220"),
+    (63, "VT320"),
+    (64, "VT420"),
+    (65, "VT510"),
+];
+
+/// The features of DA1, as of xterm's ctlseqs.
+const FEATURES: &[(u32, &str)] = &[
+    (1, "132 columns"),
+    (2, "printer"),
+    (3, "ReGIS graphics"),
+    (4, "sixel graphics"),
+    (6, "selective erase"),
+    (8, "user-defined keys"),
+    (9, "national replacement character sets"),
+    (15, "technical characters"),
+    (16, "locator port"),
+    (17, "terminal state interrogation"),
+    (18, "user windows"),
+    (21, "horizontal scrolling"),
+    (22, "ANSI color"),
+    (28, "rectangular editing"),
+    (29, "ANSI text locator"),
+    (42, "ISO Latin-2 character set"),
+    (44, "PCTerm"),
+    (45, "soft key mapping"),
+    (46, "ASCII emulation"),
+    (52, "clipboard access"),
+];
+
+/// The terminal types of DA2.
+const TYPES: &[(u32, &str)] = &[
+    (0, "VT100"),
+    (1, "VT220"),
+    (2, "VT240"),
+    (18, "VT330"),
+    (19, "VT340"),
+    (24, "VT320"),
+    (41, "VT420"),
+    (61, "VT510"),
+    (64, "VT520"),
+    (65, "VT525"),
+];
+
+/// The formats the device attributes are printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DaFormat {
+    /// "NAME: VALUE" per line.
+    Text,
+    /// {"primary": {"raw": …, "class": …, "features": [{"code": …, "name":
+    /// …}]}, "secondary": {"raw": …, "type": …, "version": …}, "tertiary":
+    /// {"raw": …}}, with null for the attributes that aren't reported.
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct QueryDaArgs {
+    /// The format to print the device attributes in.
+    #[arg(long, value_enum, default_value_t = DaFormat::Text)]
+    format: DaFormat,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(args: QueryDaArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    // The reports look like `CSI ? Pc ; Pf ... c`, `CSI > Pp ; Pv ; Pc c`,
+    // and `DCS ! | UNIT-ID ST`. The primary one comes last, as it's been
+    // requested last.
+    let response = exchange(terminal, DEVICE_ATTRIBUTES_REQUESTS, timeout)?;
+    let start = find_device_attributes(&response).expect("the primary attributes are reported");
+    let primary = find_csi(&response[start..], b"\x1B[?", b'c').unwrap_or_default();
+    let secondary = find_csi(&response[..start], b"\x1B[>", b'c');
+    let tertiary = find_osc(&response[..start], b"\x1BP!|")
+        .map(|unit_id| String::from_utf8_lossy(unit_id).into_owned());
+
+    let class = parameters(primary).first().copied();
+    let features = parameters(primary).into_iter().skip(1).collect::<Vec<_>>();
+    match args.format {
+        DaFormat::Text => {
+            println!("class: {}", describe(CLASSES, class));
+            for feature in &features {
+                println!("feature: {}", describe(FEATURES, Some(*feature)));
+            }
+            if let Some(secondary) = secondary.map(parameters) {
+                println!("type: {}", describe(TYPES, secondary.first().copied()));
+                if let Some(version) = secondary.get(1) {
+                    println!("version: {version}");
+                }
+            }
+            if let Some(unit_id) = &tertiary {
+                println!("unit id: {unit_id}");
+            }
+        }
+        DaFormat::Json => {
+            let features = features
+                .iter()
+                .map(|feature| {
+                    let name = lookup(FEATURES, *feature).map_or("null".to_owned(), json::quote);
+                    format!(r#"{{"code": {feature}, "name": {name}}}"#)
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let primary = format!(
+                r#"{{"raw": {}, "class": {}, "features": [{features}]}}"#,
+                json::quote(&String::from_utf8_lossy(primary)),
+                number(class),
+            );
+            let secondary = secondary.map_or("null".to_owned(), |report| {
+                let secondary = parameters(report);
+                format!(
+                    r#"{{"raw": {}, "type": {}, "version": {}}}"#,
+                    json::quote(&String::from_utf8_lossy(report)),
+                    number(secondary.first().copied()),
+                    number(secondary.get(1).copied()),
+                )
+            });
+            let tertiary = tertiary.map_or("null".to_owned(), |unit_id| {
+                format!(r#"{{"raw": {}}}"#, json::quote(&unit_id))
+            });
+            println!(
+                r#"{{"primary": {primary}, "secondary": {secondary}, "tertiary": {tertiary}}}"#
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the numeric parameters of the report. An empty parameter stands
+/// for 0.
+fn parameters(report: &[u8]) -> Vec<u32> {
+    if report.is_empty() {
+        return Vec::new();
+    }
+    report
+        .split(|byte| *byte == b';')
+        .map(|parameter| {
+            String::from_utf8_lossy(parameter)
+                .parse()
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn lookup(names: &[(u32, &'static str)], code: u32) -> Option<&'static str> {
+    names
+        .iter()
+        .find(|(other, _)| *other == code)
+        .map(|(_, name)| *name)
+}
+
+/// Returns the name of the code along with the code, e.g. "VT220 (62)".
+fn describe(names: &[(u32, &'static str)], code: Option<u32>) -> String {
+    match code {
+        Some(code) => match lookup(names, code) {
+            Some(name) => format!("{name} ({code})"),
+            None => format!("unknown ({code})"),
+        },
+        None => "unknown".to_owned(),
+    }
+}
+
+fn number(value: Option<u32>) -> String {
+    value.map_or("null".to_owned(), |value| value.to_string())
+}
@@ -0,0 +1,317 @@
+use std::io::{self, Write};
+
+use clap::{Args, ValueEnum};
+
+use super::{find, find_osc, query};
+use crate::error::{self, Failure};
+use crate::json;
+use crate::terminal::{Terminal, TimeoutArgs};
+
+/// The prefix of the reports of the settings the terminal knows.
+const REPORT_PREFIX: &[u8] = b"\x1BP1$r";
+
+/// The settings whose state is requested, by the final bytes, intermediates
+/// included, of the control sequences that set them.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    /// The graphic rendition, i.e. the attributes and the colors text is
+    /// written with, SGR.
+    Sgr,
+    /// The top and the bottom scroll margins, DECSTBM.
+    Margins,
+    /// The left and the right scroll margins, DECSLRM.
+    LeftRightMargins,
+    /// The shape of the cursor and whether it blinks, DECSCUSR.
+    CursorStyle,
+    /// The conformance level and whether C1 controls are sent as 7-bit or
+    /// 8-bit codes, DECSCL.
+    Conformance,
+    /// Whether the characters written are protected from selective erase,
+    /// DECSCA.
+    Protection,
+    /// The number of lines per page, DECSLPP.
+    Lines,
+    /// The number of columns per page, DECSCPP.
+    Columns,
+}
+
+impl Setting {
+    /// The final bytes of the control sequence, which the request ends with,
+    /// and the report of the setting's state does too.
+    fn suffix(self) -> &'static str {
+        match self {
+            Setting::Sgr => "m",
+            Setting::Margins => "r",
+            Setting::LeftRightMargins => "s",
+            Setting::CursorStyle => " q",
+            Setting::Conformance => "\"p",
+            Setting::Protection => "\"q",
+            Setting::Lines => "t",
+            Setting::Columns => "$|",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Setting::Sgr => "sgr",
+            Setting::Margins => "margins",
+            Setting::LeftRightMargins => "left-right-margins",
+            Setting::CursorStyle => "cursor-style",
+            Setting::Conformance => "conformance",
+            Setting::Protection => "protection",
+            Setting::Lines => "lines",
+            Setting::Columns => "columns",
+        }
+    }
+
+    /// Returns the human-readable description of the setting's parameters.
+    fn describe(self, parameters: &str) -> String {
+        let values = parameters
+            .split(';')
+            .map(|value| value.parse::<u32>().ok())
+            .collect::<Vec<_>>();
+        let value = |index: usize| values.get(index).copied().flatten();
+        match self {
+            Setting::Sgr => describe_sgr(parameters),
+            Setting::Margins => match (value(0), value(1)) {
+                (Some(top), Some(bottom)) => format!("lines {top} to {bottom}"),
+                _ => "the whole screen".to_owned(),
+            },
+            Setting::LeftRightMargins => match (value(0), value(1)) {
+                (Some(left), Some(right)) => format!("columns {left} to {right}"),
+                _ => "the whole screen".to_owned(),
+            },
+            Setting::CursorStyle => match value(0).unwrap_or(0) {
+                0 | 1 => "blinking block",
+                2 => "steady block",
+                3 => "blinking underline",
+                4 => "steady underline",
+                5 => "blinking bar",
+                6 => "steady bar",
+                _ => "unknown",
+            }
+            .to_owned(),
+            Setting::Conformance => {
+                let level = match value(0) {
+                    Some(61) => "VT100",
+                    Some(62) => "VT200",
+                    Some(63) => "VT300",
+                    Some(64) => "VT400",
+                    Some(65) => "VT500",
+                    _ => "unknown",
+                };
+                let controls = match value(1) {
+                    Some(1) => "7-bit",
+                    _ if value(0) == Some(61) => "7-bit",
+                    _ => "8-bit",
+                };
+                format!("{level} level, {controls} controls")
+            }
+            Setting::Protection => match value(0) {
+                Some(1) => "protected",
+                _ => "unprotected",
+            }
+            .to_owned(),
+            Setting::Lines => format!("{parameters} lines"),
+            Setting::Columns => format!("{parameters} columns"),
+        }
+    }
+}
+
+/// The formats the settings are printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecrqssFormat {
+    /// "NAME: PARAMETERS (DESCRIPTION)" per line.
+    Text,
+    /// {"NAME": {"raw": …, "description": …}, …}, with null for the settings
+    /// that aren't reported.
+    Json,
+    /// The control sequences that set the settings back to their reported
+    /// state, to be written to the terminal later on.
+    Sequence,
+}
+
+#[derive(Args, Debug)]
+pub struct QueryDecrqssArgs {
+    /// The settings to report.
+    #[arg(value_name = "SETTING", value_enum, required = true)]
+    settings: Vec<Setting>,
+
+    /// The format to print the settings in.
+    #[arg(long, value_enum, default_value_t = DecrqssFormat::Text)]
+    format: DecrqssFormat,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(args: QueryDecrqssArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    // The requests look like `DCS $ q SUFFIX ST`, and the reports like `DCS 1
+    // $ r PARAMETERS SUFFIX ST`, or `DCS 0 $ r ST` for the unknown settings.
+    // The reports are told apart by their suffixes, as the unknown ones are
+    // indistinguishable.
+    let request = args
+        .settings
+        .iter()
+        .map(|setting| format!("\x1BP$q{}\x1B\\", setting.suffix()))
+        .collect::<String>();
+    let response = query(terminal, request.as_bytes(), timeout)?;
+    let mut reports = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = find(&response[offset..], REPORT_PREFIX) {
+        let Some(report) = find_osc(&response[offset + start..], REPORT_PREFIX) else {
+            break;
+        };
+        offset += start + REPORT_PREFIX.len() + report.len();
+        reports.push(String::from_utf8_lossy(report).into_owned());
+    }
+    let parameters = args
+        .settings
+        .iter()
+        .map(|setting| {
+            reports.iter().find_map(|report| {
+                let parameters = report.strip_suffix(setting.suffix())?;
+                // The suffixes of some settings end with the ones of others,
+                // e.g. `"q` and ` q`, and thus the parameters must be numbers.
+                parameters
+                    .bytes()
+                    .all(|byte| byte.is_ascii_digit() || b";:".contains(&byte))
+                    .then_some(parameters)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    match args.format {
+        DecrqssFormat::Text => {
+            for (setting, parameters) in args.settings.iter().zip(&parameters) {
+                if let Some(parameters) = parameters {
+                    println!(
+                        "{}: {parameters} ({})",
+                        setting.name(),
+                        setting.describe(parameters)
+                    );
+                }
+            }
+        }
+        DecrqssFormat::Json => {
+            let members = args
+                .settings
+                .iter()
+                .zip(&parameters)
+                .map(|(setting, parameters)| {
+                    let value = parameters.map_or("null".to_owned(), |parameters| {
+                        format!(
+                            r#"{{"raw": {}, "description": {}}}"#,
+                            json::quote(parameters),
+                            json::quote(&setting.describe(parameters))
+                        )
+                    });
+                    format!("{}: {value}", json::quote(setting.name()))
+                })
+                .collect::<Vec<_>>();
+            println!("{{{}}}", members.join(", "));
+            return Ok(());
+        }
+        DecrqssFormat::Sequence => {
+            let mut stdout = io::stdout().lock();
+            for (setting, parameters) in args.settings.iter().zip(&parameters) {
+                if let Some(parameters) = parameters {
+                    write!(stdout, "\x1B[{parameters}{}", setting.suffix())?;
+                }
+            }
+            stdout.flush()?;
+        }
+    }
+
+    let missing = args
+        .settings
+        .iter()
+        .zip(&parameters)
+        .filter(|(_, parameters)| parameters.is_none())
+        .map(|(setting, _)| setting.name())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(error::new(
+            Failure::Unsupported,
+            format!(
+                "The terminal emulator doesn't report the settings: {}.",
+                missing.join(", ")
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the human-readable description of the SGR parameters, e.g. "bold,
+/// foreground 1" for `1;31`.
+fn describe_sgr(parameters: &str) -> String {
+    let mut attributes = Vec::new();
+    let mut values = parameters.split(';');
+    while let Some(value) = values.next() {
+        // Colors are given either by parameters of their own, e.g. `38;5;N`,
+        // or by subparameters, e.g. `38:5:N`.
+        let mut subparameters = value.split(':');
+        let code = subparameters.next().unwrap_or_default();
+        let attribute = match code.parse::<u32>().unwrap_or(0) {
+            0 => continue,
+            1 => "bold".to_owned(),
+            2 => "faint".to_owned(),
+            3 => "italic".to_owned(),
+            4 => match subparameters.next() {
+                Some("0") => continue,
+                Some("2") => "double underline".to_owned(),
+                Some("3") => "curly underline".to_owned(),
+                Some("4") => "dotted underline".to_owned(),
+                Some("5") => "dashed underline".to_owned(),
+                _ => "underline".to_owned(),
+            },
+            5 => "blink".to_owned(),
+            7 => "inverse".to_owned(),
+            8 => "invisible".to_owned(),
+            9 => "strikethrough".to_owned(),
+            21 => "double underline".to_owned(),
+            53 => "overline".to_owned(),
+            code @ 30..=37 => format!("foreground {}", code - 30),
+            code @ 40..=47 => format!("background {}", code - 40),
+            code @ 90..=97 => format!("foreground {}", code - 90 + 8),
+            code @ 100..=107 => format!("background {}", code - 100 + 8),
+            code @ (38 | 48 | 58) => {
+                let layer = match code {
+                    38 => "foreground",
+                    48 => "background",
+                    _ => "underline color",
+                };
+                let mut rest = subparameters.collect::<Vec<_>>();
+                if rest.is_empty() {
+                    rest.extend(values.next());
+                    match rest[..] {
+                        ["5"] => rest.extend(values.next()),
+                        ["2"] => rest.extend(values.by_ref().take(3)),
+                        _ => {}
+                    }
+                }
+                match rest[..] {
+                    ["5", slot] => format!("{layer} {slot}"),
+                    ["2", red, green, blue] | ["2", _, red, green, blue] => {
+                        match (red.parse::<u8>(), green.parse::<u8>(), blue.parse::<u8>()) {
+                            (Ok(red), Ok(green), Ok(blue)) => {
+                                format!("{layer} #{red:02x}{green:02x}{blue:02x}")
+                            }
+                            _ => format!("{layer} unknown"),
+                        }
+                    }
+                    _ => format!("{layer} unknown"),
+                }
+            }
+            code => format!("attribute {code}"),
+        };
+        attributes.push(attribute);
+    }
+    if attributes.is_empty() {
+        "default".to_owned()
+    } else {
+        attributes.join(", ")
+    }
+}
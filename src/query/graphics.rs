@@ -0,0 +1,164 @@
+use std::io;
+use std::time::Duration;
+
+use clap::{Args, ValueEnum};
+
+use super::{find, query};
+use crate::error::{self, Failure};
+use crate::terminal::{Terminal, TimeoutArgs};
+
+/// The requests to report the current and the maximum values of the number
+/// of color registers, the sixel geometry, and the ReGIS geometry,
+/// XTSMGRAPHICS. The reports don't tell the current values from the maximum
+/// ones, so they're told by the order, the current one going first.
+const GRAPHICS_REQUEST: &[u8] = b"\x1B[?1;1S\x1B[?1;4S\x1B[?2;1S\x1B[?2;4S\x1B[?3;1S\x1B[?3;4S";
+
+/// The current and the maximum values of a graphics attribute.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limit<T> {
+    pub current: Option<T>,
+    pub maximum: Option<T>,
+}
+
+/// The graphics attributes the terminal reports. The geometries are given in
+/// pixels, as width and height.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Graphics {
+    pub color_registers: Limit<u32>,
+    pub sixel_geometry: Limit<[u32; 2]>,
+    pub regis_geometry: Limit<[u32; 2]>,
+}
+
+/// The formats the graphics attributes are printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphicsFormat {
+    /// "NAME: CURRENT (maximum MAXIMUM)" per line, leaving out the unknown
+    /// values.
+    Text,
+    /// {"color_registers": {"current": …, "maximum": …}, "sixel_geometry":
+    /// {"current": {"width": …, "height": …}, "maximum": …}, "regis_geometry":
+    /// …}, with null for the unknown values.
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct QueryGraphicsArgs {
+    /// The format to print the graphics attributes in.
+    #[arg(long, value_enum, default_value_t = GraphicsFormat::Text)]
+    format: GraphicsFormat,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(args: QueryGraphicsArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    let graphics = query_graphics(terminal, timeout)?;
+    if graphics == Graphics::default() {
+        return Err(error::new(
+            Failure::Unsupported,
+            "The terminal emulator doesn't report its graphics attributes.",
+        ));
+    }
+
+    let number = |value: u32| value.to_string();
+    match args.format {
+        GraphicsFormat::Text => {
+            let geometry = |[width, height]: [u32; 2]| format!("{width}x{height}");
+            print_limit("color registers", graphics.color_registers, number);
+            print_limit("sixel geometry", graphics.sixel_geometry, geometry);
+            print_limit("regis geometry", graphics.regis_geometry, geometry);
+        }
+        GraphicsFormat::Json => {
+            let geometry =
+                |[width, height]: [u32; 2]| format!(r#"{{"width": {width}, "height": {height}}}"#);
+            println!(
+                r#"{{"color_registers": {}, "sixel_geometry": {}, "regis_geometry": {}}}"#,
+                json_limit(graphics.color_registers, number),
+                json_limit(graphics.sixel_geometry, geometry),
+                json_limit(graphics.regis_geometry, geometry),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_limit<T: Copy>(name: &str, limit: Limit<T>, format: impl Fn(T) -> String) {
+    match (limit.current, limit.maximum) {
+        (Some(current), Some(maximum)) => {
+            println!("{name}: {} (maximum {})", format(current), format(maximum))
+        }
+        (Some(current), None) => println!("{name}: {}", format(current)),
+        (None, Some(maximum)) => println!("{name}: maximum {}", format(maximum)),
+        (None, None) => {}
+    }
+}
+
+fn json_limit<T: Copy>(limit: Limit<T>, format: impl Fn(T) -> String) -> String {
+    let value = |value: Option<T>| value.map_or("null".to_owned(), &format);
+    format!(
+        r#"{{"current": {}, "maximum": {}}}"#,
+        value(limit.current),
+        value(limit.maximum)
+    )
+}
+
+/// Returns the graphics attributes the terminal reports. The ones it doesn't
+/// support are left unknown.
+pub fn query_graphics(terminal: &Terminal, timeout: Duration) -> io::Result<Graphics> {
+    // The reports look like `CSI ? Pi ; Ps ; Pv S`, with Ps being 0 on
+    // success, and two values for the geometries.
+    let response = query(terminal, GRAPHICS_REQUEST, timeout)?;
+    let mut reports: [Vec<Option<Vec<u32>>>; 3] = Default::default();
+    let mut offset = 0;
+    while let Some(start) = find(&response[offset..], b"\x1B[?") {
+        offset += start + 3;
+        let parameters = &response[offset..];
+        let Some(end) = parameters
+            .iter()
+            .position(|byte| !byte.is_ascii_digit() && *byte != b';')
+        else {
+            break;
+        };
+        if parameters[end] != b'S' {
+            continue;
+        }
+        let values = String::from_utf8_lossy(&parameters[..end])
+            .split(';')
+            .map(|value| value.parse::<u32>().ok())
+            .collect::<Option<Vec<_>>>();
+        if let Some([item, status, values @ ..]) = values.as_deref() {
+            if let Some(reports) = reports.get_mut((*item as usize).wrapping_sub(1)) {
+                reports.push((*status == 0).then(|| values.to_vec()));
+            }
+        }
+    }
+
+    let [color_registers, sixel_geometry, regis_geometry] = reports;
+    let number = |values: &[u32]| values.first().copied();
+    let geometry = |values: &[u32]| match values {
+        [width, height, ..] => Some([*width, *height]),
+        _ => None,
+    };
+    Ok(Graphics {
+        color_registers: limit(&color_registers, number),
+        sixel_geometry: limit(&sixel_geometry, geometry),
+        regis_geometry: limit(&regis_geometry, geometry),
+    })
+}
+
+/// Returns the current and the maximum values out of the reports of the
+/// requests to read them, in that order.
+fn limit<T>(reports: &[Option<Vec<u32>>], parse: impl Fn(&[u32]) -> Option<T>) -> Limit<T> {
+    let value = |index: usize| {
+        reports
+            .get(index)
+            .and_then(Option::as_deref)
+            .and_then(&parse)
+    };
+    Limit {
+        current: value(0),
+        maximum: value(1),
+    }
+}
@@ -0,0 +1,163 @@
+use std::io;
+use std::time::Duration;
+
+use clap::{Args, ValueEnum};
+
+use super::{find, query};
+use crate::error::{self, Failure};
+use crate::json;
+use crate::terminal::{Terminal, TimeoutArgs};
+
+/// The names of the well-known DEC private modes.
+const MODES: &[(u16, &str)] = &[
+    (1, "application cursor keys"),
+    (3, "132 columns"),
+    (5, "reverse video"),
+    (6, "origin mode"),
+    (7, "auto-wrap"),
+    (9, "X10 mouse reporting"),
+    (12, "blinking cursor"),
+    (25, "visible cursor"),
+    (47, "alternate screen"),
+    (66, "application keypad"),
+    (69, "left and right margins"),
+    (1000, "mouse button reporting"),
+    (1002, "mouse drag reporting"),
+    (1003, "mouse motion reporting"),
+    (1004, "focus reporting"),
+    (1005, "UTF-8 mouse encoding"),
+    (1006, "SGR mouse encoding"),
+    (1007, "alternate scroll"),
+    (1015, "urxvt mouse encoding"),
+    (1016, "SGR-pixels mouse encoding"),
+    (1036, "meta sends escape"),
+    (1047, "alternate screen"),
+    (1048, "saved cursor"),
+    (1049, "alternate screen with saved cursor"),
+    (2004, "bracketed paste"),
+    (2026, "synchronized output"),
+    (2027, "grapheme clustering"),
+    (2031, "color scheme reporting"),
+    (2048, "in-band resize reporting"),
+];
+
+/// The states of the modes, as reported by DECRPM.
+const STATES: [&str; 5] = [
+    "unrecognized",
+    "set",
+    "reset",
+    "permanently set",
+    "permanently reset",
+];
+
+/// The formats the modes are printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModeFormat {
+    /// "MODE: STATE (NAME)" per line.
+    Text,
+    /// [{"mode": …, "name": …, "state": …}, …], with null for the name of
+    /// the modes that aren't well-known.
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct QueryModeArgs {
+    /// The numbers of the DEC private modes, e.g. 2004 for bracketed paste,
+    /// or their names, as of "mode set".
+    #[arg(value_name = "MODE", required = true, value_parser = crate::mode::parse_mode)]
+    modes: Vec<u16>,
+
+    /// The format to print the modes in.
+    #[arg(long, value_enum, default_value_t = ModeFormat::Text)]
+    format: ModeFormat,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(args: QueryModeArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    let states = query_modes(terminal, &args.modes, timeout)?;
+    if states.is_empty() {
+        return Err(error::new(
+            Failure::Unsupported,
+            "The terminal emulator doesn't report the state of modes.",
+        ));
+    }
+    let state = |mode: u16| {
+        states
+            .iter()
+            .find(|(number, _)| *number == mode)
+            .map_or(STATES[0], |(_, state)| {
+                STATES.get(usize::from(*state)).unwrap_or(&STATES[0])
+            })
+    };
+    let name = |mode: u16| {
+        MODES
+            .iter()
+            .find(|(number, _)| *number == mode)
+            .map(|(_, name)| *name)
+    };
+
+    match args.format {
+        ModeFormat::Text => {
+            for mode in &args.modes {
+                match name(*mode) {
+                    Some(name) => println!("{mode}: {} ({name})", state(*mode)),
+                    None => println!("{mode}: {}", state(*mode)),
+                }
+            }
+        }
+        ModeFormat::Json => {
+            let modes = args
+                .modes
+                .iter()
+                .map(|mode| {
+                    format!(
+                        r#"{{"mode": {mode}, "name": {}, "state": {}}}"#,
+                        name(*mode).map_or("null".to_owned(), json::quote),
+                        json::quote(state(*mode)),
+                    )
+                })
+                .collect::<Vec<_>>();
+            println!("[{}]", modes.join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the states the terminal reports for the DEC private modes, by
+/// number: 0 if the mode isn't recognized, 1 if it's set, 2 if it's reset, 3
+/// if it's permanently set, and 4 if it's permanently reset. The modes the
+/// terminal doesn't report, usually all or none, are missing.
+pub fn query_modes(
+    terminal: &Terminal,
+    modes: &[u16],
+    timeout: Duration,
+) -> io::Result<Vec<(u16, u8)>> {
+    // The modes are requested in one go, with requests like `CSI ? MODE $ p`,
+    // and the reports look like `CSI ? MODE ; STATE $ y`.
+    let request = modes
+        .iter()
+        .map(|mode| format!("\x1B[?{mode}$p"))
+        .collect::<String>();
+    let response = query(terminal, request.as_bytes(), timeout)?;
+
+    let mut states = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = find(&response[offset..], b"\x1B[?") {
+        offset += start + 3;
+        let parameters = &response[offset..];
+        let Some(end) = find(parameters, b"$y") else {
+            break;
+        };
+        let report = String::from_utf8_lossy(&parameters[..end]);
+        if let Some((mode, state)) = report.split_once(';') {
+            if let (Ok(mode), Ok(state)) = (mode.parse(), state.parse()) {
+                states.push((mode, state));
+            }
+        }
+    }
+    Ok(states)
+}
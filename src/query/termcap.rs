@@ -0,0 +1,189 @@
+use std::fmt::Write as _;
+use std::io;
+use std::time::Duration;
+
+use clap::{Args, ValueEnum};
+
+use super::{find, find_osc, query};
+use crate::error::{self, Failure};
+use crate::json;
+use crate::terminal::{Terminal, TimeoutArgs};
+
+/// The prefix of the reports of the capabilities the terminal knows.
+const REPORT_PREFIX: &[u8] = b"\x1BP1+r";
+
+/// The formats the capabilities are printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TermcapFormat {
+    /// "NAME=VALUE" per line, with the control characters of the value
+    /// escaped as by infocmp, or just "NAME" for boolean capabilities.
+    Text,
+    /// {"NAME": "VALUE", …}, with true for boolean capabilities, and null for
+    /// the ones that aren't reported.
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct QueryTermcapArgs {
+    /// The names of the capabilities, as of terminfo, e.g. "RGB", "Smulx",
+    /// or "kUP".
+    #[arg(value_name = "NAME", required = true)]
+    names: Vec<String>,
+
+    /// The format to print the capabilities in.
+    #[arg(long, value_enum, default_value_t = TermcapFormat::Text)]
+    format: TermcapFormat,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(args: QueryTermcapArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    let capabilities = query_termcap(terminal, &args.names, timeout)?;
+    let values = args
+        .names
+        .iter()
+        .map(|name| {
+            capabilities
+                .iter()
+                .find(|(capability, _)| capability == name)
+                .map(|(_, value)| value.as_deref())
+        })
+        .collect::<Vec<_>>();
+
+    match args.format {
+        TermcapFormat::Text => {
+            for (name, value) in args.names.iter().zip(&values) {
+                match value {
+                    Some(Some(value)) => println!("{name}={}", escape(value)),
+                    Some(None) => println!("{name}"),
+                    None => {}
+                }
+            }
+        }
+        TermcapFormat::Json => {
+            let members = args
+                .names
+                .iter()
+                .zip(&values)
+                .map(|(name, value)| {
+                    let value = match value {
+                        Some(Some(value)) => json::quote(value),
+                        Some(None) => "true".to_owned(),
+                        None => "null".to_owned(),
+                    };
+                    format!("{}: {value}", json::quote(name))
+                })
+                .collect::<Vec<_>>();
+            println!("{{{}}}", members.join(", "));
+            return Ok(());
+        }
+    }
+
+    let missing = args
+        .names
+        .iter()
+        .zip(&values)
+        .filter(|(_, value)| value.is_none())
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(error::new(
+            Failure::Unsupported,
+            format!(
+                "The terminal emulator doesn't report the capabilities: {}.",
+                missing.join(", ")
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the capabilities the terminal reports, by name, with none as the
+/// value of boolean capabilities. The capabilities the terminal doesn't know
+/// are missing.
+pub fn query_termcap(
+    terminal: &Terminal,
+    names: &[String],
+    timeout: Duration,
+) -> io::Result<Vec<(String, Option<String>)>> {
+    let response = query(terminal, termcap_request(names).as_bytes(), timeout)?;
+    Ok(parse_termcap(&response))
+}
+
+/// Returns the requests to report the capabilities. Each capability is
+/// requested on its own, as some terminals stop at the first unknown one
+/// when given many. The request looks like `DCS + q HEX-NAME ST`.
+pub fn termcap_request<S: AsRef<str>>(names: &[S]) -> String {
+    names
+        .iter()
+        .map(|name| format!("\x1BP+q{}\x1B\\", encode(name.as_ref())))
+        .collect()
+}
+
+/// Returns the capabilities reported within the response, which look like
+/// `DCS 1 + r HEX-NAME = HEX-VALUE ST`, or `DCS 0 + r ST` for the unknown
+/// ones.
+pub fn parse_termcap(response: &[u8]) -> Vec<(String, Option<String>)> {
+    let mut capabilities = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = find(&response[offset..], REPORT_PREFIX) {
+        let Some(report) = find_osc(&response[offset + start..], REPORT_PREFIX) else {
+            break;
+        };
+        offset += start + REPORT_PREFIX.len() + report.len();
+
+        let report = String::from_utf8_lossy(report);
+        let (name, value) = match report.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (report.as_ref(), None),
+        };
+        let value = match value {
+            Some(value) => decode(value).map(Some),
+            None => Some(None),
+        };
+        if let (Some(name), Some(value)) = (decode(name), value) {
+            capabilities.push((name, value));
+        }
+    }
+    capabilities
+}
+
+fn encode(text: &str) -> String {
+    text.bytes().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02X}");
+        hex
+    })
+}
+
+fn decode(hex: &str) -> Option<String> {
+    // from_str_radix() would take a sign, e.g. "+1", for a digit.
+    if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Returns the value with its control characters escaped as by infocmp, e.g.
+/// `\E` for the escape and `^G` for the bell.
+fn escape(value: &str) -> String {
+    value.chars().fold(String::new(), |mut escaped, char| {
+        match char {
+            '\x1B' => escaped.push_str("\\E"),
+            '\\' => escaped.push_str("\\\\"),
+            '\x7F' => escaped.push_str("^?"),
+            char if char.is_ascii_control() => {
+                escaped.push('^');
+                escaped.push(char::from(char as u8 + b'@'));
+            }
+            char => escaped.push(char),
+        }
+        escaped
+    })
+}
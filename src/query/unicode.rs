@@ -0,0 +1,160 @@
+use std::io;
+
+use clap::{Args, ValueEnum};
+
+use super::{exchange_local, find, find_device_attributes};
+use crate::error::{self, Failure};
+use crate::json;
+use crate::terminal::{Terminal, TimeoutArgs};
+
+/// The strings whose width is measured, along with the widths they have when
+/// drawn as grapheme clusters, and when drawn code point by code point as by
+/// wcwidth().
+const SAMPLES: &[(&str, &str, usize, usize)] = &[
+    ("cjk", "\u{4E16}", 2, 2),
+    ("combining", "e\u{301}", 1, 1),
+    ("emoji", "\u{1F600}", 2, 2),
+    ("text-presentation", "\u{263A}", 1, 1),
+    ("emoji-presentation", "\u{2764}\u{FE0F}", 2, 1),
+    ("skin-tone", "\u{1F44D}\u{1F3FD}", 2, 4),
+    ("zwj-sequence", "\u{1F469}\u{200D}\u{1F4BB}", 2, 4),
+    ("flag", "\u{1F1FA}\u{1F1E6}", 2, 2),
+];
+
+/// The state of the grapheme clustering mode, DECRQM 2027.
+const GRAPHEME_CLUSTERING_REQUEST: &[u8] = b"\x1B[?2027$p";
+
+/// The formats the widths are printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnicodeFormat {
+    /// "NAME: VALUE" per line.
+    Text,
+    /// {"mode_2027": …, "widths": {"NAME": …}, "convention": …}, with null
+    /// for the unknown values.
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct QueryUnicodeArgs {
+    /// The format to print the widths in.
+    #[arg(long, value_enum, default_value_t = UnicodeFormat::Text)]
+    format: UnicodeFormat,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(args: QueryUnicodeArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    // Each sample is drawn at the start of the line, followed by the request
+    // to report the cursor position, DSR 6, whose column tells the width. The
+    // line is cleared and the cursor restored afterwards. The samples are
+    // drawn by the innermost multiplexer, if any, as that's the one programs
+    // have to align their output for.
+    let mut request = b"\x1B7".to_vec();
+    request.extend_from_slice(GRAPHEME_CLUSTERING_REQUEST);
+    for (_, sample, _, _) in SAMPLES {
+        request.extend_from_slice(format!("\r{sample}\x1B[6n").as_bytes());
+    }
+    request.extend_from_slice(b"\r\x1B[2K\x1B8");
+    let response = exchange_local(terminal, &request, timeout)?;
+    let response = &response[..find_device_attributes(&response).unwrap_or(response.len())];
+
+    let mode = find_mode_state(response);
+    let widths = find_columns(response)
+        .into_iter()
+        .map(|column| column.saturating_sub(1))
+        .collect::<Vec<_>>();
+    if widths.len() != SAMPLES.len() {
+        return Err(error::new(
+            Failure::Unsupported,
+            "The terminal emulator doesn't report the cursor position.",
+        ));
+    }
+
+    let matches = |expected: fn(&(&str, &str, usize, usize)) -> usize| {
+        SAMPLES
+            .iter()
+            .zip(&widths)
+            .all(|(sample, width)| expected(sample) == *width)
+    };
+    let convention = if matches(|(_, _, clusters, _)| *clusters) {
+        "grapheme clusters"
+    } else if matches(|(_, _, _, wcwidth)| *wcwidth) {
+        "wcwidth"
+    } else if widths[2] == 1 {
+        "wcwidth before Unicode 9, with narrow emoji"
+    } else {
+        "mixed"
+    };
+    let mode = mode.map(|state| match state {
+        1 => "set",
+        2 => "reset",
+        3 => "permanently set",
+        4 => "permanently reset",
+        _ => "unrecognized",
+    });
+
+    match args.format {
+        UnicodeFormat::Text => {
+            println!("mode 2027: {}", mode.unwrap_or("unrecognized"));
+            for ((name, _, _, _), width) in SAMPLES.iter().zip(&widths) {
+                println!("{name}: {width}");
+            }
+            println!("convention: {convention}");
+        }
+        UnicodeFormat::Json => {
+            let widths = SAMPLES
+                .iter()
+                .zip(&widths)
+                .map(|((name, _, _, _), width)| format!("{}: {width}", json::quote(name)))
+                .collect::<Vec<_>>();
+            println!(
+                r#"{{"mode_2027": {}, "widths": {{{}}}, "convention": {}}}"#,
+                mode.map_or("null".to_owned(), json::quote),
+                widths.join(", "),
+                json::quote(convention),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the state of the grapheme clustering mode, reported as `CSI ?
+/// 2027 ; STATE $ y`.
+fn find_mode_state(response: &[u8]) -> Option<u8> {
+    let start = find(response, b"\x1B[?2027;")? + 8;
+    let end = find(&response[start..], b"$y")?;
+    String::from_utf8_lossy(&response[start..start + end])
+        .parse()
+        .ok()
+}
+
+/// Returns the columns of the cursor position reports, which look like `CSI
+/// ROW ; COLUMN R`, in order.
+fn find_columns(response: &[u8]) -> Vec<usize> {
+    let mut columns = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = find(&response[offset..], b"\x1B[") {
+        offset += start + 2;
+        let parameters = &response[offset..];
+        let Some(end) = parameters
+            .iter()
+            .position(|byte| !byte.is_ascii_digit() && *byte != b';')
+        else {
+            break;
+        };
+        if parameters[end] != b'R' {
+            continue;
+        }
+        let report = String::from_utf8_lossy(&parameters[..end]);
+        if let Some(column) = report
+            .split_once(';')
+            .and_then(|(_, column)| column.parse().ok())
+        {
+            columns.push(column);
+        }
+    }
+    columns
+}
@@ -0,0 +1,42 @@
+use std::io;
+use std::time::Duration;
+
+use clap::Args;
+
+use super::{find_osc, query};
+use crate::error::{self, Failure};
+use crate::terminal::{Terminal, TimeoutArgs};
+
+/// The request to report the name and the version of the terminal, XTVERSION.
+const VERSION_REQUEST: &[u8] = b"\x1B[>0q";
+
+#[derive(Args, Debug)]
+pub struct QueryVersionArgs {
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(args: QueryVersionArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    match query_version(terminal, timeout)? {
+        Some(version) => println!("{version}"),
+        None => {
+            return Err(error::new(
+                Failure::Unsupported,
+                "The terminal emulator doesn't report its name and version.",
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name and the version the terminal reports, e.g. `kitty(0.31.0)`
+/// or `WezTerm 20240203`, or none if it doesn't report them.
+pub fn query_version(terminal: &Terminal, timeout: Duration) -> io::Result<Option<String>> {
+    // The report looks like `DCS > | NAME VERSION ST`, the format of the
+    // payload being up to the terminal.
+    let response = query(terminal, VERSION_REQUEST, timeout)?;
+    Ok(find_osc(&response, b"\x1BP>|")
+        .map(|version| String::from_utf8_lossy(version).trim().to_owned()))
+}
@@ -0,0 +1,254 @@
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::process;
+use std::time::Duration;
+
+use clap::{ArgGroup, Args, Subcommand, ValueEnum};
+
+use crate::error::{self, Failure};
+use crate::query;
+use crate::terminal::{Terminal, TimeoutArgs};
+use crate::tty::{self, DEFAULT_TIMEOUT};
+
+/// The requests to report the size of the text area in characters, in
+/// pixels, and the size of a character cell in pixels, XTWINOPS 18, 14, and
+/// 16.
+const SIZE_REQUEST: &[u8] = b"\x1B[18t\x1B[14t\x1B[16t";
+
+/// The formats the size is printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeFormat {
+    /// "NAME VALUE" per line, e.g. "rows 50", leaving out the unknown values.
+    Text,
+    /// {"rows": …, "columns": …, "width": …, "height": …, "cell_width": …,
+    /// "cell_height": …}, with null for the unknown values.
+    Json,
+}
+
+#[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct SizeArgs {
+    #[command(subcommand)]
+    command: Option<SizeCommands>,
+
+    /// The format to print the size in.
+    #[arg(long, value_enum, default_value_t = SizeFormat::Text)]
+    format: SizeFormat,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SizeCommands {
+    /// Resize the text area of the terminal window by means of XTWINOPS, e.g.
+    /// to record a demo at a fixed size. Many terminals ignore it, or do only
+    /// if configured to.
+    Set(SizeSetArgs),
+}
+
+#[derive(Args, Debug)]
+#[command(group(
+    ArgGroup::new("size")
+        .required(true)
+        .multiple(true)
+        .args(["rows", "columns", "pixels"])
+))]
+pub struct SizeSetArgs {
+    /// The number of rows to resize to. If omitted, the rows are left as they
+    /// are.
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    rows: Option<u16>,
+
+    /// The number of columns to resize to. If omitted, the columns are left as
+    /// they are.
+    #[arg(
+        long,
+        alias = "cols",
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    columns: Option<u16>,
+
+    /// The size in pixels to resize to, e.g. "1280x720", instead of the size
+    /// in characters.
+    #[arg(
+        long,
+        value_name = "WIDTHxHEIGHT",
+        conflicts_with_all = ["rows", "columns"],
+        value_parser = parse_pixels
+    )]
+    pixels: Option<(u32, u32)>,
+
+    /// Run the command with the terminal resized, and restore the size once
+    /// it exits, however it exits. The exit code is the one of the command.
+    #[arg(long, default_value_t = false, requires = "command")]
+    restore: bool,
+
+    /// The command to run, along with its arguments.
+    #[arg(trailing_var_arg = true, value_name = "COMMAND", requires = "restore")]
+    command: Vec<OsString>,
+}
+
+/// Parses the size in pixels given as "WIDTHxHEIGHT".
+fn parse_pixels(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid size '{value}', expected WIDTHxHEIGHT");
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(invalid()),
+    }
+}
+
+/// The size of the text area of the terminal.
+#[derive(Debug, Default)]
+pub struct Size {
+    pub rows: Option<u32>,
+    pub columns: Option<u32>,
+    /// The width and the height in pixels.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// The width and the height of a character cell in pixels.
+    pub cell_width: Option<u32>,
+    pub cell_height: Option<u32>,
+}
+
+pub fn execute(args: SizeArgs, terminal: &Terminal) -> io::Result<()> {
+    if let Some(SizeCommands::Set(set_args)) = args.command {
+        return execute_set(set_args, terminal);
+    }
+
+    let timeout = args.timeout.duration();
+
+    let size = query_size(timeout, terminal)?;
+    let fields = [
+        ("rows", size.rows),
+        ("columns", size.columns),
+        ("width", size.width),
+        ("height", size.height),
+        ("cell_width", size.cell_width),
+        ("cell_height", size.cell_height),
+    ];
+    match args.format {
+        SizeFormat::Text => {
+            for (name, value) in fields {
+                if let Some(value) = value {
+                    println!("{name} {value}");
+                }
+            }
+        }
+        SizeFormat::Json => {
+            let members = fields
+                .iter()
+                .map(|(name, value)| match value {
+                    Some(value) => format!("\"{name}\": {value}"),
+                    None => format!("\"{name}\": null"),
+                })
+                .collect::<Vec<_>>();
+            println!("{{{}}}", members.join(", "));
+        }
+    }
+    Ok(())
+}
+
+fn execute_set(args: SizeSetArgs, terminal: &Terminal) -> io::Result<()> {
+    // Omitted parameters leave the height or the width as it is.
+    let parameter = |value: Option<u16>| value.map(|value| value.to_string()).unwrap_or_default();
+    let resize = match args.pixels {
+        Some((width, height)) => format!("\x1B[4;{height};{width}t"),
+        None => format!(
+            "\x1B[8;{};{}t",
+            parameter(args.rows),
+            parameter(args.columns)
+        ),
+    };
+    if !args.restore {
+        return terminal.send([resize.into_bytes()]);
+    }
+
+    let size = query_size(DEFAULT_TIMEOUT, terminal)?;
+    let restore = match (args.pixels, size.width, size.height) {
+        (Some(_), Some(width), Some(height)) => format!("\x1B[4;{height};{width}t"),
+        (Some(_), _, _) => {
+            return Err(error::new(
+                Failure::Unsupported,
+                "The terminal emulator doesn't report the size in pixels to restore.",
+            ))
+        }
+        (None, _, _) => format!(
+            "\x1B[8;{};{}t",
+            size.rows.expect("the rows are known"),
+            size.columns
+                .map(|columns| columns.to_string())
+                .unwrap_or_default()
+        ),
+    };
+    terminal.send([resize.into_bytes()])?;
+
+    let status = tty::run_then(&args.command, || terminal.send([restore.into_bytes()]))?;
+    process::exit(tty::exit_code(status))
+}
+
+/// Returns the size of the text area, as reported by the terminal, or as
+/// known to the terminal device if the terminal doesn't report it.
+pub fn query_size(timeout: Duration, terminal: &Terminal) -> io::Result<Size> {
+    let tty = terminal.open()?;
+    let mut size = Size::default();
+
+    // The terminal device knows the size in characters, and in pixels if the
+    // terminal tells it, which is not the case for remote sessions.
+    let mut winsize: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(tty.as_raw_fd(), libc::TIOCGWINSZ, &mut winsize) } == 0 {
+        let known = |value: u16| (value > 0).then_some(u32::from(value));
+        size.rows = known(winsize.ws_row);
+        size.columns = known(winsize.ws_col);
+        size.width = known(winsize.ws_xpixel);
+        size.height = known(winsize.ws_ypixel);
+    }
+
+    // The reports look like `CSI 8 ; ROWS ; COLUMNS t`, `CSI 4 ; HEIGHT ;
+    // WIDTH t`, and `CSI 6 ; HEIGHT ; WIDTH t`.
+    let response = match query::query(terminal, SIZE_REQUEST, timeout) {
+        Ok(response) => response,
+        Err(e) if error::failure(&e) == Some(Failure::Timeout) && size.rows.is_some() => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    if let Some([rows, columns]) = parse_report(&response, b"\x1B[8;") {
+        (size.rows, size.columns) = (Some(rows), Some(columns));
+    }
+    if let Some([height, width]) = parse_report(&response, b"\x1B[4;") {
+        (size.width, size.height) = (Some(width), Some(height));
+    }
+    if let Some([height, width]) = parse_report(&response, b"\x1B[6;") {
+        (size.cell_width, size.cell_height) = (Some(width), Some(height));
+    }
+
+    // The size of a cell is derived from the others if it's not reported.
+    if size.cell_width.is_none() {
+        if let (Some(width), Some(columns)) = (size.width, size.columns) {
+            size.cell_width = Some(width / columns);
+        }
+    }
+    if size.cell_height.is_none() {
+        if let (Some(height), Some(rows)) = (size.height, size.rows) {
+            size.cell_height = Some(height / rows);
+        }
+    }
+
+    if size.rows.is_none() {
+        return Err(error::new(
+            Failure::Unsupported,
+            "Neither the terminal emulator nor the terminal device report the size.",
+        ));
+    }
+    Ok(size)
+}
+
+/// Returns both numbers of the XTWINOPS report that starts with the prefix,
+/// unless either is zero, i.e. unknown, the same as with the terminal device.
+fn parse_report(response: &[u8], prefix: &[u8]) -> Option<[u32; 2]> {
+    let report = query::find_csi(response, prefix, b't')?;
+    let report = String::from_utf8_lossy(report);
+    let (first, second) = report.split_once(';')?;
+    Some([first.parse().ok()?, second.parse().ok()?]).filter(|numbers| !numbers.contains(&0))
+}
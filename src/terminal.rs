@@ -0,0 +1,214 @@
+use std::fs::File;
+use std::io::{self, IoSlice, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Args;
+
+use crate::error::{self, Failure};
+use crate::passthrough::Passthrough;
+use crate::tty;
+
+/// The options of the commands that wait for the terminal to respond.
+#[derive(Args, Debug, Default)]
+pub struct TimeoutArgs {
+    /// The maximum time to wait for the terminal to respond, in milliseconds.
+    #[arg(long, value_name = "MS")]
+    timeout: Option<u64>,
+}
+
+impl TimeoutArgs {
+    pub fn duration(&self) -> Duration {
+        self.timeout
+            .map(Duration::from_millis)
+            .unwrap_or(tty::DEFAULT_TIMEOUT)
+    }
+}
+
+/// The controlling terminal associated with the process group of that process.
+/// It can be used to write to and read from the terminal no matter how output
+/// has been redirected.
+pub const TTY_DEVICE: &str = "/dev/tty";
+
+/// The terminal emulator the escape sequences are sent to, along with the means
+/// to reach it.
+#[derive(Debug)]
+pub struct Terminal {
+    /// The terminal device, the controlling terminal unless requested
+    /// otherwise.
+    pub device: PathBuf,
+
+    pub passthrough: Passthrough,
+
+    /// Whether to print the escape sequences to the standard output instead of
+    /// sending them to the terminal, e.g. to embed them in a shell prompt.
+    pub print_sequence: bool,
+}
+
+impl Terminal {
+    /// Returns whether the escape sequences can be sent.
+    pub fn is_available(&self) -> bool {
+        self.print_sequence || self.open_device(false).is_ok()
+    }
+
+    /// Sends the escape sequences at once, each one wrapped to pass through the
+    /// multiplexers.
+    pub fn send(&self, sequences: impl IntoIterator<Item = Vec<u8>>) -> io::Result<()> {
+        self.passthrough.check()?;
+        let mut output = Vec::new();
+        for sequence in sequences {
+            output.extend(self.passthrough.enclose(sequence));
+        }
+
+        if self.print_sequence {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&output)?;
+            return stdout.flush();
+        }
+        self.open_device(false)?.write_all(&output)
+    }
+
+    /// Opens the terminal device to query the terminal, i.e. to send escape
+    /// sequences and read the responses. The sequences have to be wrapped with
+    /// `wrap()` before being sent.
+    pub fn open(&self) -> io::Result<File> {
+        if self.print_sequence {
+            return Err(error::new(
+                Failure::Unsupported,
+                "The terminal can't be queried when printing escape sequences.",
+            ));
+        }
+        self.open_device(true)
+    }
+
+    /// Pushes the input into the input queue of the terminal device, as if it
+    /// was typed by the user, so it's read by whatever program is running in
+    /// the foreground.
+    pub fn type_input(&self, input: &[u8]) -> io::Result<()> {
+        if self.print_sequence {
+            return Err(error::new(
+                Failure::Unsupported,
+                "The input can't be typed when printing escape sequences.",
+            ));
+        }
+        let tty = self.open_device(false)?;
+        tty::simulate_input(tty.as_raw_fd(), input).map_err(|e| match e.raw_os_error() {
+            // Linux 6.2 and later may be built or configured to reject TIOCSTI
+            // (see the dev.tty.legacy_tiocsti sysctl), and only privileged
+            // processes may type into a terminal other than the controlling one.
+            Some(libc::EIO | libc::EPERM) => error::new(
+                Failure::Unsupported,
+                format!(
+                    "Cannot type into the terminal '{}': the system doesn't allow it.",
+                    self.device.display()
+                ),
+            ),
+            _ => e,
+        })
+    }
+
+    /// Returns the writer to send an escape sequence piece by piece, e.g. to
+    /// stream a huge one. Each piece is wrapped to pass through the
+    /// multiplexers on its own, and they concatenate the pieces back.
+    pub fn writer(&self) -> io::Result<SequenceWriter<'_>> {
+        self.passthrough.check()?;
+        let output: Box<dyn Write> = if self.print_sequence {
+            Box::new(io::stdout().lock())
+        } else {
+            Box::new(self.open_device(false)?)
+        };
+        Ok(SequenceWriter {
+            output,
+            passthrough: &self.passthrough,
+        })
+    }
+
+    /// Wraps the escape sequence to pass through the multiplexers.
+    pub fn wrap(&self, sequence: Vec<u8>) -> io::Result<Vec<u8>> {
+        self.passthrough.wrap(sequence)
+    }
+
+    fn open_device(&self, read: bool) -> io::Result<File> {
+        let file = File::options()
+            .read(read)
+            .write(true)
+            .open(&self.device)
+            .map_err(|e| {
+                let device = self.device.display();
+                match (e.kind(), e.raw_os_error()) {
+                    // Opening the controlling terminal fails with ENXIO when
+                    // the process has none, e.g. when run by cron.
+                    (_, Some(libc::ENXIO)) => error::new(
+                        Failure::NoTty,
+                        format!(
+                            "Cannot open the terminal '{device}': the process has no \
+                             controlling terminal."
+                        ),
+                    ),
+                    (io::ErrorKind::NotFound, _) => error::new(
+                        Failure::NoTty,
+                        format!("Cannot open the terminal '{device}': it doesn't exist."),
+                    ),
+                    (io::ErrorKind::PermissionDenied, _) => io::Error::new(
+                        e.kind(),
+                        format!(
+                            "Cannot open the terminal '{device}': permission denied, \
+                             terminals are accessible by their owners only."
+                        ),
+                    ),
+                    _ => io::Error::new(
+                        e.kind(),
+                        format!("Cannot open the terminal '{device}': {e}"),
+                    ),
+                }
+            })?;
+
+        if unsafe { libc::isatty(file.as_raw_fd()) } == 0 {
+            return Err(error::new(
+                Failure::NoTty,
+                format!("'{}' isn't a terminal.", self.device.display()),
+            ));
+        }
+        Ok(file)
+    }
+}
+
+/// The writer sending an escape sequence to the terminal piece by piece.
+pub struct SequenceWriter<'a> {
+    output: Box<dyn Write>,
+    passthrough: &'a Passthrough,
+}
+
+impl SequenceWriter<'_> {
+    /// Writes the pieces of the sequence at once, by means of vectored writes
+    /// so the pieces aren't copied into a single buffer.
+    pub fn write(&mut self, pieces: &[&[u8]]) -> io::Result<()> {
+        let wrapped: Vec<Vec<u8>>;
+        let mut slices: Vec<IoSlice> = if self.passthrough.is_empty() {
+            pieces.iter().map(|piece| IoSlice::new(piece)).collect()
+        } else {
+            wrapped = pieces
+                .iter()
+                .map(|piece| self.passthrough.enclose(piece.to_vec()))
+                .collect();
+            wrapped.iter().map(|piece| IoSlice::new(piece)).collect()
+        };
+
+        // Write::write_all_vectored() isn't stable yet.
+        let mut slices = &mut slices[..];
+        while !slices.is_empty() {
+            match self.output.write_vectored(slices) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(size) => IoSlice::advance_slices(&mut slices, size),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
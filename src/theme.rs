@@ -0,0 +1,430 @@
+mod builtin;
+mod preview;
+mod scheme;
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::time::Duration;
+
+use clap::builder::PossibleValuesParser;
+use clap::{Args, Subcommand};
+
+use scheme::{Scheme, SchemeFormat};
+
+use crate::color::{self, Target};
+use crate::error::{self, Failure};
+use crate::query::{self, find};
+use crate::terminal::{Terminal, TimeoutArgs};
+use crate::tty::{self, DEFAULT_TIMEOUT};
+
+/// The luminance below which a background is dark. Both black and white text
+/// have the same contrast against a background of this luminance.
+const DARK_LUMINANCE: f64 = 0.179;
+
+/// The requests to turn the reports of color scheme changes, DEC mode 2031,
+/// on and off.
+const ENABLE_THEME_REPORTS: &[u8] = b"\x1B[?2031h";
+const DISABLE_THEME_REPORTS: &[u8] = b"\x1B[?2031l";
+
+/// The sequences resetting the palette, the foreground, the background, and
+/// the cursor colors to the ones the terminal is configured with.
+const RESET_COLORS: &[u8] = b"\x1B]104\x1B\\\x1B]110\x1B\\\x1B]111\x1B\\\x1B]112\x1B\\";
+
+/// The exit code of `detect` for a light background, so it's not confused with
+/// the failures.
+const EXIT_LIGHT: i32 = 10;
+
+#[derive(Subcommand, Debug)]
+pub enum ThemeCommands {
+    /// Print whether the background of the terminal is either "dark" or
+    /// "light", e.g. to pick a color scheme to match. The exit code is 0 for
+    /// a dark background, and 10 for a light one.
+    Detect(ThemeDetectArgs),
+    /// Watch the terminal switching between dark and light color schemes, e.g.
+    /// following the desktop, and print either "dark" or "light" on every
+    /// switch. Requires a terminal supporting DEC mode 2031, such as recent
+    /// versions of ghostty, contour, and kitty.
+    Watch(ThemeWatchArgs),
+    /// Print the colors of the terminal, as reported by the terminal, as a
+    /// theme file, e.g. to carry the current theme over to another terminal.
+    Export(ThemeExportArgs),
+    /// Change the colors of the terminal to the ones of the theme file, e.g.
+    /// to recolor a remote terminal without its own configuration. The colors
+    /// the theme file doesn't give are left as they are.
+    Apply(ThemeApplyArgs),
+    /// Preview the theme file, or the bundled scheme, by printing a sample of
+    /// text in its colors, e.g. to pick a scheme over SSH. If neither is
+    /// given, the current colors of the terminal are previewed.
+    Preview(ThemePreviewArgs),
+    /// Print the names of the base16 schemes bundled with ttybox, one per
+    /// line.
+    List,
+}
+
+#[derive(Args, Debug)]
+pub struct ThemeApplyArgs {
+    /// The theme file, either of alacritty, kitty, base16, or as exported in
+    /// JSON. If "-", the theme is read from the standard input.
+    #[arg(required_unless_present_any = ["reset", "builtin"])]
+    file: Option<PathBuf>,
+
+    /// Apply the bundled scheme of the name instead, as listed by "list".
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with = "file",
+        value_parser = PossibleValuesParser::new(builtin::names())
+    )]
+    builtin: Option<String>,
+
+    /// The format of the theme file. If omitted, it's told by the contents.
+    #[arg(long, value_enum)]
+    format: Option<SchemeFormat>,
+
+    /// Reset the colors to the ones the terminal is configured with instead,
+    /// undoing the themes applied.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["file", "builtin"])]
+    reset: bool,
+
+    /// Print the sequences instead of sending them to the terminal.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ThemePreviewArgs {
+    /// The theme file, in any of the formats "apply" accepts. If "-", the
+    /// theme is read from the standard input.
+    file: Option<PathBuf>,
+
+    /// Preview the bundled scheme of the name instead, as listed by "list".
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with = "file",
+        value_parser = PossibleValuesParser::new(builtin::names())
+    )]
+    builtin: Option<String>,
+
+    /// The format of the theme file. If omitted, it's told by the contents.
+    #[arg(long, value_enum)]
+    format: Option<SchemeFormat>,
+
+    /// Apply the theme for as long as a key isn't pressed, and restore the
+    /// colors then, instead of drawing the sample in true colors. This shows
+    /// the default colors and the cursor as well.
+    #[arg(long, default_value_t = false)]
+    apply: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ThemeExportArgs {
+    /// The format of the theme file.
+    #[arg(long, value_enum, default_value_t = SchemeFormat::Kitty)]
+    format: SchemeFormat,
+
+    /// Export the whole 256-color palette, not just the 16 ANSI colors. The
+    /// base16 format always takes the palette slots 0 to 21, along with the
+    /// bright colors as base24 if they differ from the normal ones.
+    #[arg(long, default_value_t = false)]
+    extended: bool,
+
+    /// The name of the theme, if the format has one.
+    #[arg(long, default_value = "ttybox")]
+    name: String,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ThemeWatchArgs {
+    /// Run the shell command on every switch instead of printing, with the
+    /// TTYBOX_THEME environment variable set to either "dark" or "light".
+    #[arg(long, value_name = "COMMAND")]
+    exec: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ThemeDetectArgs {
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(command: ThemeCommands, terminal: &Terminal) -> io::Result<()> {
+    match command {
+        ThemeCommands::Detect(args) => execute_detect(args, terminal),
+        ThemeCommands::Watch(args) => execute_watch(args, terminal),
+        ThemeCommands::Export(args) => execute_export(args, terminal),
+        ThemeCommands::Apply(args) => execute_apply(args, terminal),
+        ThemeCommands::Preview(args) => execute_preview(args, terminal),
+        ThemeCommands::List => {
+            let mut stdout = io::stdout().lock();
+            for name in builtin::names() {
+                writeln!(stdout, "{name}")?;
+            }
+            stdout.flush()
+        }
+    }
+}
+
+fn execute_detect(args: ThemeDetectArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    let background = color::query_color(Target::Bg, timeout, terminal)?;
+    if background.luminance() < DARK_LUMINANCE {
+        println!("dark");
+        Ok(())
+    } else {
+        println!("light");
+        process::exit(EXIT_LIGHT)
+    }
+}
+
+fn execute_watch(args: ThemeWatchArgs, terminal: &Terminal) -> io::Result<()> {
+    let mut tty = terminal.open()?;
+    let enable = terminal.wrap(ENABLE_THEME_REPORTS.to_vec())?;
+    let disable = terminal.wrap(DISABLE_THEME_REPORTS.to_vec())?;
+
+    tty::with_noecho_cbreak_mode(tty.as_raw_fd(), || {
+        // Watching ends once interrupted, and the reports must be turned off
+        // then, as otherwise they'd be read by the shell as typed.
+        tty::set_reset_sequence(disable.clone());
+        tty.write_all(&enable)?;
+        tty.flush()?;
+
+        let result = watch_reports(&tty, |theme| match &args.exec {
+            Some(exec) => {
+                Command::new("sh")
+                    .arg("-c")
+                    .arg(exec)
+                    .env("TTYBOX_THEME", theme)
+                    .status()?;
+                Ok(())
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                writeln!(stdout, "{theme}")?;
+                stdout.flush()
+            }
+        });
+        tty.write_all(&disable)?;
+        result
+    })
+}
+
+fn execute_export(args: ThemeExportArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+    let slots = match args.format {
+        _ if args.extended => 256,
+        SchemeFormat::Base16 => 22,
+        _ => 16,
+    };
+
+    let scheme = query_scheme(slots, timeout, terminal)?;
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(scheme.format(args.format, &args.name).as_bytes())?;
+    stdout.flush()
+}
+
+fn execute_apply(args: ThemeApplyArgs, terminal: &Terminal) -> io::Result<()> {
+    let sequences = match load_scheme(args.file, args.builtin, args.format)? {
+        Some(scheme) => scheme.sequences(),
+        None => RESET_COLORS.to_vec(),
+    };
+
+    if args.dry_run {
+        // The sequences are printed one per line, with the escapes shown.
+        let mut stdout = io::stdout().lock();
+        for sequence in sequences.split_inclusive(|byte| *byte == b'\\') {
+            writeln!(stdout, "{}", sequence.escape_ascii())?;
+        }
+        return stdout.flush();
+    }
+    terminal.send([sequences])
+}
+
+fn execute_preview(args: ThemePreviewArgs, terminal: &Terminal) -> io::Result<()> {
+    let scheme = match load_scheme(args.file, args.builtin, args.format)? {
+        Some(scheme) if args.apply => scheme,
+        scheme => {
+            print!("{}", preview::render(scheme.as_ref()));
+            return io::stdout().flush();
+        }
+    };
+
+    // The colors are restored to the ones in use, if the terminal reports
+    // them, as they may differ from the configured ones.
+    let slots = scheme
+        .palette
+        .last()
+        .map_or(0, |(slot, _)| u16::from(*slot) + 1);
+    let restore = match query_scheme(slots, DEFAULT_TIMEOUT, terminal) {
+        Ok(current) => {
+            // The cursor drawn in reverse video isn't reported, and is reset
+            // instead.
+            let mut restore = current.sequences();
+            if current.cursor.is_none() {
+                restore.extend_from_slice(b"\x1B]112\x1B\\");
+            }
+            restore
+        }
+        Err(e) if error::failure(&e) == Some(Failure::Unsupported) => RESET_COLORS.to_vec(),
+        Err(e) => return Err(e),
+    };
+
+    let mut tty = terminal.open()?;
+    let apply = terminal.wrap(scheme.sequences())?;
+    let restore = terminal.wrap(restore)?;
+    tty::with_noecho_cbreak_mode(tty.as_raw_fd(), || {
+        // The colors must be restored if interrupted while waiting as well.
+        tty::set_reset_sequence(restore.clone());
+        tty.write_all(&apply)?;
+        tty.flush()?;
+
+        print!("{}", preview::render(None));
+        eprint!("Press any key to restore the colors.");
+        io::stdout().flush()?;
+
+        let mut key = [0u8; 16];
+        let result = loop {
+            match tty.read(&mut key) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        eprintln!();
+        tty.write_all(&restore)?;
+        tty.flush()?;
+        result.map(|_| ())
+    })
+}
+
+/// Returns the scheme of the theme file, or the bundled one of the name, if
+/// either is given.
+fn load_scheme(
+    file: Option<PathBuf>,
+    builtin: Option<String>,
+    format: Option<SchemeFormat>,
+) -> io::Result<Option<Scheme>> {
+    if let Some(name) = builtin {
+        return Ok(Some(
+            builtin::scheme(&name).expect("the name is one of the bundled schemes"),
+        ));
+    }
+    let Some(file) = file else {
+        return Ok(None);
+    };
+    let text = if file == Path::new("-") {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        fs::read_to_string(&file).map_err(|e| {
+            io::Error::new(e.kind(), format!("Cannot read '{}': {e}", file.display()))
+        })?
+    };
+    let format = format.unwrap_or_else(|| SchemeFormat::detect(&text));
+    Scheme::parse(&text, format).map(Some)
+}
+
+/// Returns the colors of the terminal, along with the palette slots up to the
+/// given one, as reported by the terminal.
+fn query_scheme(slots: u16, timeout: Duration, terminal: &Terminal) -> io::Result<Scheme> {
+    // All the colors are queried at once, and each one is reported on its own
+    // as either `ESC ] 10 ; rgb:RRRR/GGGG/BBBB ST` or, for the palette,
+    // `ESC ] 4 ; SLOT ; rgb:RRRR/GGGG/BBBB ST`.
+    let mut request = String::from("\x1B]10;?\x1B\\\x1B]11;?\x1B\\\x1B]12;?\x1B\\\x1B]4");
+    for slot in 0..slots {
+        request.push_str(&format!(";{slot};?"));
+    }
+    request.push_str("\x1B\\");
+    let response = query::query(terminal, request.as_bytes(), timeout)?;
+
+    let report = |prefix: String| query::find_osc(&response, prefix.as_bytes());
+    let unsupported = || {
+        error::new(
+            Failure::Unsupported,
+            "The terminal emulator doesn't report its colors.",
+        )
+    };
+    Ok(Scheme {
+        foreground: Some(color::parse_report(
+            report("\x1B]10;".into()).ok_or_else(unsupported)?,
+        )?),
+        background: Some(color::parse_report(
+            report("\x1B]11;".into()).ok_or_else(unsupported)?,
+        )?),
+        // Some terminals don't report the cursor color, e.g. if it's not set
+        // and the cursor is drawn in reverse video.
+        cursor: report("\x1B]12;".into())
+            .map(color::parse_report)
+            .transpose()?,
+        palette: (0..slots)
+            .map(|slot| {
+                let report = report(format!("\x1B]4;{slot};")).ok_or_else(|| {
+                    error::new(
+                        Failure::Unsupported,
+                        format!("The terminal emulator doesn't report the palette slot {slot}."),
+                    )
+                })?;
+                Ok((slot as u8, color::parse_report(report)?))
+            })
+            .collect::<io::Result<_>>()?,
+    })
+}
+
+/// Reads the input of the terminal, and calls the function with the theme
+/// every time the terminal reports it's changed. Anything else is discarded,
+/// including whatever is typed meanwhile.
+fn watch_reports<F>(mut tty: &File, mut func: F) -> io::Result<()>
+where
+    F: FnMut(&str) -> io::Result<()>,
+{
+    let mut input = Vec::new();
+    let mut buffer = [0u8; 1024];
+    let mut last_theme = None;
+
+    loop {
+        let size = match tty.read(&mut buffer) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(size) => size,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        input.extend_from_slice(&buffer[..size]);
+
+        // A report looks like `CSI ? 997 ; 1 n` for a dark theme, and
+        // `CSI ? 997 ; 2 n` for a light one.
+        while let Some(start) = find(&input, b"\x1B[?997;") {
+            let Some(end) = input[start..].iter().position(|byte| *byte == b'n') else {
+                break;
+            };
+            let theme = match &input[start + 7..start + end] {
+                b"1" => Some("dark"),
+                b"2" => Some("light"),
+                _ => None,
+            };
+            input.drain(..start + end + 1);
+
+            // Terminals may report the same theme again, e.g. when the desktop
+            // changes its accent color.
+            if let Some(theme) = theme.filter(|theme| last_theme != Some(*theme)) {
+                func(theme)?;
+                last_theme = Some(theme);
+            }
+        }
+
+        // What can't be a part of a report is dropped, so the input doesn't
+        // grow forever.
+        if let Some(escape) = input.iter().rposition(|byte| *byte == 0x1B) {
+            input.drain(..escape);
+        } else {
+            input.clear();
+        }
+    }
+}
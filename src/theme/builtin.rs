@@ -0,0 +1,113 @@
+use crate::color;
+
+use super::scheme::Scheme;
+
+/// The base16 schemes bundled with ttybox, along with their colors, base00 to
+/// base0F.
+pub const SCHEMES: &[(&str, [u32; 16])] = &[
+    (
+        "catppuccin-mocha",
+        [
+            0x1e1e2e, 0x181825, 0x313244, 0x45475a, 0x585b70, 0xcdd6f4, 0xf5e0dc, 0xb4befe,
+            0xf38ba8, 0xfab387, 0xf9e2af, 0xa6e3a1, 0x94e2d5, 0x89b4fa, 0xcba6f7, 0xf2cdcd,
+        ],
+    ),
+    (
+        "default-dark",
+        [
+            0x181818, 0x282828, 0x383838, 0x585858, 0xb8b8b8, 0xd8d8d8, 0xe8e8e8, 0xf8f8f8,
+            0xab4642, 0xdc9656, 0xf7ca88, 0xa1b56c, 0x86c1b9, 0x7cafc2, 0xba8baf, 0xa16946,
+        ],
+    ),
+    (
+        "default-light",
+        [
+            0xf8f8f8, 0xe8e8e8, 0xd8d8d8, 0xb8b8b8, 0x585858, 0x383838, 0x282828, 0x181818,
+            0xab4642, 0xdc9656, 0xf7ca88, 0xa1b56c, 0x86c1b9, 0x7cafc2, 0xba8baf, 0xa16946,
+        ],
+    ),
+    (
+        "dracula",
+        [
+            0x282936, 0x3a3c4e, 0x4d4f68, 0x626483, 0x62d6e8, 0xe9e9f4, 0xf1f2f8, 0xf7f7fb,
+            0xea51b2, 0xb45bcf, 0x00f769, 0xebff87, 0xa1efe4, 0x62d6e8, 0xb45bcf, 0x00f769,
+        ],
+    ),
+    (
+        "gruvbox-dark-hard",
+        [
+            0x1d2021, 0x3c3836, 0x504945, 0x665c54, 0xbdae93, 0xd5c4a1, 0xebdbb2, 0xfbf1c7,
+            0xfb4934, 0xfe8019, 0xfabd2f, 0xb8bb26, 0x8ec07c, 0x83a598, 0xd3869b, 0xd65d0e,
+        ],
+    ),
+    (
+        "gruvbox-dark-medium",
+        [
+            0x282828, 0x3c3836, 0x504945, 0x665c54, 0xbdae93, 0xd5c4a1, 0xebdbb2, 0xfbf1c7,
+            0xfb4934, 0xfe8019, 0xfabd2f, 0xb8bb26, 0x8ec07c, 0x83a598, 0xd3869b, 0xd65d0e,
+        ],
+    ),
+    (
+        "gruvbox-light-hard",
+        [
+            0xf9f5d7, 0xebdbb2, 0xd5c4a1, 0xbdae93, 0x665c54, 0x504945, 0x3c3836, 0x282828,
+            0x9d0006, 0xaf3a03, 0xb57614, 0x79740e, 0x427b58, 0x076678, 0x8f3f71, 0xd65d0e,
+        ],
+    ),
+    (
+        "monokai",
+        [
+            0x272822, 0x383830, 0x49483e, 0x75715e, 0xa59f85, 0xf8f8f2, 0xf5f4f1, 0xf9f8f5,
+            0xf92672, 0xfd971f, 0xf4bf75, 0xa6e22e, 0xa1efe4, 0x66d9ef, 0xae81ff, 0xcc6633,
+        ],
+    ),
+    (
+        "nord",
+        [
+            0x2e3440, 0x3b4252, 0x434c5e, 0x4c566a, 0xd8dee9, 0xe5e9f0, 0xeceff4, 0x8fbcbb,
+            0xbf616a, 0xd08770, 0xebcb8b, 0xa3be8c, 0x88c0d0, 0x81a1c1, 0xb48ead, 0x5e81ac,
+        ],
+    ),
+    (
+        "onedark",
+        [
+            0x282c34, 0x353b45, 0x3e4451, 0x545862, 0x565c64, 0xabb2bf, 0xb6bdca, 0xc8ccd4,
+            0xe06c75, 0xd19a66, 0xe5c07b, 0x98c379, 0x56b6c2, 0x61afef, 0xc678dd, 0xbe5046,
+        ],
+    ),
+    (
+        "solarized-dark",
+        [
+            0x002b36, 0x073642, 0x586e75, 0x657b83, 0x839496, 0x93a1a1, 0xeee8d5, 0xfdf6e3,
+            0xdc322f, 0xcb4b16, 0xb58900, 0x859900, 0x2aa198, 0x268bd2, 0x6c71c4, 0xd33682,
+        ],
+    ),
+    (
+        "solarized-light",
+        [
+            0xfdf6e3, 0xeee8d5, 0x93a1a1, 0x839496, 0x657b83, 0x586e75, 0x073642, 0x002b36,
+            0xdc322f, 0xcb4b16, 0xb58900, 0x859900, 0x2aa198, 0x268bd2, 0x6c71c4, 0xd33682,
+        ],
+    ),
+    (
+        "tomorrow-night",
+        [
+            0x1d1f21, 0x282a2e, 0x373b41, 0x969896, 0xb4b7b4, 0xc5c8c6, 0xe0e0e0, 0xffffff,
+            0xcc6666, 0xde935f, 0xf0c674, 0xb5bd68, 0x8abeb7, 0x81a2be, 0xb294bb, 0xa3685a,
+        ],
+    ),
+];
+
+/// Returns the names of the bundled schemes.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    SCHEMES.iter().map(|(name, _)| *name)
+}
+
+/// Returns the bundled scheme of the name.
+pub fn scheme(name: &str) -> Option<Scheme> {
+    let (_, colors) = SCHEMES.iter().find(|(other, _)| *other == name)?;
+    let bases = colors.map(|color| {
+        color::parse_color(&format!("#{color:06x}")).expect("the bundled colors are valid")
+    });
+    Some(Scheme::from_base16(bases))
+}
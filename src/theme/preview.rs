@@ -0,0 +1,128 @@
+use std::fmt::Write;
+
+use super::scheme::Scheme;
+
+/// The width the lines of the preview are padded to, so the background of
+/// the scheme fills a block.
+const WIDTH: usize = 48;
+
+/// The style of a span of the preview.
+#[derive(Clone, Copy)]
+enum Style {
+    Plain,
+    /// The foreground of the palette slot.
+    Fg(u8),
+    /// The bold foreground of the palette slot.
+    Bold(u8),
+    /// The background of the palette slot.
+    Bg(u8),
+}
+
+/// The sample the preview is made of: a palette, a directory listing, a diff,
+/// and a prompt.
+const SAMPLE: &[&[(Style, &str)]] = &[
+    &[
+        (Style::Plain, " "),
+        (Style::Bg(0), "   "),
+        (Style::Bg(1), "   "),
+        (Style::Bg(2), "   "),
+        (Style::Bg(3), "   "),
+        (Style::Bg(4), "   "),
+        (Style::Bg(5), "   "),
+        (Style::Bg(6), "   "),
+        (Style::Bg(7), "   "),
+    ],
+    &[
+        (Style::Plain, " "),
+        (Style::Bg(8), "   "),
+        (Style::Bg(9), "   "),
+        (Style::Bg(10), "   "),
+        (Style::Bg(11), "   "),
+        (Style::Bg(12), "   "),
+        (Style::Bg(13), "   "),
+        (Style::Bg(14), "   "),
+        (Style::Bg(15), "   "),
+    ],
+    &[],
+    &[(Style::Plain, " $ ls -l")],
+    &[
+        (Style::Plain, " drwxr-xr-x  "),
+        (Style::Bold(4), "src"),
+        (Style::Plain, "/"),
+    ],
+    &[
+        (Style::Plain, " -rwxr-xr-x  "),
+        (Style::Bold(2), "build.sh"),
+        (Style::Plain, "*"),
+    ],
+    &[
+        (Style::Plain, " lrwxrwxrwx  "),
+        (Style::Bold(6), "latest"),
+        (Style::Plain, " -> v1.2"),
+    ],
+    &[
+        (Style::Plain, " -rw-r--r--  "),
+        (Style::Bold(1), "release.tar.gz"),
+    ],
+    &[(Style::Plain, " -rw-r--r--  README.md")],
+    &[],
+    &[(Style::Plain, " $ git diff")],
+    &[(Style::Bold(15), " --- a/src/main.rs")],
+    &[(Style::Bold(15), " +++ b/src/main.rs")],
+    &[(Style::Fg(6), " @@ -1,3 +1,3 @@")],
+    &[(Style::Plain, "  fn main() {")],
+    &[(Style::Fg(1), " -    println!(\"Hello\");")],
+    &[(Style::Fg(2), " +    println!(\"Hello, world!\");")],
+    &[(Style::Plain, "  }")],
+    &[],
+    &[
+        (Style::Plain, " "),
+        (Style::Bold(2), "user@host"),
+        (Style::Plain, " "),
+        (Style::Bold(4), "~/src/ttybox"),
+        (Style::Plain, " "),
+        (Style::Fg(3), "(main)"),
+        (Style::Fg(8), " 3s"),
+        (Style::Plain, " $ "),
+        (Style::Bg(7), " "),
+    ],
+];
+
+/// Returns the preview drawn with the colors of the scheme, given by true
+/// colors, or with the colors of the terminal's palette if there's none.
+pub fn render(scheme: Option<&Scheme>) -> String {
+    let colors = |slot: u8, layer: u8| match scheme.and_then(|scheme| scheme.slot(slot)) {
+        Some(color) => {
+            let [red, green, blue] = color.rgb();
+            format!("{layer}8;2;{red};{green};{blue}")
+        }
+        None => format!("{layer}8;5;{slot}"),
+    };
+    let mut base = String::from("0");
+    if let Some(color) = scheme.and_then(|scheme| scheme.foreground) {
+        let [red, green, blue] = color.rgb();
+        let _ = write!(base, ";38;2;{red};{green};{blue}");
+    }
+    if let Some(color) = scheme.and_then(|scheme| scheme.background) {
+        let [red, green, blue] = color.rgb();
+        let _ = write!(base, ";48;2;{red};{green};{blue}");
+    }
+
+    let mut preview = String::new();
+    for line in SAMPLE {
+        let mut width = 0;
+        for (style, text) in line.iter() {
+            let style = match style {
+                Style::Plain => String::new(),
+                Style::Fg(slot) => format!(";{}", colors(*slot, 3)),
+                Style::Bold(slot) => format!(";1;{}", colors(*slot, 3)),
+                Style::Bg(slot) => format!(";{}", colors(*slot, 4)),
+            };
+            let _ = write!(preview, "\x1B[{base}{style}m{text}");
+            width += text.chars().count();
+        }
+        let padding = WIDTH.saturating_sub(width);
+        let _ = writeln!(preview, "\x1B[{base}m{:padding$}\x1B[0m", "");
+    }
+    preview
+}
@@ -0,0 +1,451 @@
+use std::fmt::Write;
+use std::io;
+
+use clap::ValueEnum;
+
+use crate::color::{self, Color, Format};
+use crate::json;
+
+/// The names of the 8 ANSI colors, as used by the theme files.
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// The palette slots the base16 colors are kept in, as laid out by
+/// base16-shell: base00 to base0F in order.
+const BASE16_SLOTS: [u8; 16] = [0, 18, 19, 8, 20, 7, 21, 15, 1, 16, 3, 2, 6, 4, 5, 17];
+
+/// The palette slots the bright colors of base24, base12 to base17, are kept
+/// in. base10 and base11 are darker backgrounds, which have no slot.
+const BASE24_SLOTS: [(usize, u8); 6] = [
+    (0x12, 9),
+    (0x13, 11),
+    (0x14, 10),
+    (0x15, 14),
+    (0x16, 12),
+    (0x17, 13),
+];
+
+/// The formats of the theme files.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemeFormat {
+    /// The TOML configuration of alacritty.
+    Alacritty,
+    /// The configuration of kitty.
+    Kitty,
+    /// The YAML scheme of base16 or base24, which takes the palette slots 0
+    /// to 21.
+    Base16,
+    /// {"foreground": "#rrggbb", "background": …, "cursor": …, "palette": […]}.
+    Json,
+}
+
+impl SchemeFormat {
+    /// Guesses the format of the theme file from its contents.
+    pub fn detect(text: &str) -> SchemeFormat {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        match lines.next() {
+            Some(line) if line.starts_with('{') => SchemeFormat::Json,
+            Some(line) if line.starts_with('[') => SchemeFormat::Alacritty,
+            _ if text.lines().any(|line| line.trim().starts_with("base00")) => SchemeFormat::Base16,
+            _ => SchemeFormat::Kitty,
+        }
+    }
+}
+
+/// The colors of a terminal. Any of them may be missing, e.g. if the theme
+/// file doesn't change it.
+#[derive(Debug, Default)]
+pub struct Scheme {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub cursor: Option<Color>,
+    /// The palette slots along with their colors, in order.
+    pub palette: Vec<(u8, Color)>,
+}
+
+impl Scheme {
+    /// Parses the theme file in the format.
+    pub fn parse(text: &str, format: SchemeFormat) -> io::Result<Scheme> {
+        let mut scheme = match format {
+            SchemeFormat::Alacritty => Self::parse_alacritty(text)?,
+            SchemeFormat::Kitty => Self::parse_kitty(text)?,
+            SchemeFormat::Base16 => Self::parse_base16(text)?,
+            SchemeFormat::Json => Self::parse_json(text)?,
+        };
+        // The slot given last wins, as with the terminals' own configuration.
+        scheme.palette.reverse();
+        scheme.palette.sort_by_key(|(slot, _)| *slot);
+        scheme.palette.dedup_by_key(|(slot, _)| *slot);
+        Ok(scheme)
+    }
+
+    /// Returns the sequences changing the colors of the terminal to the ones
+    /// of the scheme, by means of OSC 10, 11, 12, and 4.
+    pub fn sequences(&self) -> Vec<u8> {
+        let mut sequences = String::new();
+        for (code, color) in [
+            (10, self.foreground),
+            (11, self.background),
+            (12, self.cursor),
+        ] {
+            if let Some(color) = color {
+                let _ = write!(sequences, "\x1B]{code};{}\x1B\\", color.format(Format::Rgb));
+            }
+        }
+        if !self.palette.is_empty() {
+            sequences.push_str("\x1B]4");
+            for (slot, color) in &self.palette {
+                let _ = write!(sequences, ";{slot};{}", color.format(Format::Rgb));
+            }
+            sequences.push_str("\x1B\\");
+        }
+        sequences.into_bytes()
+    }
+
+    /// Returns the theme file of the scheme in the format, named as given if
+    /// the format has a name.
+    pub fn format(&self, format: SchemeFormat, name: &str) -> String {
+        match format {
+            SchemeFormat::Alacritty => self.format_alacritty(),
+            SchemeFormat::Kitty => self.format_kitty(),
+            SchemeFormat::Base16 => self.format_base16(name),
+            SchemeFormat::Json => self.format_json(),
+        }
+    }
+
+    /// Returns the scheme of the base16 colors, base00 to base0F, laid out
+    /// on the palette the way base16-shell does.
+    pub fn from_base16(bases: [Color; 16]) -> Scheme {
+        let mut scheme = Scheme {
+            foreground: Some(bases[0x5]),
+            background: Some(bases[0x0]),
+            cursor: Some(bases[0x5]),
+            palette: Vec::new(),
+        };
+        for (base, slot) in BASE16_SLOTS.iter().enumerate() {
+            scheme.palette.push((*slot, bases[base]));
+        }
+        // The bright colors are the same as the normal ones, but for black
+        // and white.
+        for slot in 1..7 {
+            let color = scheme.slot(slot).expect("the normal colors are set");
+            scheme.palette.push((slot + 8, color));
+        }
+        scheme.palette.sort_by_key(|(slot, _)| *slot);
+        scheme
+    }
+
+    /// Returns the color of the palette slot, if the scheme has it.
+    pub fn slot(&self, slot: u8) -> Option<Color> {
+        self.palette
+            .iter()
+            .find(|(other, _)| *other == slot)
+            .map(|(_, color)| *color)
+    }
+
+    fn parse_alacritty(text: &str) -> io::Result<Scheme> {
+        let mut scheme = Scheme::default();
+        let mut table = String::new();
+        let mut index: Option<u8> = None;
+        let mut indexed_color: Option<Color> = None;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = strip_comment(line);
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                table = header.trim_matches(['[', ']', ' ']).to_owned();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid(number, "expected KEY = VALUE"))?;
+            let key = key.trim();
+            let value = value.trim().trim_matches(['"', '\'']);
+            let color = || parse_color(value).map_err(|e| invalid(number, &e));
+
+            match (table.as_str(), key) {
+                ("colors.primary", "foreground") => scheme.foreground = Some(color()?),
+                ("colors.primary", "background") => scheme.background = Some(color()?),
+                ("colors.cursor", "cursor") => scheme.cursor = color().ok(),
+                ("colors.normal" | "colors.bright", _) => {
+                    let Some(slot) = COLOR_NAMES.iter().position(|name| *name == key) else {
+                        continue;
+                    };
+                    let offset = if table == "colors.normal" { 0 } else { 8 };
+                    scheme.palette.push((slot as u8 + offset, color()?));
+                }
+                ("colors.indexed_colors", "index") => {
+                    index = Some(
+                        value
+                            .parse()
+                            .map_err(|_| invalid(number, "invalid index"))?,
+                    )
+                }
+                ("colors.indexed_colors", "color") => indexed_color = Some(color()?),
+                _ => {}
+            }
+
+            // The entries of indexed_colors are tables with index and color
+            // given in any order.
+            if let (Some(slot), Some(color)) = (index, indexed_color) {
+                scheme.palette.push((slot, color));
+                (index, indexed_color) = (None, None);
+            }
+        }
+        Ok(scheme)
+    }
+
+    fn parse_kitty(text: &str) -> io::Result<Scheme> {
+        let mut scheme = Scheme::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            let color = || parse_color(value).map_err(|e| invalid(number, &e));
+
+            match key {
+                "foreground" => scheme.foreground = Some(color()?),
+                "background" => scheme.background = Some(color()?),
+                // The cursor may be "none", i.e. drawn in reverse video.
+                "cursor" => scheme.cursor = color().ok(),
+                _ => {
+                    if let Some(slot) = key.strip_prefix("color").and_then(|slot| slot.parse().ok())
+                    {
+                        scheme.palette.push((slot, color()?));
+                    }
+                }
+            }
+        }
+        Ok(scheme)
+    }
+
+    fn parse_base16(text: &str) -> io::Result<Scheme> {
+        // The colors are given either at the top level, or in the palette
+        // mapping of the newer scheme format, which is told apart by the
+        // indentation only.
+        // The bright colors of base24 are optional, and stand in for the
+        // ones base16 repeats from the normal colors.
+        let mut bases = [None; 24];
+        for (number, line) in text.lines().enumerate() {
+            let line = strip_comment(line);
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let Some(base) = key
+                .trim()
+                .strip_prefix("base")
+                .filter(|base| base.len() == 2)
+                .and_then(|base| u8::from_str_radix(base, 16).ok())
+                .filter(|base| (*base as usize) < bases.len())
+            else {
+                continue;
+            };
+            let value = value.trim().trim_matches(['"', '\'']);
+            let value = value.strip_prefix('#').unwrap_or(value);
+            bases[base as usize] =
+                Some(parse_color(&format!("#{value}")).map_err(|e| invalid(number, &e))?);
+        }
+
+        let mut colors = [Color::default(); 16];
+        for (base, color) in colors.iter_mut().enumerate() {
+            *color = bases[base].ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid theme: base{base:02X} is missing."),
+                )
+            })?;
+        }
+        let mut scheme = Self::from_base16(colors);
+        for (base, slot) in BASE24_SLOTS {
+            if let Some(color) = bases[base] {
+                scheme.palette.retain(|(other, _)| *other != slot);
+                scheme.palette.push((slot, color));
+            }
+        }
+        scheme.palette.sort_by_key(|(slot, _)| *slot);
+        Ok(scheme)
+    }
+
+    fn parse_json(text: &str) -> io::Result<Scheme> {
+        let document = json::parse(text)?;
+        let color = |value: &json::Value| {
+            value
+                .as_str()
+                .ok_or_else(|| "expected a string".to_owned())
+                .and_then(parse_color)
+                .map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid theme: {e}."))
+                })
+        };
+
+        let mut scheme = Scheme::default();
+        for (key, field) in [
+            ("foreground", &mut scheme.foreground),
+            ("background", &mut scheme.background),
+            ("cursor", &mut scheme.cursor),
+        ] {
+            if let Some(value) = document.get(key) {
+                *field = Some(color(value)?);
+            }
+        }
+        if let Some(json::Value::Array(palette)) = document.get("palette") {
+            for (slot, value) in palette.iter().enumerate().take(256) {
+                if *value != json::Value::Null {
+                    scheme.palette.push((slot as u8, color(value)?));
+                }
+            }
+        }
+        Ok(scheme)
+    }
+
+    fn format_alacritty(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "[colors.primary]");
+        if let Some(foreground) = self.foreground {
+            let _ = writeln!(text, "foreground = \"{}\"", hex(foreground));
+        }
+        if let Some(background) = self.background {
+            let _ = writeln!(text, "background = \"{}\"", hex(background));
+        }
+        if let Some(cursor) = self.cursor {
+            let _ = writeln!(text, "\n[colors.cursor]");
+            let _ = writeln!(text, "cursor = \"{}\"", hex(cursor));
+        }
+        for (table, offset) in [("normal", 0), ("bright", 8)] {
+            let _ = writeln!(text, "\n[colors.{table}]");
+            for (slot, name) in COLOR_NAMES.iter().enumerate() {
+                if let Some(color) = self.slot(slot as u8 + offset) {
+                    let _ = writeln!(text, "{name} = \"{}\"", hex(color));
+                }
+            }
+        }
+        for (slot, color) in self.palette.iter().filter(|(slot, _)| *slot >= 16) {
+            let _ = writeln!(text, "\n[[colors.indexed_colors]]");
+            let _ = writeln!(text, "index = {slot}");
+            let _ = writeln!(text, "color = \"{}\"", hex(*color));
+        }
+        text
+    }
+
+    fn format_kitty(&self) -> String {
+        let mut text = String::new();
+        for (key, color) in [
+            ("foreground", self.foreground),
+            ("background", self.background),
+            ("cursor", self.cursor),
+        ] {
+            if let Some(color) = color {
+                let _ = writeln!(text, "{key} {}", hex(color));
+            }
+        }
+        for (slot, color) in &self.palette {
+            let _ = writeln!(text, "color{slot} {}", hex(*color));
+        }
+        text
+    }
+
+    fn format_base16(&self, name: &str) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "scheme: {}", json::quote(name));
+        let _ = writeln!(text, "author: \"ttybox\"");
+        for (base, slot) in BASE16_SLOTS.iter().enumerate() {
+            // The background and the foreground are the ones in use, as they
+            // are what the palette slots 0 and 7 stand in for.
+            let color = match base {
+                0x0 => self.background.or(self.slot(*slot)),
+                0x5 => self.foreground.or(self.slot(*slot)),
+                _ => self.slot(*slot),
+            };
+            if let Some(color) = color {
+                let _ = writeln!(text, "base{base:02X}: \"{}\"", &hex(color)[1..]);
+            }
+        }
+        // The bright colors are written as base24 only if they are not the
+        // ones base16 would repeat anyway.
+        if BASE24_SLOTS
+            .iter()
+            .any(|(_, slot)| self.slot(*slot) != self.slot(slot - 8))
+        {
+            for (base, slot) in BASE24_SLOTS {
+                if let Some(color) = self.slot(slot) {
+                    let _ = writeln!(text, "base{base:02X}: \"{}\"", &hex(color)[1..]);
+                }
+            }
+        }
+        text
+    }
+
+    fn format_json(&self) -> String {
+        let mut members = Vec::new();
+        for (key, color) in [
+            ("foreground", self.foreground),
+            ("background", self.background),
+            ("cursor", self.cursor),
+        ] {
+            if let Some(color) = color {
+                members.push(format!("\"{key}\": \"{}\"", hex(color)));
+            }
+        }
+        // The palette is indexed by slot, with the missing slots left null.
+        let length = self
+            .palette
+            .last()
+            .map_or(0, |(slot, _)| *slot as usize + 1);
+        let palette = (0..length)
+            .map(|slot| match self.slot(slot as u8) {
+                Some(color) => format!("\"{}\"", hex(color)),
+                None => "null".to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        members.push(format!("\"palette\": [{palette}]"));
+        format!("{{{}}}\n", members.join(", "))
+    }
+}
+
+/// Parses the color in any of the formats theme files use, including the
+/// "0xrrggbb" of alacritty.
+fn parse_color(value: &str) -> Result<Color, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => color::parse_color(&format!("#{hex}")),
+        None => color::parse_color(value),
+    }
+}
+
+/// Returns the line without the comment it ends with, if any, and without
+/// the surrounding whitespace.
+fn strip_comment(line: &str) -> &str {
+    // A hash starts a comment unless it's in a string, as colors are.
+    let mut quoted = false;
+    let end = line
+        .char_indices()
+        .find(|(_, char)| {
+            if *char == '"' || *char == '\'' {
+                quoted = !quoted;
+            }
+            *char == '#' && !quoted
+        })
+        .map_or(line.len(), |(index, _)| index);
+    line[..end].trim()
+}
+
+/// Returns the error of the malformed line of the theme file.
+fn invalid(number: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid theme at line {}: {message}.", number + 1),
+    )
+}
+
+fn hex(color: Color) -> String {
+    color.format(Format::Hex)
+}
@@ -0,0 +1,239 @@
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, BufRead, Read, Write};
+use std::os::unix::ffi::OsStringExt;
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{Args, Subcommand};
+
+use crate::error::{self, Failure};
+use crate::query;
+use crate::terminal::{Terminal, TimeoutArgs};
+use crate::tty;
+
+/// The request to report the window title, XTWINOPS 21.
+const TITLE_REQUEST: &[u8] = b"\x1B[21t";
+
+/// The requests to save both the title and the icon name on the terminal's
+/// stack, and to restore them, XTWINOPS 22 and 23.
+const PUSH_TITLE: &[u8] = b"\x1B[22;0t";
+const POP_TITLE: &[u8] = b"\x1B[23;0t";
+
+#[derive(Subcommand, Debug)]
+pub enum TitleCommands {
+    /// Set the title of the terminal window by means of OSC 2.
+    Set(TitleSetArgs),
+    /// Print the title of the terminal window, as reported by the terminal.
+    /// Many terminals don't report it, or report it only if configured to, as
+    /// it may be set by untrusted programs.
+    Get(TitleGetArgs),
+    /// Set the icon name, which some window managers show on the taskbar
+    /// instead of the title, by means of OSC 1.
+    Icon(TitleIconArgs),
+    /// Run the command with the title set for as long as it runs, and restore
+    /// the previous title once it exits, however it exits. The exit code is
+    /// the one of the command.
+    Run(TitleRunArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TitleSetArgs {
+    /// The title to set. If omitted, the title is read from the standard
+    /// input.
+    title: Option<OsString>,
+
+    /// Set the icon name as well, by means of OSC 0 instead of OSC 2.
+    #[arg(long, default_value_t = false)]
+    and_icon: bool,
+
+    /// Read the standard input line by line, and set the title to every line
+    /// as it's read, e.g. to show the progress of a long running command.
+    #[arg(long, default_value_t = false, conflicts_with = "title")]
+    follow: bool,
+
+    /// The minimum time between title updates when following, in
+    /// milliseconds. Lines read in between are skipped, except for the most
+    /// recent one.
+    #[arg(long, value_name = "MS", default_value_t = 100, requires = "follow")]
+    interval: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct TitleIconArgs {
+    /// The icon name to set. If omitted, the name is read from the standard
+    /// input.
+    name: Option<OsString>,
+
+    /// Set the title as well, by means of OSC 0 instead of OSC 1.
+    #[arg(long, default_value_t = false)]
+    both: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct TitleRunArgs {
+    /// The command to run, along with its arguments.
+    #[arg(required = true, trailing_var_arg = true, value_name = "COMMAND")]
+    command: Vec<OsString>,
+
+    /// The title to set, where "{cmd}" stands for the command line, and
+    /// "{cwd}" for the current working directory.
+    #[arg(long, value_name = "TEMPLATE", default_value = "{cmd}")]
+    title: String,
+}
+
+#[derive(Args, Debug)]
+pub struct TitleGetArgs {
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+pub fn execute(command: TitleCommands, terminal: &Terminal) -> io::Result<()> {
+    match command {
+        TitleCommands::Set(args) => execute_set(args, terminal),
+        TitleCommands::Get(args) => execute_get(args, terminal),
+        TitleCommands::Icon(args) => execute_icon(args, terminal),
+        TitleCommands::Run(args) => execute_run(args, terminal),
+    }
+}
+
+fn execute_set(args: TitleSetArgs, terminal: &Terminal) -> io::Result<()> {
+    let code = if args.and_icon { 0 } else { 2 };
+    if args.follow {
+        return follow(code, Duration::from_millis(args.interval), terminal);
+    }
+    let title = read_text(args.title)?;
+    terminal.send([osc(code, &title)])
+}
+
+/// Sets the title to every line of the standard input, yet no more often
+/// than once per interval.
+fn follow(code: u8, interval: Duration, terminal: &Terminal) -> io::Result<()> {
+    // The lines are read by a thread of their own, so the most recent line
+    // skipped due to the rate limit is shown once the interval passes, even
+    // if no more lines follow for a while.
+    let (sender, receiver) = mpsc::channel();
+    let reader = thread::spawn(move || -> io::Result<()> {
+        let mut stdin = io::stdin().lock();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if stdin.read_until(b'\n', &mut line)? == 0 {
+                return Ok(());
+            }
+            if line.ends_with(b"\n") {
+                line.pop();
+            }
+            if sender.send(line.clone()).is_err() {
+                return Ok(());
+            }
+        }
+    });
+
+    let mut last_update: Option<Instant> = None;
+    let mut pending: Option<Vec<u8>> = None;
+    loop {
+        let due = last_update.map(|last_update| last_update + interval);
+        let received = match (&pending, due) {
+            (Some(_), Some(due)) => {
+                receiver.recv_timeout(due.saturating_duration_since(Instant::now()))
+            }
+            _ => receiver
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(line) => pending = Some(line),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        if due.is_none_or(|due| Instant::now() >= due) {
+            if let Some(line) = pending.take() {
+                terminal.send([osc(code, &line)])?;
+                last_update = Some(Instant::now());
+            }
+        }
+    }
+
+    if let Some(line) = pending {
+        terminal.send([osc(code, &line)])?;
+    }
+    reader.join().expect("the standard input isn't read")
+}
+
+fn execute_icon(args: TitleIconArgs, terminal: &Terminal) -> io::Result<()> {
+    let name = read_text(args.name)?;
+    let code = if args.both { 0 } else { 1 };
+    terminal.send([osc(code, &name)])
+}
+
+fn execute_run(args: TitleRunArgs, terminal: &Terminal) -> io::Result<()> {
+    let command_line = args
+        .command
+        .iter()
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let title = args
+        .title
+        .replace("{cmd}", &command_line)
+        .replace("{cwd}", &env::current_dir()?.to_string_lossy());
+
+    // The current title is saved on the terminal's title stack, as it can't
+    // be reported by most terminals.
+    terminal.send([PUSH_TITLE.to_vec(), osc(2, title.as_bytes())])?;
+
+    let status = tty::run_then(&args.command, || terminal.send([POP_TITLE.to_vec()]))?;
+    process::exit(tty::exit_code(status))
+}
+
+fn execute_get(args: TitleGetArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    // The title report looks like `ESC ] l TITLE ST`.
+    let response = query::query(terminal, TITLE_REQUEST, timeout)?;
+    match query::find_osc(&response, b"\x1B]l") {
+        Some(title) => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(title)?;
+            stdout.write_all(b"\n")?;
+            stdout.flush()
+        }
+        None => Err(error::new(
+            Failure::Unsupported,
+            "The terminal emulator doesn't report the window title, or has \
+             reporting disabled.",
+        )),
+    }
+}
+
+/// Returns the text given on the command line, or read from the standard
+/// input without the trailing newline if omitted.
+fn read_text(text: Option<OsString>) -> io::Result<Vec<u8>> {
+    match text {
+        Some(text) => Ok(text.into_vec()),
+        None => {
+            let mut text = Vec::new();
+            io::stdin().read_to_end(&mut text)?;
+            if text.ends_with(b"\n") {
+                text.pop();
+            }
+            Ok(text)
+        }
+    }
+}
+
+/// Returns the OSC sequence with the given code and text. Control characters,
+/// which would end the sequence prematurely, are replaced with spaces.
+fn osc(code: u8, text: &[u8]) -> Vec<u8> {
+    let mut sequence = format!("\x1B]{code};").into_bytes();
+    sequence.extend(
+        text.iter()
+            .map(|byte| if byte.is_ascii_control() { b' ' } else { *byte }),
+    );
+    sequence.extend(b"\x1B\\");
+    sequence
+}
@@ -0,0 +1,317 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem::MaybeUninit;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus};
+use std::ptr;
+use std::sync::atomic::{AtomicI32, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Once;
+use std::time::Duration;
+
+use mio::unix::SourceFd;
+use mio::{Events, Interest, Poll, Token};
+
+use crate::error::{self, Failure};
+
+/// The default maximum time to wait for the terminal emulator to respond. If
+/// it hasn't started responding within that time, it most likely doesn't
+/// support the request or is simply sluggish. The value should be as small as
+/// possible to provide smooth experience in unsupported terminals but remain
+/// big enough to properly work in slow terminals.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The minimum time to wait for the rest of the response once the terminal
+/// emulator has started sending it. Large responses may stall for a while on
+/// slow links such as SSH, which doesn't mean the terminal has given up, so the
+/// window is more generous than the one for the first byte.
+const MAX_STALL_TIME: Duration = Duration::from_secs(2);
+
+/// The buffer size for reading responses from the terminal. One should
+/// consider a trade-off between memory utilization and the frequency of system
+/// calls when picking the value.
+const BUFFER_SIZE: usize = 8192;
+
+/// The signals that terminate the process by default and are likely to arrive
+/// while the terminal is switched into a non-default mode, e.g. when the user
+/// hits Ctrl+C while waiting for the terminal to respond.
+const TERMINATION_SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// The terminal device whose mode has to be restored on termination, or -1 if
+/// there's none. Signal handlers can't take locks, so the state is shared with
+/// them via an atomic, which is set only after the mode below is saved.
+static SAVED_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// The original mode of the terminal device above.
+static mut SAVED_TERMIOS: MaybeUninit<libc::termios> = MaybeUninit::uninit();
+
+/// The sequence to write to the terminal device above on termination, if any,
+/// e.g. to turn off a mode of the terminal turned on for the time being.
+static RESET_SEQUENCE: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+static RESET_SEQUENCE_LEN: AtomicUsize = AtomicUsize::new(0);
+
+static INSTALL_SIGNAL_HANDLERS: Once = Once::new();
+
+/// Runs the function with the terminal switched into noecho/cbreak mode, i.e.
+/// with both echoing and line buffering of the input turned off. The original
+/// mode of the terminal is restored afterwards, no matter whether the function
+/// has succeeded or failed, or the process has been interrupted by a signal.
+pub fn with_noecho_cbreak_mode<F, T>(fd: RawFd, func: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T>,
+{
+    let _guard = NoechoCbreakMode::enable(fd)?;
+    func()
+}
+
+/// The guard that keeps the terminal in noecho/cbreak mode while alive, and
+/// restores the original mode when dropped.
+pub struct NoechoCbreakMode {
+    fd: RawFd,
+    original: libc::termios,
+    registered: bool,
+}
+
+impl NoechoCbreakMode {
+    pub fn enable(fd: RawFd) -> io::Result<Self> {
+        let original = get_termios(fd)?;
+
+        // Only the outermost guard registers the mode to restore on
+        // termination, since it's the one holding the truly original mode.
+        let registered = SAVED_FD.load(Ordering::Acquire) < 0;
+        if registered {
+            unsafe { ptr::addr_of_mut!(SAVED_TERMIOS).write(MaybeUninit::new(original)) };
+            SAVED_FD.store(fd, Ordering::Release);
+            INSTALL_SIGNAL_HANDLERS.call_once(install_signal_handlers);
+        }
+        let guard = NoechoCbreakMode {
+            fd,
+            original,
+            registered,
+        };
+
+        // See `man 3 termios` for details on the flags. VMIN and VTIME make
+        // reads return as soon as a single byte is available, as in cbreak mode.
+        let mut termios = original;
+        termios.c_lflag &= !(libc::ECHO | libc::ICANON);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        set_termios(fd, &termios)?;
+
+        Ok(guard)
+    }
+}
+
+/// Sets the sequence to write to the terminal if the process is terminated by
+/// a signal while the terminal is in noecho/cbreak mode. The sequence lives
+/// until the process exits, as the signal may arrive at any moment.
+pub fn set_reset_sequence(sequence: Vec<u8>) {
+    let sequence = Box::leak(sequence.into_boxed_slice());
+    RESET_SEQUENCE_LEN.store(sequence.len(), Ordering::Release);
+    RESET_SEQUENCE.store(sequence.as_mut_ptr(), Ordering::Release);
+}
+
+impl Drop for NoechoCbreakMode {
+    fn drop(&mut self) {
+        let _ = set_termios(self.fd, &self.original);
+        if self.registered {
+            SAVED_FD.store(-1, Ordering::Release);
+        }
+    }
+}
+
+fn install_signal_handlers() {
+    for signal in TERMINATION_SIGNALS {
+        unsafe {
+            // Signals ignored on purpose, e.g. by nohup, must stay ignored.
+            let mut current: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(signal, ptr::null(), &mut current);
+            if current.sa_sigaction == libc::SIG_IGN {
+                continue;
+            }
+
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction =
+                handle_termination_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // The handler is reset to the default one once invoked, so the
+            // signal re-raised by the handler terminates the process as usual.
+            action.sa_flags = libc::SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, ptr::null_mut());
+        }
+    }
+}
+
+extern "C" fn handle_termination_signal(signal: libc::c_int) {
+    // Only async-signal-safe functions may be called here, which write(),
+    // tcsetattr(), and raise() are.
+    let fd = SAVED_FD.load(Ordering::Acquire);
+    let sequence = RESET_SEQUENCE.load(Ordering::Acquire);
+    unsafe {
+        if fd >= 0 {
+            if !sequence.is_null() {
+                let len = RESET_SEQUENCE_LEN.load(Ordering::Acquire);
+                libc::write(fd, sequence.cast(), len);
+            }
+            let termios = ptr::addr_of!(SAVED_TERMIOS).read();
+            libc::tcsetattr(fd, libc::TCSANOW, termios.as_ptr());
+        }
+        libc::raise(signal);
+    }
+}
+
+/// Pushes the bytes into the input queue of the terminal, as if they were
+/// typed, one byte at a time by means of TIOCSTI.
+pub fn simulate_input(fd: RawFd, input: &[u8]) -> io::Result<()> {
+    for byte in input {
+        if unsafe { libc::ioctl(fd, libc::TIOCSTI, byte as *const u8) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn get_termios(fd: RawFd) -> io::Result<libc::termios> {
+    let mut termios = MaybeUninit::<libc::termios>::uninit();
+
+    if unsafe { libc::tcgetattr(fd, termios.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { termios.assume_init() })
+}
+
+fn set_termios(fd: RawFd, termios: &libc::termios) -> io::Result<()> {
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, termios) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Reads the response of the terminal until it's complete according to the
+/// predicate, failing with `TimedOut` if the terminal doesn't start responding
+/// within the timeout. The tty is expected to be in non-blocking mode.
+pub fn read_response<F>(tty: &File, timeout: Duration, is_complete: F) -> io::Result<Vec<u8>>
+where
+    F: Fn(&[u8]) -> bool,
+{
+    let mut content = Vec::<u8>::with_capacity(BUFFER_SIZE);
+    read_stream(tty, timeout, |data| {
+        content.extend_from_slice(data);
+        Ok(is_complete(&content))
+    })?;
+    Ok(content)
+}
+
+/// Reads the response of the terminal, passing each part to the consumer as
+/// it arrives, until the consumer reports the response complete. Fails with
+/// `TimedOut` if the terminal doesn't start responding within the timeout.
+/// Once it does, the deadline is extended as long as the response keeps
+/// arriving, and a stalled response fails with the timeout failure instead.
+pub fn read_stream<F>(tty: &File, timeout: Duration, mut consume: F) -> io::Result<()>
+where
+    F: FnMut(&[u8]) -> io::Result<bool>,
+{
+    const TOKEN: Token = Token(0);
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(1);
+    let mut started = false;
+
+    poll.registry()
+        .register(&mut SourceFd(&tty.as_raw_fd()), TOKEN, Interest::READABLE)?;
+
+    'poll: loop {
+        let window = if started {
+            timeout.max(MAX_STALL_TIME)
+        } else {
+            timeout
+        };
+        poll.poll(&mut events, Some(window))?;
+
+        if events.is_empty() {
+            return Err(if started {
+                error::new(
+                    Failure::Timeout,
+                    "The terminal emulator has stopped responding midway.",
+                )
+            } else {
+                io::Error::from(io::ErrorKind::TimedOut)
+            });
+        }
+
+        for event in events.iter() {
+            if event.token() == TOKEN && event.is_readable() {
+                started = true;
+                if consume(&read_with_draining(tty)?)? {
+                    break 'poll;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_with_draining(mut tty: &File) -> io::Result<Vec<u8>> {
+    let mut content = Vec::<u8>::with_capacity(BUFFER_SIZE);
+    let mut content_buf = [0u8; BUFFER_SIZE];
+    loop {
+        match tty.read(&mut content_buf) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(size) => content.extend_from_slice(&content_buf[0..size]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(content)
+}
+
+/// Runs the command till it exits, and then restores whatever has been changed
+/// for it, no matter whether it has succeeded.
+pub fn run_then<F>(command: &[OsString], restore: F) -> io::Result<ExitStatus>
+where
+    F: FnOnce() -> io::Result<()>,
+{
+    let status = Command::new(&command[0])
+        .args(&command[1..])
+        .spawn()
+        .and_then(|mut child| {
+            // Interrupting the command from the keyboard interrupts ttybox as
+            // well, which has to outlive the command to restore the changes.
+            // The signals are ignored once the command is spawned, so it
+            // doesn't inherit that.
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_IGN);
+                libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+            }
+            child.wait()
+        });
+    restore()?;
+    status.map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Cannot run '{}': {e}", command[0].to_string_lossy()),
+        )
+    })
+}
+
+/// Returns the exit code to exit with on behalf of the command. A command
+/// killed by a signal is reported the way shells do.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
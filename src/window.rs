@@ -0,0 +1,165 @@
+use std::io;
+
+use clap::{Args, Subcommand, ValueEnum};
+
+use crate::error::{self, Failure};
+use crate::query;
+use crate::terminal::{Terminal, TimeoutArgs};
+use crate::tty::DEFAULT_TIMEOUT;
+
+/// The request to report the state of the window, XTWINOPS 11, which is
+/// answered by `CSI 1 t` if it's open, and `CSI 2 t` if it's iconified.
+const STATE_REQUEST: &[u8] = b"\x1B[11t";
+
+/// The request to report the position of the window, XTWINOPS 13.
+const POSITION_REQUEST: &[u8] = b"\x1B[13t";
+
+#[derive(Subcommand, Debug)]
+pub enum WindowCommands {
+    /// Minimize the terminal window.
+    Minimize,
+    /// Maximize the terminal window.
+    Maximize,
+    /// Make the terminal window fullscreen.
+    Fullscreen,
+    /// Restore the terminal window from being minimized, maximized, or
+    /// fullscreen.
+    Restore,
+    /// Raise the terminal window to the front, e.g. once a build fails.
+    Raise,
+    /// Lower the terminal window to the back.
+    Lower,
+    /// Print or change the position of the terminal window on the screen, in
+    /// pixels. Without a subcommand, the position is printed.
+    Pos(WindowPosArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct WindowPosArgs {
+    #[command(subcommand)]
+    command: Option<PosCommands>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PosCommands {
+    /// Print the position of the window, as reported by the terminal, as "X
+    /// Y".
+    Get(PosGetArgs),
+    /// Move the window to the position.
+    Set(PosSetArgs),
+}
+
+/// The formats the window position is printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PosFormat {
+    /// "X Y".
+    Text,
+    /// {"x": X, "y": Y}.
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct PosGetArgs {
+    /// The format to print the position in.
+    #[arg(long, value_enum, default_value_t = PosFormat::Text)]
+    format: PosFormat,
+
+    #[command(flatten)]
+    timeout: TimeoutArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct PosSetArgs {
+    /// The distance of the window from the left edge of the screen.
+    x: u32,
+
+    /// The distance of the window from the top edge of the screen.
+    y: u32,
+}
+
+pub fn execute(command: WindowCommands, terminal: &Terminal) -> io::Result<()> {
+    match command {
+        WindowCommands::Pos(args) => match args.command {
+            Some(PosCommands::Get(args)) => execute_pos_get(args, terminal),
+            Some(PosCommands::Set(args)) => execute_pos_set(args, terminal),
+            None => execute_pos_get(
+                PosGetArgs {
+                    format: PosFormat::Text,
+                    timeout: TimeoutArgs::default(),
+                },
+                terminal,
+            ),
+        },
+        command => execute_operation(command, terminal),
+    }
+}
+
+fn execute_operation(command: WindowCommands, terminal: &Terminal) -> io::Result<()> {
+    // See the "Window manipulation" of XTWINOPS in xterm's ctlseqs.
+    let sequence: &[u8] = match command {
+        WindowCommands::Minimize => b"\x1B[2t",
+        WindowCommands::Maximize => b"\x1B[9;1t",
+        WindowCommands::Fullscreen => b"\x1B[10;1t",
+        WindowCommands::Restore => b"\x1B[1t\x1B[10;0t\x1B[9;0t",
+        WindowCommands::Raise => b"\x1B[5t",
+        WindowCommands::Lower => b"\x1B[6t",
+        WindowCommands::Pos(_) => unreachable!("the position isn't an operation"),
+    };
+
+    // Terminals silently ignore the operations they don't support or have
+    // disabled, e.g. xterm unless allowWindowOps is set, and most of them
+    // don't report the window state either then.
+    if !terminal.print_sequence && !reports_state(terminal)? {
+        eprintln!(
+            "ttybox: The terminal emulator doesn't report the window state, so it likely \
+             ignores window operations, or has them disabled."
+        );
+    }
+    terminal.send([sequence.to_vec()])
+}
+
+fn execute_pos_get(args: PosGetArgs, terminal: &Terminal) -> io::Result<()> {
+    let timeout = args.timeout.duration();
+
+    // The report looks like `CSI 3 ; X ; Y t`.
+    let response = query::query(terminal, POSITION_REQUEST, timeout)?;
+    let report = query::find_csi(&response, b"\x1B[3;", b't').ok_or_else(|| {
+        error::new(
+            Failure::Unsupported,
+            "The terminal emulator doesn't report the window position, or has reporting \
+             disabled.",
+        )
+    })?;
+    let report = String::from_utf8_lossy(report);
+    let (x, y) = report
+        .split_once(';')
+        .and_then(|(x, y)| Some((x.parse::<u32>().ok()?, y.parse::<u32>().ok()?)))
+        .ok_or_else(|| {
+            error::new(
+                Failure::Protocol,
+                format!(
+                    "Malformed window position report: {}.",
+                    report.escape_debug()
+                ),
+            )
+        })?;
+
+    match args.format {
+        PosFormat::Text => println!("{x} {y}"),
+        PosFormat::Json => println!(r#"{{"x": {x}, "y": {y}}}"#),
+    }
+    Ok(())
+}
+
+fn execute_pos_set(args: PosSetArgs, terminal: &Terminal) -> io::Result<()> {
+    terminal.send([format!("\x1B[3;{};{}t", args.x, args.y).into_bytes()])
+}
+
+/// Returns whether the terminal reports the state of the window.
+fn reports_state(terminal: &Terminal) -> io::Result<bool> {
+    match query::query(terminal, STATE_REQUEST, DEFAULT_TIMEOUT) {
+        Ok(response) => Ok(query::find_csi(&response, b"\x1B[", b't').is_some()),
+        Err(e) if error::failure(&e) == Some(Failure::Timeout) => Ok(false),
+        Err(e) => Err(e),
+    }
+}